@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ActiveSession = { 
+/**
+ * Whether this is the session making the request.
+ */
+current: boolean, 
+/**
+ * Names the session without giving away its cookie.
+ */
+key: string, user_id: string, created: string, last_seen: string, ip: string, user_agent: string, };
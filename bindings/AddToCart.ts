@@ -1,3 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type AddToCart = { listingId: string, number: number, };
+export type AddToCart = { number: number, 
+/**
+ * Defaults to the listing's first variant.
+ */
+variantId: string | null, };
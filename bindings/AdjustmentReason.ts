@@ -1,4 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
-import type { Product } from "./Product";
 
-export type ListingResponse = { listings: Array<Product>, };
+export type AdjustmentReason = "Restock" | "Damage" | "Correction" | "Sale";
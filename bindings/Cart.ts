@@ -1,4 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { CartItem } from "./CartItem";
 
-export type Cart = { items: Array<CartItem>, };
+/**
+ * Items are keyed by variant id.
+ */
+export type Cart = { items: { [key in string]?: CartItem }, };
@@ -1,4 +1,5 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Product } from "./Product";
+import type { Variant } from "./Variant";
 
-export type CartItem = { listing: Product, number: number, };
+export type CartItem = { listing: Product, variant: Variant, number: number, };
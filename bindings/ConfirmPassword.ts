@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * The password, asked for again before turning 2FA off or replacing the
+ * recovery codes.
+ */
+export type ConfirmPassword = { password: string, };
@@ -1,3 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type Credentials = { email: string, password: string, };
+export type Credentials = { email: string, password: string, 
+/**
+ * Keep the session after the browser closes.
+ */
+remember?: boolean, };
@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type ErrorCause = "Internal" | "Unauthorized" | "MissingInventory";
+export type ErrorCause = "Internal" | "Unauthorized" | "Forbidden" | "MissingInventory" | "InsufficientStock" | "NotFound" | "BadRequest" | "Conflict" | "TooManyRequests";
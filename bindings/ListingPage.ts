@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Facets } from "./Facets";
+import type { Product } from "./Product";
+
+export type ListingPage = { listings: Array<Product>, page: number, per_page: number, total: number, next_page: number | null, facets: Facets, };
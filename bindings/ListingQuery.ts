@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ListingSort } from "./ListingSort";
+
+export type ListingQuery = { page: number, per_page: number, q: string | null, category: string | null, tag: string | null, min_price: string | null, max_price: string | null, in_stock: boolean, sort: ListingSort | null, };
@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type LoginStatus = "LoggedIn" | "NotLoggedIn" | "Unauthorized" | "Failed";
+export type LoginStatus = "LoggedIn" | "NotLoggedIn" | "Unauthorized" | "Locked" | "TwoFactorRequired" | "Failed";
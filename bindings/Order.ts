@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { OrderLine } from "./OrderLine";
+import type { OrderStatus } from "./OrderStatus";
+
+export type Order = { order_id: string, user_id: string, lines: Array<OrderLine>, subtotal: string, shipping: string, tax: string, total: string, placed_at: string, status: OrderStatus, };
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { InventoryError } from "./InventoryError";
+import type { OrderStatus } from "./OrderStatus";
+
+export type OrderError = "EmptyCart" | "Unverified" | "NotFound" | { "InvalidStatus": OrderStatus } | { "Inventory": InventoryError };
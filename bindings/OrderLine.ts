@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A line as it was bought, so later price or name changes to the product
+ * don't rewrite order history.
+ */
+export type OrderLine = { listing_id: string, variant_id: string, name: string, variant: string, price: string, number: number, };
@@ -1,3 +1,13 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ProductSpecs } from "./ProductSpecs";
+import type { Variant } from "./Variant";
 
-export type Product = { listing_id: string, name: string, price: string, description: string, image: string, };
+export type Product = { listing_id: string, name: string, 
+/**
+ * The lowest variant price, shown as "from" on listings.
+ */
+price: string, description: string, image: string, category: string, tags: Array<string>, listed_at: string, specs: ProductSpecs, 
+/**
+ * Never empty. The first variant shares the product's `listing_id`.
+ */
+variants: Array<Variant>, };
@@ -0,0 +1,13 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LightNeeds } from "./LightNeeds";
+import type { Watering } from "./Watering";
+
+/**
+ * Care and physical details shown on the product page. Plants fill in the
+ * care fields, rocks the mineral ones.
+ */
+export type ProductSpecs = { light: LightNeeds | null, watering: Watering | null, pet_safe: boolean | null, size: string | null, 
+/**
+ * Mohs scale, 1 to 10.
+ */
+hardness: number | null, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ReviewStatus } from "./ReviewStatus";
+
+export type Review = { review_id: string, listing_id: string, author: string, 
+/**
+ * One to five stars.
+ */
+rating: number, title: string, body: string, created_at: string, status: ReviewStatus, };
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RatingSummary } from "./RatingSummary";
+import type { Review } from "./Review";
+
+export type ReviewPage = { reviews: Array<Review>, page: number, per_page: number, total: number, next_page: number | null, summary: RatingSummary, };
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Where and when a login has been used, so users can see their sessions
+ * and revoke them.
+ */
+export type SessionInfo = { 
+/**
+ * Names the session without giving away its cookie.
+ */
+key: string, user_id: string, created: string, last_seen: string, ip: string, user_agent: string, };
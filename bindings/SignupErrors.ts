@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What is wrong with each field of a rejected signup, so the form can show
+ * the errors next to the inputs.
+ */
+export type SignupErrors = { username: string | null, email: string | null, password: string | null, };
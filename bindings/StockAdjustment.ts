@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AdjustmentReason } from "./AdjustmentReason";
+
+/**
+ * A single change to a listing's `free` stock. `user_id` is empty for
+ * adjustments made by the store itself, such as opening stock.
+ */
+export type StockAdjustment = { id: string, listing_id: string, variant_id: string, user_id: string | null, created_at: string, delta: bigint, reason: AdjustmentReason, };
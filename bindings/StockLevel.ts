@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Inventory } from "./Inventory";
+import type { Product } from "./Product";
+import type { Variant } from "./Variant";
+
+export type StockLevel = { product: Product, variant: Variant, inventory: Inventory, };
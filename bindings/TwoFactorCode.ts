@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A code from an authenticator app, or a recovery code.
+ */
+export type TwoFactorCode = { code: string, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What an authenticator app needs to be set up, as a secret to type in or
+ * an `otpauth://` URI to show as a QR code.
+ */
+export type TwoFactorSetup = { secret: string, uri: string, };
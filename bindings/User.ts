@@ -1,3 +1,12 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Role } from "./Role";
 
-export type User = { id: string, email: string, username: string, };
+export type User = { id: string, email: string, username: string, role: Role, 
+/**
+ * Whether the user has followed the link mailed to `email`.
+ */
+verified: boolean, 
+/**
+ * Whether logging in also needs a code from an authenticator app.
+ */
+two_factor_enabled: boolean, };
@@ -1,3 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SignupErrors } from "./SignupErrors";
 
-export type UserError = "NotFound";
+export type UserError = "NotFound" | "InvalidEmail" | "InvalidUsername" | "EmailTaken" | "UsernameTaken" | { "WeakPassword": string } | "WrongPassword" | { "InvalidSignup": SignupErrors } | "InvalidCode" | "TwoFactorOn" | "TwoFactorOff" | "TwoFactorRequired" | "IdentityLinked";
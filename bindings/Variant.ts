@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { VariantOption } from "./VariantOption";
+
+/**
+ * A purchasable configuration of a product, such as a size and pot colour,
+ * with its own SKU, price and stock.
+ */
+export type Variant = { variant_id: string, sku: string, options: Array<VariantOption>, price: string, };
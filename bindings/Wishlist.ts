@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackInStock } from "./BackInStock";
+import type { WishlistItem } from "./WishlistItem";
+
+/**
+ * Saved listings in the order they were added, with notices for any that
+ * have come back into stock since.
+ */
+export type Wishlist = { items: Array<WishlistItem>, notices: Array<BackInStock>, };
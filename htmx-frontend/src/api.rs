@@ -1,25 +1,62 @@
 mod account;
+mod admin;
 mod cart;
+mod order;
+mod review;
 mod store;
+mod wishlist;
 
-use crate::AppState;
-use account::{check_in, login, logout};
+use crate::{
+    csrf::{self, Rejection},
+    rate_limit::{retry_after, RateLimiter},
+    AppState, Auth,
+};
+use account::{
+    account, begin_two_factor, change_email, change_password, change_username, check_in,
+    confirm_two_factor, delete_account, disable_two_factor, forgot_password, login, logout,
+    new_recovery_codes, resend_verification, reset_password, revoke_other_sessions, revoke_session,
+    sessions, two_factor_login, verify_email,
+};
+use admin::{
+    adjust_stock, all_orders, inventory, low_stock_alerts, moderate_review, refund_order,
+    reviews_for_moderation, set_low_stock_threshold, set_staff_two_factor, ship_order,
+    stock_ledger,
+};
 use axum::{
+    extract::{ConnectInfo, Request, State},
     http::StatusCode,
-    response::IntoResponse,
-    routing::{delete, get, post},
+    middleware::{from_fn, from_fn_with_state, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
     Json, Router,
 };
+use axum_login::tower_sessions::Session;
 use cart::{add_to_cart, fetch_cart, remove_from_cart};
+use order::{orders, place_order};
+use review::{reviews, submit_review};
 use serde::Serialize;
-use store::listing;
+use std::net::SocketAddr;
+use store::{listing, notify_when_restocked, product};
+use store_lib::{
+    account::UserError, mail::MailError, order::OrderError, password_reset::ResetError,
+    review::ReviewError, store::InventoryError, verification::VerifyError,
+};
 use ts_rs::TS;
+use wishlist::{
+    add_to_wishlist, dismiss_notices, fetch_wishlist, move_to_cart, remove_from_wishlist,
+};
 
 #[derive(Serialize, TS)]
 enum ErrorCause {
     Internal,
     Unauthorized,
+    Forbidden,
     MissingInventory,
+    InsufficientStock,
+    NotFound,
+    BadRequest,
+    Conflict,
+    TooManyRequests,
 }
 
 #[derive(Serialize, TS)]
@@ -44,6 +81,13 @@ impl StoreError {
         }
     }
 
+    fn forbidden(message: String) -> Self {
+        Self {
+            reason: ErrorCause::Forbidden,
+            message,
+        }
+    }
+
     fn missing_inventory(message: String) -> Self {
         Self {
             reason: ErrorCause::MissingInventory,
@@ -52,31 +96,313 @@ impl StoreError {
     }
 }
 
+impl From<InventoryError> for StoreError {
+    fn from(value: InventoryError) -> Self {
+        let reason = match value {
+            InventoryError::NotFound => ErrorCause::MissingInventory,
+            InventoryError::InsufficientStock => ErrorCause::InsufficientStock,
+            InventoryError::ManualSale => ErrorCause::BadRequest,
+        };
+
+        Self {
+            reason,
+            message: value.to_string(),
+        }
+    }
+}
+
+impl From<OrderError> for StoreError {
+    fn from(value: OrderError) -> Self {
+        match value {
+            OrderError::EmptyCart => Self {
+                reason: ErrorCause::BadRequest,
+                message: value.to_string(),
+            },
+            OrderError::Unverified => Self {
+                reason: ErrorCause::Forbidden,
+                message: value.to_string(),
+            },
+            OrderError::NotFound => Self {
+                reason: ErrorCause::NotFound,
+                message: value.to_string(),
+            },
+            OrderError::InvalidStatus(_) => Self {
+                reason: ErrorCause::Conflict,
+                message: value.to_string(),
+            },
+            OrderError::Inventory(e) => e.into(),
+        }
+    }
+}
+
+impl From<VerifyError> for StoreError {
+    fn from(value: VerifyError) -> Self {
+        let reason = match value {
+            VerifyError::Invalid | VerifyError::Expired => ErrorCause::BadRequest,
+            VerifyError::Stale => ErrorCause::Conflict,
+        };
+
+        Self {
+            reason,
+            message: value.to_string(),
+        }
+    }
+}
+
+impl From<UserError> for StoreError {
+    fn from(value: UserError) -> Self {
+        let reason = match value {
+            UserError::PasswordHashingFailed(_) => ErrorCause::Internal,
+            UserError::NotFound => ErrorCause::NotFound,
+            UserError::InvalidEmail
+            | UserError::InvalidUsername
+            | UserError::WeakPassword(_)
+            | UserError::InvalidSignup(_) => ErrorCause::BadRequest,
+            UserError::EmailTaken
+            | UserError::UsernameTaken
+            | UserError::TwoFactorOn
+            | UserError::TwoFactorOff
+            | UserError::IdentityLinked => ErrorCause::Conflict,
+            UserError::WrongPassword | UserError::TwoFactorRequired => ErrorCause::Forbidden,
+            UserError::InvalidCode => ErrorCause::Unauthorized,
+        };
+
+        Self {
+            reason,
+            message: value.to_string(),
+        }
+    }
+}
+
+impl From<ResetError> for StoreError {
+    fn from(value: ResetError) -> Self {
+        match value {
+            ResetError::Invalid | ResetError::Expired => Self {
+                reason: ErrorCause::BadRequest,
+                message: value.to_string(),
+            },
+            ResetError::User(e) => e.into(),
+        }
+    }
+}
+
+impl From<ReviewError> for StoreError {
+    fn from(value: ReviewError) -> Self {
+        let reason = match value {
+            ReviewError::NotFound => ErrorCause::NotFound,
+            ReviewError::NotPurchased => ErrorCause::Forbidden,
+            ReviewError::AlreadyReviewed => ErrorCause::Conflict,
+            ReviewError::InvalidRating | ReviewError::InvalidTitle | ReviewError::BodyTooLong => {
+                ErrorCause::BadRequest
+            }
+        };
+
+        Self {
+            reason,
+            message: value.to_string(),
+        }
+    }
+}
+
+impl From<MailError> for StoreError {
+    fn from(value: MailError) -> Self {
+        let reason = match value {
+            MailError::InvalidAddress(_) => ErrorCause::BadRequest,
+            _ => ErrorCause::Internal,
+        };
+
+        Self {
+            reason,
+            message: value.to_string(),
+        }
+    }
+}
+
 impl IntoResponse for StoreError {
     fn into_response(self) -> axum::response::Response {
         let code = match self.reason {
             ErrorCause::Internal => StatusCode::BAD_REQUEST,
             ErrorCause::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCause::Forbidden => StatusCode::FORBIDDEN,
             ErrorCause::MissingInventory => StatusCode::NOT_FOUND,
+            ErrorCause::InsufficientStock => StatusCode::CONFLICT,
+            ErrorCause::NotFound => StatusCode::NOT_FOUND,
+            ErrorCause::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCause::Conflict => StatusCode::CONFLICT,
+            ErrorCause::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
         };
         (code, Json(self)).into_response()
     }
 }
 
-pub fn api_routes() -> Router<AppState> {
+/// Middleware for API routes, answering with a `StoreError`.
+async fn rate_limited(
+    State(limiter): State<RateLimiter>,
+    auth: Auth,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match limiter.take(&auth, addr) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let error = StoreError {
+                reason: ErrorCause::TooManyRequests,
+                message: "Too many requests".to_string(),
+            };
+            retry_after(error.into_response(), wait)
+        }
+    }
+}
+
+/// Middleware for API routes, refusing state changing requests without the
+/// session's CSRF token in `X-CSRF-Token`. Clients get the token from
+/// `/check-in`.
+async fn csrf_checked(session: Session, request: Request, next: Next) -> Response {
+    let error = match csrf::check(&session, request).await {
+        Ok(request) => return next.run(request).await,
+        Err(Rejection::BadToken) => StoreError {
+            reason: ErrorCause::Forbidden,
+            message: "Missing or invalid CSRF token".to_string(),
+        },
+        Err(Rejection::TooLarge) => StoreError {
+            reason: ErrorCause::BadRequest,
+            message: "Request body is too large".to_string(),
+        },
+    };
+    error.into_response()
+}
+
+/// `auth` limits logins, signups and account recovery, `cart` limits
+/// changes to the cart.
+pub fn api_routes(auth: &RateLimiter, cart: &RateLimiter) -> Router<AppState> {
+    let auth_limit = from_fn_with_state(auth.clone(), rate_limited);
+    let cart_limit = from_fn_with_state(cart.clone(), rate_limited);
+
     Router::new()
         .route("/status", get(async || "alive"))
-        .route("/login", post(login))
+        .route("/login", post(login).layer(auth_limit.clone()))
+        .route(
+            "/login/two-factor",
+            post(two_factor_login).layer(auth_limit.clone()),
+        )
         .route("/logout", post(logout))
         .route("/check-in", get(check_in))
+        .route("/account", get(account).delete(delete_account))
+        .route("/account/username", put(change_username))
+        .route("/account/email", put(change_email))
+        .route("/account/password", put(change_password))
+        .route(
+            "/account/sessions",
+            get(sessions).delete(revoke_other_sessions),
+        )
+        .route("/account/sessions/:key", delete(revoke_session))
+        .route("/account/two-factor", post(begin_two_factor))
+        .route("/account/two-factor/confirm", post(confirm_two_factor))
+        .route("/account/two-factor/disable", post(disable_two_factor))
+        .route(
+            "/account/two-factor/recovery-codes",
+            post(new_recovery_codes),
+        )
+        .route(
+            "/password-reset",
+            post(forgot_password).layer(auth_limit.clone()),
+        )
+        .route(
+            "/password-reset/confirm",
+            post(reset_password).layer(auth_limit.clone()),
+        )
+        .route(
+            "/verify-email",
+            post(verify_email).layer(auth_limit.clone()),
+        )
+        .route(
+            "/verify-email/resend",
+            post(resend_verification).layer(auth_limit),
+        )
         .route("/listings", get(listing))
+        .route("/listings/:listing_id", get(product))
+        .route(
+            "/listings/:listing_id/reviews",
+            get(reviews).post(submit_review),
+        )
+        .route("/listings/:listing_id/notify", post(notify_when_restocked))
         .route("/cart", get(fetch_cart))
-        .route("/cart/{listing_id}", post(add_to_cart))
-        .route("/cart/{listing_id}", delete(remove_from_cart))
+        .route(
+            "/cart/:id",
+            post(add_to_cart).delete(remove_from_cart).layer(cart_limit),
+        )
+        .route("/orders", get(orders).post(place_order))
+        .route("/wishlist", get(fetch_wishlist))
+        .route("/wishlist/notices", delete(dismiss_notices))
+        .route(
+            "/wishlist/:listing_id",
+            put(add_to_wishlist).delete(remove_from_wishlist),
+        )
+        .route("/wishlist/:listing_id/move", post(move_to_cart))
+        .route("/admin/inventory", get(inventory))
+        .route("/admin/inventory/:variant_id", post(adjust_stock))
+        .route("/admin/inventory/:variant_id/ledger", get(stock_ledger))
+        .route(
+            "/admin/inventory/:variant_id/threshold",
+            put(set_low_stock_threshold),
+        )
+        .route("/admin/alerts", get(low_stock_alerts))
+        .route("/admin/orders", get(all_orders))
+        .route("/admin/orders/:order_id/ship", post(ship_order))
+        .route("/admin/orders/:order_id/refund", post(refund_order))
+        .route("/admin/reviews", get(reviews_for_moderation))
+        .route("/admin/reviews/:review_id", put(moderate_review))
+        .route("/admin/two-factor-policy", put(set_staff_two_factor))
+        .route_layer(from_fn(csrf_checked))
 }
 
 #[cfg(test)]
 mod test {
+    use super::*;
+    use axum::{
+        body::{to_bytes, Body},
+        http::header,
+    };
+    use axum_login::tower_sessions::{MemoryStore, SessionManagerLayer};
+    use tower::ServiceExt;
+
     #[test]
     fn export_typescript() {}
+
+    #[tokio::test]
+    async fn state_changes_need_the_csrf_header() {
+        let app = Router::new()
+            .route("/logout", post(|| async {}))
+            .route(
+                "/check-in",
+                get(|session: Session| async move { csrf::token(&session).await }),
+            )
+            .route_layer(from_fn(csrf_checked))
+            .layer(SessionManagerLayer::new(MemoryStore::default()));
+        let request = |method: &str, uri: &str| Request::builder().method(method).uri(uri);
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/check-in").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].clone();
+        let token = to_bytes(response.into_body(), 64).await.unwrap();
+        let token = String::from_utf8(token.to_vec()).unwrap();
+
+        let logout = |token: &str| {
+            request("POST", "/logout")
+                .header(header::COOKIE, cookie.clone())
+                .header(csrf::CSRF_HEADER, token)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(logout("wrong")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = to_bytes(response.into_body(), 1024).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains(r#""reason":"Forbidden""#));
+        let response = app.oneshot(logout(&token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
@@ -1,10 +1,25 @@
-use crate::Auth;
+use crate::csrf::{self, CSRF_HEADER};
+use crate::{emails, pending_login, rate_limit::retry_after, sessions, AppState, Auth};
+use axum::extract::{ConnectInfo, Path, State};
 use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use axum::Json;
+use axum_login::tower_sessions::{session_store, Session};
+use chrono::Utc;
 use serde::Serialize;
-use store_lib::account::{Credentials, User};
+use std::net::SocketAddr;
+use store_lib::account::{
+    ChangeEmail, ChangePassword, ChangeUsername, Credentials, DeleteAccount, User, UserError,
+};
+use store_lib::password_reset::{ForgotPassword, ResetPassword};
+use store_lib::session_store::SessionInfo;
+use store_lib::two_factor::{ConfirmPassword, RecoveryCodes, TwoFactorCode, TwoFactorSetup};
+use store_lib::verification::VerifyEmail;
 use tracing::warn;
 use ts_rs::TS;
+use uuid::Uuid;
+
+use super::{ErrorCause, StoreError};
 
 #[derive(Serialize, TS)]
 #[ts(export)]
@@ -12,6 +27,9 @@ enum LoginStatus {
     LoggedIn,
     NotLoggedIn,
     Unauthorized,
+    Locked,
+    /// The password was right; send a code to `/login/two-factor` next.
+    TwoFactorRequired,
     Failed,
 }
 
@@ -22,47 +40,135 @@ pub(crate) struct LoginResponse {
     user: Option<User>,
 }
 
-pub(crate) async fn check_in(auth: Auth) -> Json<LoginResponse> {
+/// Sends the session's CSRF token in `X-CSRF-Token`, for clients to send
+/// back with state changing requests. Logging out ends the session, so
+/// check in again afterwards.
+pub(crate) async fn check_in(auth: Auth, session: Session) -> Response {
     let status = match auth.user {
         Some(_) => LoginStatus::LoggedIn,
         None => LoginStatus::NotLoggedIn,
     };
 
-    Json(LoginResponse {
+    let token = csrf::token(&session).await;
+    let body = Json(LoginResponse {
         status,
         user: auth.user,
-    })
+    });
+    ([(CSRF_HEADER, token)], body).into_response()
+}
+
+fn login_response(status: StatusCode, login_status: LoginStatus) -> Response {
+    let body = Json(LoginResponse {
+        status: login_status,
+        user: None,
+    });
+    (status, body).into_response()
 }
 
+/// Logs in, counting failures towards locking the account and the client's
+/// address. Locked out clients get a 429 with `Retry-After`.
 pub(crate) async fn login(
     mut auth: Auth,
+    session: Session,
+    State(AppState {
+        login_attempts,
+        session_config,
+        ..
+    }): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Json(creds): Json<Credentials>,
-) -> (StatusCode, Json<LoginResponse>) {
-    let Ok(Some(user)) = auth.authenticate(creds).await else {
-        return (
-            StatusCode::UNAUTHORIZED,
-            Json(LoginResponse {
-                status: LoginStatus::Unauthorized,
-                user: None,
-            }),
-        );
+) -> Response {
+    let now = Utc::now();
+    if let Err(locked) = login_attempts.check(&creds.email, addr.ip(), now) {
+        let response = login_response(StatusCode::TOO_MANY_REQUESTS, LoginStatus::Locked);
+        let wait = (locked.until - now).to_std().unwrap_or_default();
+        return retry_after(response, wait);
+    }
+
+    let user = match auth.authenticate(creds.clone()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            login_attempts.record_failure(&creds.email, addr.ip(), now);
+            return login_response(StatusCode::UNAUTHORIZED, LoginStatus::Unauthorized);
+        }
+        Err(e) => {
+            warn!("Login failed: {e}");
+            return login_response(StatusCode::INTERNAL_SERVER_ERROR, LoginStatus::Failed);
+        }
     };
+    if user.two_factor_enabled {
+        return match pending_login::start(&session, &user, creds.remember, now).await {
+            true => login_response(StatusCode::OK, LoginStatus::TwoFactorRequired),
+            false => login_response(StatusCode::INTERNAL_SERVER_ERROR, LoginStatus::Failed),
+        };
+    }
+    login_attempts.record_success(&creds.email);
 
     match auth.login(&user).await {
-        Ok(_) => (
-            StatusCode::OK,
+        Ok(_) => {
+            if creds.remember {
+                sessions::remember(&session, session_config.lifetime);
+            }
             Json(LoginResponse {
                 status: LoginStatus::LoggedIn,
                 user: Some(user),
-            }),
-        ),
-        Err(_) => (
-            StatusCode::BAD_REQUEST,
-            Json(LoginResponse {
-                status: LoginStatus::Failed,
-                user: None,
-            }),
-        ),
+            })
+            .into_response()
+        }
+        Err(_) => login_response(StatusCode::BAD_REQUEST, LoginStatus::Failed),
+    }
+}
+
+/// The second login step for accounts with 2FA, taking an app or recovery
+/// code. Wrong codes count towards the lockout like wrong passwords.
+pub(crate) async fn two_factor_login(
+    mut auth: Auth,
+    session: Session,
+    State(s): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(TwoFactorCode { code }): Json<TwoFactorCode>,
+) -> Response {
+    let now = Utc::now();
+    let Some(pending) = pending_login::pending(&session, now).await else {
+        return login_response(StatusCode::UNAUTHORIZED, LoginStatus::NotLoggedIn);
+    };
+    if let Err(locked) = s.login_attempts.check(&pending.email, addr.ip(), now) {
+        pending_login::clear(&session).await;
+        let response = login_response(StatusCode::TOO_MANY_REQUESTS, LoginStatus::Locked);
+        let wait = (locked.until - now).to_std().unwrap_or_default();
+        return retry_after(response, wait);
+    }
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.check_two_factor(&pending.user_id, &code, now)
+    };
+    let user = match result {
+        Ok(user) => user,
+        Err(UserError::InvalidCode) => {
+            s.login_attempts
+                .record_failure(&pending.email, addr.ip(), now);
+            return login_response(StatusCode::UNAUTHORIZED, LoginStatus::Unauthorized);
+        }
+        Err(e) => {
+            warn!("Two-factor login failed: {e}");
+            pending_login::clear(&session).await;
+            return login_response(StatusCode::UNAUTHORIZED, LoginStatus::NotLoggedIn);
+        }
+    };
+    s.login_attempts.record_success(&pending.email);
+    pending_login::clear(&session).await;
+    if pending.remember {
+        sessions::remember(&session, s.session_config.lifetime);
+    }
+
+    match auth.login(&user).await {
+        Ok(_) => Json(LoginResponse {
+            status: LoginStatus::LoggedIn,
+            user: Some(user),
+        })
+        .into_response(),
+        Err(_) => login_response(StatusCode::BAD_REQUEST, LoginStatus::Failed),
     }
 }
 
@@ -76,3 +182,267 @@ pub(crate) async fn logout(mut auth: Auth) -> Json<LoginResponse> {
         user: None,
     })
 }
+
+pub(crate) async fn verify_email(
+    State(s): State<AppState>,
+    Json(VerifyEmail { token }): Json<VerifyEmail>,
+) -> Result<Json<User>, StoreError> {
+    let mut user_store = s.user_backend.lock().expect("user store threading issue");
+    Ok(Json(user_store.verify_email(
+        &s.email_verifier,
+        &token,
+        Utc::now(),
+    )?))
+}
+
+/// Mails the logged in user a new verification link.
+pub(crate) async fn resend_verification(
+    auth: Auth,
+    State(s): State<AppState>,
+) -> Result<StatusCode, StoreError> {
+    let user = auth.user.ok_or(StoreError::unauthorized(
+        "Must be logged in to verify an email address".to_string(),
+    ))?;
+    if user.verified {
+        return Err(StoreError {
+            reason: ErrorCause::Conflict,
+            message: "email address is already verified".to_string(),
+        });
+    }
+
+    let token = s.email_verifier.token(&user, Utc::now());
+    s.outbox
+        .enqueue(emails::verify_email(&user, &token, &s.site_url));
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Mails a reset link if the address has an account. Always accepted, so
+/// callers can't tell which addresses are registered.
+pub(crate) async fn forgot_password(
+    State(s): State<AppState>,
+    Json(ForgotPassword { email }): Json<ForgotPassword>,
+) -> StatusCode {
+    let reset = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.request_password_reset(&email, Utc::now())
+    };
+    if let Some((user, token)) = reset {
+        s.outbox
+            .enqueue(emails::password_reset(&user, &token, &s.site_url));
+    }
+    StatusCode::ACCEPTED
+}
+
+pub(crate) async fn reset_password(
+    State(s): State<AppState>,
+    Json(reset): Json<ResetPassword>,
+) -> Result<Json<User>, StoreError> {
+    let mut user_store = s.user_backend.lock().expect("user store threading issue");
+    Ok(Json(user_store.reset_password(
+        &reset.token,
+        &reset.password,
+        Utc::now(),
+    )?))
+}
+
+fn logged_in(auth: &Auth) -> Result<User, StoreError> {
+    auth.user.clone().ok_or(StoreError::unauthorized(
+        "Must be logged in to manage an account".to_string(),
+    ))
+}
+
+pub(crate) async fn account(auth: Auth) -> Result<Json<User>, StoreError> {
+    Ok(Json(logged_in(&auth)?))
+}
+
+pub(crate) async fn change_username(
+    auth: Auth,
+    State(s): State<AppState>,
+    Json(ChangeUsername { username }): Json<ChangeUsername>,
+) -> Result<Json<User>, StoreError> {
+    let user = logged_in(&auth)?;
+    let updated = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.change_username(&user.id, &username)?
+    };
+    s.review_backend
+        .rename_author(&updated.id, &updated.username);
+    Ok(Json(updated))
+}
+
+/// Moves the account to a new address and mails it a verification link.
+pub(crate) async fn change_email(
+    auth: Auth,
+    State(s): State<AppState>,
+    Json(ChangeEmail { email }): Json<ChangeEmail>,
+) -> Result<Json<User>, StoreError> {
+    let user = logged_in(&auth)?;
+    let updated = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.change_email(&user.id, &email)?
+    };
+    if !updated.verified {
+        let token = s.email_verifier.token(&updated, Utc::now());
+        s.outbox
+            .enqueue(emails::verify_email(&updated, &token, &s.site_url));
+    }
+    Ok(Json(updated))
+}
+
+/// Changes the password and renews this session, which the change would
+/// otherwise end along with every other.
+pub(crate) async fn change_password(
+    mut auth: Auth,
+    State(s): State<AppState>,
+    Json(change): Json<ChangePassword>,
+) -> Result<Json<User>, StoreError> {
+    let user = logged_in(&auth)?;
+    let updated = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.change_password(&user.id, &change.current_password, &change.new_password)?
+    };
+    if let Err(e) = auth.login(&updated).await {
+        warn!("Failed to renew session after password change: {e}");
+    }
+    Ok(Json(updated))
+}
+
+/// Deletes the account, keeping its orders and reviews without the link
+/// back to it.
+pub(crate) async fn delete_account(
+    mut auth: Auth,
+    State(s): State<AppState>,
+    Json(DeleteAccount { password }): Json<DeleteAccount>,
+) -> Result<StatusCode, StoreError> {
+    let user = logged_in(&auth)?;
+    {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.delete(&user.id, &password)?;
+    }
+    s.forget_account(&mut auth, &user).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Starts setting up an authenticator app. 2FA stays off until
+/// `confirm_two_factor` gets a code from it.
+pub(crate) async fn begin_two_factor(
+    auth: Auth,
+    State(s): State<AppState>,
+) -> Result<Json<TwoFactorSetup>, StoreError> {
+    let user = logged_in(&auth)?;
+    let mut user_store = s.user_backend.lock().expect("user store threading issue");
+    Ok(Json(user_store.begin_two_factor(&user.id)?))
+}
+
+/// Turns 2FA on, returning the recovery codes. They aren't shown again.
+pub(crate) async fn confirm_two_factor(
+    auth: Auth,
+    State(s): State<AppState>,
+    Json(TwoFactorCode { code }): Json<TwoFactorCode>,
+) -> Result<Json<RecoveryCodes>, StoreError> {
+    let user = logged_in(&auth)?;
+    let mut user_store = s.user_backend.lock().expect("user store threading issue");
+    let (_, codes) = user_store.confirm_two_factor(&user.id, &code, Utc::now())?;
+    Ok(Json(RecoveryCodes { codes }))
+}
+
+pub(crate) async fn disable_two_factor(
+    auth: Auth,
+    State(s): State<AppState>,
+    Json(ConfirmPassword { password }): Json<ConfirmPassword>,
+) -> Result<Json<User>, StoreError> {
+    let user = logged_in(&auth)?;
+    let mut user_store = s.user_backend.lock().expect("user store threading issue");
+    Ok(Json(user_store.disable_two_factor(&user.id, &password)?))
+}
+
+pub(crate) async fn new_recovery_codes(
+    auth: Auth,
+    State(s): State<AppState>,
+    Json(ConfirmPassword { password }): Json<ConfirmPassword>,
+) -> Result<Json<RecoveryCodes>, StoreError> {
+    let user = logged_in(&auth)?;
+    let mut user_store = s.user_backend.lock().expect("user store threading issue");
+    let codes = user_store.new_recovery_codes(&user.id, &password)?;
+    Ok(Json(RecoveryCodes { codes }))
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub(crate) struct ActiveSession {
+    #[serde(flatten)]
+    info: SessionInfo,
+    /// Whether this is the session making the request.
+    current: bool,
+}
+
+fn session_error(e: session_store::Error) -> StoreError {
+    warn!("Session store failed: {e}");
+    StoreError::internal("sessions are unavailable".to_string())
+}
+
+/// Where the user is logged in, most recently active first.
+pub(crate) async fn sessions(
+    auth: Auth,
+    session: Session,
+    State(s): State<AppState>,
+) -> Result<Json<Vec<ActiveSession>>, StoreError> {
+    let user = logged_in(&auth)?;
+    let current = sessions::current_key(&session).await;
+    let active = s
+        .session_store
+        .user_sessions(&user.id)
+        .await
+        .map_err(session_error)?;
+    Ok(Json(
+        active
+            .into_iter()
+            .map(|info| ActiveSession {
+                current: current == Some(info.key),
+                info,
+            })
+            .collect(),
+    ))
+}
+
+/// Logs out one session, which may be this one.
+pub(crate) async fn revoke_session(
+    mut auth: Auth,
+    session: Session,
+    State(s): State<AppState>,
+    Path(key): Path<Uuid>,
+) -> Result<StatusCode, StoreError> {
+    let user = logged_in(&auth)?;
+    if sessions::current_key(&session).await == Some(key) {
+        if let Err(e) = auth.logout().await {
+            warn!("Failed to end session: {e}");
+        }
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    match s.session_store.revoke(&user.id, &key).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(StoreError {
+            reason: ErrorCause::NotFound,
+            message: "no such session".to_string(),
+        }),
+        Err(e) => Err(session_error(e)),
+    }
+}
+
+/// Logs out every session but this one.
+pub(crate) async fn revoke_other_sessions(
+    auth: Auth,
+    session: Session,
+    State(s): State<AppState>,
+) -> Result<StatusCode, StoreError> {
+    let user = logged_in(&auth)?;
+    let current = sessions::current_key(&session)
+        .await
+        .unwrap_or_else(Uuid::nil);
+    s.session_store
+        .revoke_others(&user.id, &current)
+        .await
+        .map_err(session_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,197 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use store_lib::{
+    account::User,
+    order::Order,
+    review::{ModerateReview, Review},
+    store::{AdjustStock, Inventory, LowStockThreshold, Product, StockAdjustment, Variant},
+    two_factor::StaffTwoFactorPolicy,
+};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{emails, AppState, Auth};
+
+use super::StoreError;
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+pub(crate) struct StockLevel {
+    product: Product,
+    variant: Variant,
+    inventory: Inventory,
+}
+
+impl From<(Product, Variant, Inventory)> for StockLevel {
+    fn from((product, variant, inventory): (Product, Variant, Inventory)) -> Self {
+        Self {
+            product,
+            variant,
+            inventory,
+        }
+    }
+}
+
+fn require_admin(auth: Auth) -> Result<User, StoreError> {
+    let user = auth.user.ok_or(StoreError::unauthorized(
+        "Must be logged in to manage the store".to_string(),
+    ))?;
+
+    if !user.is_admin() {
+        return Err(StoreError::forbidden("admin only".to_string()));
+    }
+
+    let user_store = auth.backend.lock().expect("user store threading issue");
+    if user_store.needs_two_factor(&user) {
+        return Err(StoreError::forbidden(
+            "set up two-factor authentication to manage the store".to_string(),
+        ));
+    }
+    drop(user_store);
+
+    Ok(user)
+}
+
+/// Requires staff to use 2FA, or stops requiring it.
+pub(crate) async fn set_staff_two_factor(
+    auth: Auth,
+    State(AppState { user_backend, .. }): State<AppState>,
+    Json(StaffTwoFactorPolicy { required }): Json<StaffTwoFactorPolicy>,
+) -> Result<StatusCode, StoreError> {
+    require_admin(auth)?;
+    let mut user_store = user_backend.lock().expect("user store threading issue");
+    user_store.set_staff_two_factor_required(required);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn inventory(
+    auth: Auth,
+    State(AppState {
+        inventory_backend, ..
+    }): State<AppState>,
+) -> Result<Json<Vec<StockLevel>>, StoreError> {
+    require_admin(auth)?;
+
+    let levels = inventory_backend
+        .stock_levels()
+        .into_iter()
+        .map(StockLevel::from)
+        .collect();
+
+    Ok(Json(levels))
+}
+
+pub(crate) async fn adjust_stock(
+    auth: Auth,
+    State(AppState {
+        inventory_backend, ..
+    }): State<AppState>,
+    Path(variant_id): Path<Uuid>,
+    Json(adjustment): Json<AdjustStock>,
+) -> Result<Json<Inventory>, StoreError> {
+    let admin = require_admin(auth)?;
+    let inventory = inventory_backend.adjust(variant_id, admin.id, adjustment)?;
+    Ok(Json(inventory))
+}
+
+pub(crate) async fn stock_ledger(
+    auth: Auth,
+    State(AppState {
+        inventory_backend, ..
+    }): State<AppState>,
+    Path(variant_id): Path<Uuid>,
+) -> Result<Json<Vec<StockAdjustment>>, StoreError> {
+    require_admin(auth)?;
+    Ok(Json(inventory_backend.ledger(&variant_id)))
+}
+
+pub(crate) async fn set_low_stock_threshold(
+    auth: Auth,
+    State(AppState {
+        inventory_backend, ..
+    }): State<AppState>,
+    Path(variant_id): Path<Uuid>,
+    Json(threshold): Json<LowStockThreshold>,
+) -> Result<Json<Inventory>, StoreError> {
+    require_admin(auth)?;
+    let inventory = inventory_backend.set_low_stock_threshold(&variant_id, threshold)?;
+    Ok(Json(inventory))
+}
+
+pub(crate) async fn low_stock_alerts(
+    auth: Auth,
+    State(AppState {
+        inventory_backend, ..
+    }): State<AppState>,
+) -> Result<Json<Vec<StockLevel>>, StoreError> {
+    require_admin(auth)?;
+
+    let alerts = inventory_backend
+        .low_stock_alerts()
+        .into_iter()
+        .map(StockLevel::from)
+        .collect();
+
+    Ok(Json(alerts))
+}
+
+/// Every review, pending first, so admins can approve or hide them.
+pub(crate) async fn reviews_for_moderation(
+    auth: Auth,
+    State(AppState { review_backend, .. }): State<AppState>,
+) -> Result<Json<Vec<Review>>, StoreError> {
+    require_admin(auth)?;
+    Ok(Json(review_backend.all()))
+}
+
+pub(crate) async fn moderate_review(
+    auth: Auth,
+    State(AppState { review_backend, .. }): State<AppState>,
+    Path(review_id): Path<Uuid>,
+    Json(ModerateReview { status }): Json<ModerateReview>,
+) -> Result<Json<Review>, StoreError> {
+    require_admin(auth)?;
+    Ok(Json(review_backend.moderate(&review_id, status)?))
+}
+
+/// Every order, newest first.
+pub(crate) async fn all_orders(
+    auth: Auth,
+    State(AppState { order_backend, .. }): State<AppState>,
+) -> Result<Json<Vec<Order>>, StoreError> {
+    require_admin(auth)?;
+    Ok(Json(order_backend.all()))
+}
+
+/// Ships an order and emails the customer.
+pub(crate) async fn ship_order(
+    auth: Auth,
+    State(s): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> Result<Json<Order>, StoreError> {
+    require_admin(auth)?;
+    let order = s.order_backend.ship(&order_id, &s.inventory_backend)?;
+    if let Some(to) = emails::order_recipient(&s.user_backend, &order) {
+        s.outbox
+            .enqueue(emails::order_shipped(&to, &order, &s.site_url));
+    }
+    Ok(Json(order))
+}
+
+/// Refunds an order and emails the customer.
+pub(crate) async fn refund_order(
+    auth: Auth,
+    State(s): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> Result<Json<Order>, StoreError> {
+    require_admin(auth)?;
+    let order = s.order_backend.refund(&order_id)?;
+    if let Some(to) = emails::order_recipient(&s.user_backend, &order) {
+        s.outbox.enqueue(emails::order_refunded(&to, &order));
+    }
+    Ok(Json(order))
+}
@@ -1,12 +1,10 @@
-use std::collections::HashMap;
-
 use axum::{
     debug_handler,
     extract::{Path, State},
     Json,
 };
 use serde::{Deserialize, Serialize};
-use store_lib::cart::{Cart, CartItem};
+use store_lib::cart::Cart;
 use ts_rs::TS;
 use uuid::Uuid;
 
@@ -19,21 +17,12 @@ pub(crate) async fn fetch_cart(
     auth: Auth,
     State(AppState { cart_backend, .. }): State<AppState>,
 ) -> Result<Json<Cart>, StoreError> {
-    let mut cart_store = cart_backend
-        .lock()
-        .map_err(|e| StoreError::internal(e.to_string()))?;
-
     // TODO: Allow viewing cart without user?
     let user = auth.user.ok_or(StoreError::unauthorized(
         "Must be logged in to view cart".to_string(),
     ))?;
 
-    let Some(cart) = cart_store.carts.get(&user.id) else {
-        cart_store.carts.insert(user.id, Cart::default());
-        return Ok(Json(Cart::default()));
-    };
-
-    Ok(Json(cart.clone()))
+    Ok(Json(cart_backend.cart(&user.id)))
 }
 
 #[derive(Serialize, Deserialize, TS)]
@@ -41,6 +30,9 @@ pub(crate) async fn fetch_cart(
 #[ts(export)]
 pub struct AddToCart {
     pub number: usize,
+    /// Defaults to the listing's first variant.
+    #[serde(default)]
+    pub variant_id: Option<Uuid>,
 }
 
 pub(crate) async fn add_to_cart(
@@ -53,67 +45,33 @@ pub(crate) async fn add_to_cart(
     Path(listing_id): Path<Uuid>,
     Json(item): Json<AddToCart>,
 ) -> Result<Json<Cart>, StoreError> {
-    let mut cart_store = cart_backend
-        .lock()
-        .map_err(|e| StoreError::internal(e.to_string()))?;
-
-    let inventory = inventory_backend
-        .products
-        .lock()
-        .map_err(|e| StoreError::internal(e.to_string()))?;
-
     let user_id = auth
         .user
         .ok_or(StoreError::unauthorized("user not found".to_string()))?
         .id();
 
-    let cart = cart_store
-        .carts
-        .entry(user_id)
-        .or_insert_with(|| Cart::new(HashMap::new()));
-
-    let listing = inventory
-        .get(&listing_id)
-        .ok_or(StoreError::missing_inventory(listing_id.to_string()))?;
-
-    cart.items
-        .entry(listing_id)
-        .and_modify(|p| p.number += item.number)
-        .or_insert(CartItem {
-            listing: listing.clone(),
-            number: item.number,
-        });
-
-    Ok(Json(cart.clone()))
-}
+    let cart = cart_backend.add(
+        user_id,
+        &inventory_backend,
+        &listing_id,
+        item.variant_id,
+        item.number,
+    )?;
 
-#[derive(Serialize, Deserialize, TS)]
-#[serde(rename_all = "camelCase")]
-#[ts(export)]
-pub struct RemoveFromCart {
-    pub listing_id: Uuid,
+    Ok(Json(cart))
 }
 
+/// Removes a cart line. Lines are keyed by variant, which for products
+/// without options is the listing id.
 pub(crate) async fn remove_from_cart(
     auth: Auth,
     State(AppState { cart_backend, .. }): State<AppState>,
-    Path(listing_id): Path<Uuid>,
+    Path(variant_id): Path<Uuid>,
 ) -> Result<Json<Cart>, StoreError> {
-    let mut cart_store = cart_backend
-        .lock()
-        .map_err(|e| StoreError::internal(e.to_string()))?;
-
     let user_id = auth
         .user
         .ok_or(StoreError::unauthorized("user not found".to_string()))?
         .id();
 
-    let cart = cart_store
-        .carts
-        .entry(user_id)
-        .or_insert_with(|| Cart::new(HashMap::new()));
-
-    cart.items.remove(&listing_id);
-
-    Ok(Json(cart.clone()))
+    Ok(Json(cart_backend.remove(user_id, &variant_id)))
 }
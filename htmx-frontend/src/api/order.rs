@@ -0,0 +1,41 @@
+use axum::{extract::State, Json};
+use store_lib::order::Order;
+
+use crate::{emails, AppState, Auth};
+
+use super::StoreError;
+
+pub(crate) async fn orders(
+    auth: Auth,
+    State(AppState { order_backend, .. }): State<AppState>,
+) -> Result<Json<Vec<Order>>, StoreError> {
+    let user = auth.user.ok_or(StoreError::unauthorized(
+        "Must be logged in to view orders".to_string(),
+    ))?;
+
+    Ok(Json(order_backend.orders(&user.id)))
+}
+
+/// Places an order for everything in the user's cart and empties it.
+pub(crate) async fn place_order(
+    auth: Auth,
+    State(AppState {
+        cart_backend,
+        inventory_backend,
+        order_backend,
+        outbox,
+        site_url,
+        ..
+    }): State<AppState>,
+) -> Result<Json<Order>, StoreError> {
+    let user = auth.user.ok_or(StoreError::unauthorized(
+        "Must be logged in to place an order".to_string(),
+    ))?;
+
+    let cart = cart_backend.cart(&user.id);
+    let order = order_backend.place(&user, &cart, &inventory_backend)?;
+    cart_backend.clear(&user.id);
+    outbox.enqueue(emails::order_confirmation(&user.email, &order, &site_url));
+
+    Ok(Json(order))
+}
@@ -0,0 +1,48 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use store_lib::review::{NewReview, Review, ReviewPage, ReviewQuery};
+use uuid::Uuid;
+
+use crate::{AppState, Auth};
+
+use super::StoreError;
+
+pub(crate) async fn reviews(
+    State(AppState {
+        inventory_backend,
+        review_backend,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+    Query(query): Query<ReviewQuery>,
+) -> Result<Json<ReviewPage>, StoreError> {
+    inventory_backend
+        .product(&listing_id)
+        .ok_or(StoreError::missing_inventory(listing_id.to_string()))?;
+
+    Ok(Json(review_backend.reviews(&listing_id, &query)))
+}
+
+pub(crate) async fn submit_review(
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        order_backend,
+        review_backend,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+    Json(review): Json<NewReview>,
+) -> Result<Json<Review>, StoreError> {
+    let user = auth.user.ok_or(StoreError::unauthorized(
+        "Must be logged in to write a review".to_string(),
+    ))?;
+    inventory_backend
+        .product(&listing_id)
+        .ok_or(StoreError::missing_inventory(listing_id.to_string()))?;
+
+    let review = review_backend.submit(&user, listing_id, &order_backend, review)?;
+    Ok(Json(review))
+}
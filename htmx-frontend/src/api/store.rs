@@ -1,28 +1,54 @@
-use axum::{extract::State, Json};
-use serde::Serialize;
-use store_lib::store::Product;
-use ts_rs::TS;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use store_lib::{
+    listing::{ListingPage, ListingQuery},
+    restock::RestockSubscription,
+    store::Product,
+};
+use uuid::Uuid;
 
 use crate::AppState;
 
-#[derive(Serialize, TS)]
-#[ts(export)]
-pub(crate) struct ListingResponse {
-    listings: Vec<Product>,
-}
+use super::StoreError;
 
 pub(crate) async fn listing(
     State(AppState {
         inventory_backend, ..
     }): State<AppState>,
-) -> Json<ListingResponse> {
-    let listings = inventory_backend
-        .products
-        .lock()
-        .unwrap()
-        .values()
-        .map(Product::clone)
-        .collect();
+    Query(query): Query<ListingQuery>,
+) -> Json<ListingPage> {
+    Json(inventory_backend.listings(&query))
+}
+
+pub(crate) async fn product(
+    State(AppState {
+        inventory_backend, ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+) -> Result<Json<Product>, StoreError> {
+    inventory_backend
+        .product(&listing_id)
+        .map(Json)
+        .ok_or(StoreError::missing_inventory(listing_id.to_string()))
+}
+
+/// Emails `email` once when the listing can be bought again.
+pub(crate) async fn notify_when_restocked(
+    State(AppState {
+        inventory_backend,
+        restock_alerts,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+    Json(RestockSubscription { email }): Json<RestockSubscription>,
+) -> Result<StatusCode, StoreError> {
+    inventory_backend
+        .product(&listing_id)
+        .ok_or(StoreError::missing_inventory(listing_id.to_string()))?;
 
-    Json(ListingResponse { listings })
+    restock_alerts.subscribe(listing_id, &email)?;
+    Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,82 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use store_lib::{account::User, cart::Cart, wishlist::Wishlist};
+use uuid::Uuid;
+
+use crate::{AppState, Auth};
+
+use super::StoreError;
+
+fn require_user(auth: Auth) -> Result<User, StoreError> {
+    auth.user.ok_or(StoreError::unauthorized(
+        "Must be logged in to use a wishlist".to_string(),
+    ))
+}
+
+pub(crate) async fn fetch_wishlist(
+    auth: Auth,
+    State(AppState {
+        wishlist_backend, ..
+    }): State<AppState>,
+) -> Result<Json<Wishlist>, StoreError> {
+    let user = require_user(auth)?;
+    Ok(Json(wishlist_backend.wishlist(&user.id)))
+}
+
+pub(crate) async fn add_to_wishlist(
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        wishlist_backend,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+) -> Result<Json<Wishlist>, StoreError> {
+    let user = require_user(auth)?;
+    let product = inventory_backend
+        .product(&listing_id)
+        .ok_or(StoreError::missing_inventory(listing_id.to_string()))?;
+
+    Ok(Json(wishlist_backend.add(user.id, &product)))
+}
+
+pub(crate) async fn remove_from_wishlist(
+    auth: Auth,
+    State(AppState {
+        wishlist_backend, ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+) -> Result<Json<Wishlist>, StoreError> {
+    let user = require_user(auth)?;
+    Ok(Json(wishlist_backend.remove(user.id, &listing_id)))
+}
+
+pub(crate) async fn move_to_cart(
+    auth: Auth,
+    State(AppState {
+        cart_backend,
+        inventory_backend,
+        wishlist_backend,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+) -> Result<Json<Cart>, StoreError> {
+    let user = require_user(auth)?;
+    let cart =
+        wishlist_backend.move_to_cart(user.id, &listing_id, &cart_backend, &inventory_backend)?;
+    Ok(Json(cart))
+}
+
+/// Marks the user's back-in-stock notices as seen.
+pub(crate) async fn dismiss_notices(
+    auth: Auth,
+    State(AppState {
+        wishlist_backend, ..
+    }): State<AppState>,
+) -> Result<Json<Wishlist>, StoreError> {
+    let user = require_user(auth)?;
+    wishlist_backend.dismiss_notices(&user.id);
+    Ok(Json(wishlist_backend.wishlist(&user.id)))
+}
@@ -1,7 +1,9 @@
+mod csrf_field;
 pub mod icons;
 mod navbar;
 mod notification;
 mod page_wrapper;
+pub mod rating;
 pub mod text_field;
 
 #[allow(dead_code)]
@@ -15,6 +17,7 @@ pub enum Color {
     Default,
 }
 
+pub use crate::components::csrf_field::csrf_field;
 pub use crate::components::navbar::navbar;
 pub use crate::components::notification::notification;
 pub use crate::components::page_wrapper::PageWrapper;
@@ -0,0 +1,24 @@
+use maud::{html, Markup};
+
+use crate::csrf::CSRF_FIELD;
+
+/// The session's CSRF token, for plain form posts. HTMX requests send it
+/// in a header instead.
+pub fn csrf_field(csrf_token: &str) -> Markup {
+    html! {
+        input type="hidden" name=(CSRF_FIELD) value=(csrf_token);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn the_token_is_escaped() {
+        assert_eq!(
+            csrf_field(r#"a"b"#).into_string(),
+            r#"<input type="hidden" name="csrf_token" value="a&quot;b">"#
+        );
+    }
+}
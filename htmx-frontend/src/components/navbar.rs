@@ -1,11 +1,14 @@
 use crate::{
-    components::icons::{MOON, SHOPPING_CART},
+    components::{
+        csrf_field,
+        icons::{MOON, SHOPPING_CART},
+    },
     Auth,
 };
 use maud::{html, Markup};
 use store_lib::account::User;
 
-pub fn navbar(auth: &Auth) -> Markup {
+pub fn navbar(auth: &Auth, csrf_token: &str) -> Markup {
     html! {
         nav.navbar {
             .navbar-brand {
@@ -20,26 +23,46 @@ pub fn navbar(auth: &Auth) -> Markup {
             .navbar-menu {
                 .navbar-start {
                     a.navbar-item href="/" { "Store" }
+                    @if auth.user.is_some() {
+                        a.navbar-item href="/wishlist" { "Wishlist" }
+                    }
+                    @if auth.user.as_ref().is_some_and(|u| u.is_admin()) {
+                        a.navbar-item href="/admin/inventory" { "Inventory" }
+                        a.navbar-item href="/admin/orders" { "Orders" }
+                        a.navbar-item href="/admin/reviews" { "Reviews" }
+                        a.navbar-item href="/admin/security" { "Security" }
+                    }
+                    form.navbar-item action="/" method="get" role="search" {
+                        .field.has-addons {
+                            .control {
+                                input.input name="q" type="search" placeholder="Search plants" aria-label="Search plants";
+                            }
+                            .control {
+                                button.button type="submit" { "Search" }
+                            }
+                        }
+                    }
                 }
 
                 .navbar-end {
-                    (account_buttons(&auth.user))
+                    (account_buttons(&auth.user, csrf_token))
                 }
             }
         }
     }
 }
 
-fn account_buttons(maybe_user: &Option<User>) -> Markup {
+fn account_buttons(maybe_user: &Option<User>, csrf_token: &str) -> Markup {
     html!(
         .navbar-item {
             @if let Some(ref user) = maybe_user {
-                span { "Welcome, " (user.username)}
+                a href="/account" title="Account Settings" { "Welcome, " (user.username) }
             }
         }
         .navbar-item.buttons {
             @if maybe_user.is_some() {
                 form action="/logout" method="post" {
+                    (csrf_field(csrf_token))
                     button.button.logout.is-warning type="submit"  { "Logout" }
                 }
             } @else {
@@ -1,4 +1,8 @@
-use crate::{components::navbar, Auth};
+use crate::{
+    components::navbar,
+    csrf::{self, CSRF_FIELD, CSRF_HEADER},
+    Auth,
+};
 use axum::{
     async_trait,
     extract::FromRequestParts,
@@ -6,13 +10,15 @@ use axum::{
 };
 
 use axum_extra::extract::cookie::CookieJar;
-use maud::{html, Markup, DOCTYPE};
+use axum_login::tower_sessions::Session;
+use maud::{html, Markup, PreEscaped, DOCTYPE};
 
 const DEFAULT_THEME: &'static str = "dark";
 
 pub struct PageWrapper {
     auth: Auth,
     theme: String,
+    csrf_token: String,
 }
 
 #[async_trait]
@@ -32,7 +38,14 @@ where
             .unwrap_or(DEFAULT_THEME)
             .to_owned();
 
-        Ok(Self { auth, theme })
+        let session = Session::from_request_parts(parts, state).await?;
+        let csrf_token = csrf::token(&session).await;
+
+        Ok(Self {
+            auth,
+            theme,
+            csrf_token,
+        })
     }
 }
 
@@ -40,23 +53,30 @@ impl PageWrapper {
     pub fn render(self, template: Markup) -> Markup {
         html!(
             html data-theme=(&self.theme) {
-                (header("Rocks and Plants!"))
-                body {
-                    (navbar(&self.auth))
+                (header("Rocks and Plants!", &self.csrf_token))
+                body hx-headers=(format!(r#"{{"{CSRF_HEADER}": "{}"}}"#, self.csrf_token)) {
+                    (navbar(&self.auth, &self.csrf_token))
                     div id="notifications" {}
                     (template)
                     (footer())
                     (htmx_script())
+                    (csrf_script())
                 }
             }
         )
     }
+
+    /// For `csrf_field` in the page's post forms.
+    pub fn csrf_token(&self) -> &str {
+        &self.csrf_token
+    }
 }
 
-fn header(page_title: &str) -> Markup {
+fn header(page_title: &str, csrf_token: &str) -> Markup {
     html! {
         (DOCTYPE)
         meta charset="utf-8";
+        meta name="csrf-token" content=(csrf_token);
         title { (page_title) }
         link rel="preconnect" href="https://fonts.googleapis.com";
         link rel="preconnect" href="https://fonts.gstatic.com" crossorigin;
@@ -84,3 +104,22 @@ fn htmx_script() -> Markup {
         crossorigin="anonymous" { }
     }
 }
+
+/// Adds the CSRF token to plain form posts missing it as they're
+/// submitted, such as forms swapped in by HTMX.
+fn csrf_script() -> Markup {
+    let script = format!(
+        r#"document.addEventListener("submit", function (event) {{
+            var form = event.target;
+            if (form.method !== "post" || form.elements["{CSRF_FIELD}"]) return;
+            var input = document.createElement("input");
+            input.type = "hidden";
+            input.name = "{CSRF_FIELD}";
+            input.value = document.querySelector('meta[name="csrf-token"]').content;
+            form.appendChild(input);
+        }});"#
+    );
+    html! {
+        script { (PreEscaped(script)) }
+    }
+}
@@ -0,0 +1,28 @@
+use maud::{html, Markup};
+use store_lib::review::RatingSummary;
+
+/// Five stars with the average rounded to the nearest whole star.
+pub fn stars(rating: f32) -> Markup {
+    let filled = rating.round().clamp(0.0, 5.0) as usize;
+    html! {
+        span.has-text-warning title=(format!("{rating:.1} out of 5")) {
+            @for i in 0..5 {
+                @if i < filled { "\u{2605}" } @else { "\u{2606}" }
+            }
+        }
+    }
+}
+
+pub fn rating_summary(summary: RatingSummary) -> Markup {
+    html! {
+        @if summary.count == 0 {
+            span.has-text-grey.is-size-7 { "No reviews yet" }
+        } @else {
+            span.is-size-7 {
+                (stars(summary.average))
+                " " (format!("{:.1}", summary.average))
+                " (" (summary.count) ")"
+            }
+        }
+    }
+}
@@ -0,0 +1,606 @@
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use bigdecimal::{BigDecimal, One, Zero};
+use clap::{Parser, Subcommand};
+use serde::Deserialize;
+use store_lib::{fixture::decimal, order::Pricing};
+
+use crate::rate_limit::RateLimit;
+
+/// Read when no file is named and it exists.
+const DEFAULT_CONFIG_FILE: &str = "plantomics.toml";
+/// Shortest SECRET_KEY accepted, in bytes.
+const MIN_SECRET_KEY_LENGTH: usize = 32;
+
+/// Command line flags. These override the same settings from the file and
+/// environment.
+#[derive(Parser, Debug)]
+#[command(about = "The Plantomics shop")]
+pub struct Args {
+    /// TOML file to read settings from [default: plantomics.toml, if present]
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(long)]
+    pub bind: Option<IpAddr>,
+    /// Port to listen on
+    #[arg(long, short)]
+    pub port: Option<u16>,
+    /// Prefix for links in emails and OIDC redirects
+    #[arg(long)]
+    pub site_url: Option<String>,
+    /// Postgres or SQLite URL for sessions
+    #[arg(long)]
+    pub database_url: Option<String>,
+    /// Directory served at /assets
+    #[arg(long)]
+    pub assets_dir: Option<PathBuf>,
+    /// Serve a mock identity provider and log in through it
+    #[arg(long)]
+    pub mock_idp: bool,
+    /// Load users and products from a YAML or JSON fixture at startup. Not
+    /// a config file setting, so a file shared with production can't load
+    /// demo accounts.
+    #[arg(long = "seed", value_name = "FILE")]
+    pub seed: Vec<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check fixture files load cleanly, without starting the server. The
+    /// stores live in the server, so pass the files to --seed to use them.
+    CheckFixtures {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+/// Settings, layered from lowest to highest priority: defaults, the TOML
+/// file, environment variables, then command line flags.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub session: SessionSettings,
+    pub mail: MailConfig,
+    pub security: SecurityConfig,
+    pub oidc: OidcSettings,
+    pub shop: ShopConfig,
+    pub features: Features,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Defaults to `http://localhost:<port>`.
+    pub site_url: Option<String>,
+    pub assets_dir: PathBuf,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8080,
+            site_url: None,
+            assets_dir: PathBuf::from("assets"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Where sessions are kept. A local SQLite file by default.
+    pub url: String,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::from("sqlite://sessions.db?mode=rwc"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionSettings {
+    /// How long a remembered login lasts without being used.
+    pub lifetime_days: i64,
+    /// Only send the cookie over HTTPS.
+    pub secure_cookie: bool,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            lifetime_days: 30,
+            secure_cookie: false,
+        }
+    }
+}
+
+/// Mail goes through `smtp_host` when it is set, logging in when
+/// `smtp_username` is too. Otherwise it is written to files in `dir`.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    pub from: String,
+    pub dir: PathBuf,
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            from: String::from("Plantomics <shop@plantomics.example>"),
+            dir: PathBuf::from("mail"),
+            smtp_host: None,
+            smtp_port: 25,
+            smtp_username: None,
+            smtp_password: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// Key for signing tokens sent by email. Without one a random key is
+    /// used, so links sent before a restart stop working.
+    pub secret_key: Option<String>,
+    pub login_max_failures: u32,
+    pub login_max_ip_failures: u32,
+    pub login_lockout_minutes: i64,
+    pub auth_rate_limit: RateLimit,
+    pub cart_rate_limit: RateLimit,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            secret_key: None,
+            login_max_failures: 5,
+            login_max_ip_failures: 20,
+            login_lockout_minutes: 15,
+            auth_rate_limit: RateLimit {
+                burst: 10,
+                per_minute: 10,
+            },
+            cart_rate_limit: RateLimit {
+                burst: 30,
+                per_minute: 60,
+            },
+        }
+    }
+}
+
+/// An identity provider to log in with. Without `issuer_url` there are
+/// only password logins.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OidcSettings {
+    pub issuer_url: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    /// Shown on the login button.
+    pub provider_name: String,
+}
+
+impl Default for OidcSettings {
+    fn default() -> Self {
+        Self {
+            issuer_url: None,
+            client_id: None,
+            client_secret: None,
+            provider_name: String::from("Single Sign-On"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShopConfig {
+    /// Flat rate for standard shipping.
+    #[serde(deserialize_with = "decimal")]
+    pub shipping: BigDecimal,
+    /// Sales tax on the subtotal, as a fraction.
+    #[serde(deserialize_with = "decimal")]
+    pub tax_rate: BigDecimal,
+}
+
+impl Default for ShopConfig {
+    fn default() -> Self {
+        let pricing = Pricing::default();
+        Self {
+            shipping: pricing.shipping,
+            tax_rate: pricing.tax_rate,
+        }
+    }
+}
+
+impl ShopConfig {
+    pub fn pricing(&self) -> Pricing {
+        Pricing {
+            shipping: self.shipping.with_scale(2),
+            tax_rate: self.tax_rate.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    /// Serve a mock identity provider at /mock-idp and log in through it.
+    pub mock_idp: bool,
+    /// Make staff set up 2FA before managing the store.
+    pub staff_two_factor: bool,
+}
+
+impl Config {
+    /// Reads each layer in turn, then checks the result.
+    pub fn load(args: &Args) -> Result<Self, ConfigError> {
+        let path = args
+            .config
+            .clone()
+            .or_else(|| std::env::var("CONFIG_FILE").ok().map(PathBuf::from))
+            .or_else(|| {
+                let default = PathBuf::from(DEFAULT_CONFIG_FILE);
+                default.exists().then_some(default)
+            });
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        config.apply_args(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Read {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        toml::from_str(&text).map_err(|error| ConfigError::Parse {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    /// Environment variables, named as they were before there was a
+    /// config file.
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        let Config {
+            server,
+            database,
+            session,
+            mail,
+            security,
+            oidc,
+            shop,
+            features,
+        } = self;
+        env("BIND_ADDRESS", &mut server.bind)?;
+        env("PORT", &mut server.port)?;
+        env_opt("SITE_URL", &mut server.site_url)?;
+        env("ASSETS_DIR", &mut server.assets_dir)?;
+        env("DATABASE_URL", &mut database.url)?;
+        env("SESSION_LIFETIME_DAYS", &mut session.lifetime_days)?;
+        env("SESSION_SECURE_COOKIE", &mut session.secure_cookie)?;
+        env("MAIL_FROM", &mut mail.from)?;
+        env("MAIL_DIR", &mut mail.dir)?;
+        env_opt("SMTP_HOST", &mut mail.smtp_host)?;
+        env("SMTP_PORT", &mut mail.smtp_port)?;
+        env_opt("SMTP_USERNAME", &mut mail.smtp_username)?;
+        env_opt("SMTP_PASSWORD", &mut mail.smtp_password)?;
+        env_opt("SECRET_KEY", &mut security.secret_key)?;
+        env("LOGIN_MAX_FAILURES", &mut security.login_max_failures)?;
+        env("LOGIN_MAX_IP_FAILURES", &mut security.login_max_ip_failures)?;
+        env("LOGIN_LOCKOUT_MINUTES", &mut security.login_lockout_minutes)?;
+        env("AUTH_RATE_BURST", &mut security.auth_rate_limit.burst)?;
+        env(
+            "AUTH_RATE_PER_MINUTE",
+            &mut security.auth_rate_limit.per_minute,
+        )?;
+        env("CART_RATE_BURST", &mut security.cart_rate_limit.burst)?;
+        env(
+            "CART_RATE_PER_MINUTE",
+            &mut security.cart_rate_limit.per_minute,
+        )?;
+        env_opt("OIDC_ISSUER_URL", &mut oidc.issuer_url)?;
+        env_opt("OIDC_CLIENT_ID", &mut oidc.client_id)?;
+        env_opt("OIDC_CLIENT_SECRET", &mut oidc.client_secret)?;
+        env("OIDC_PROVIDER_NAME", &mut oidc.provider_name)?;
+        env("SHIPPING_COST", &mut shop.shipping)?;
+        env("TAX_RATE", &mut shop.tax_rate)?;
+        env("OIDC_MOCK", &mut features.mock_idp)?;
+        env("STAFF_TWO_FACTOR", &mut features.staff_two_factor)?;
+        Ok(())
+    }
+
+    fn apply_args(&mut self, args: &Args) {
+        if let Some(bind) = args.bind {
+            self.server.bind = bind;
+        }
+        if let Some(port) = args.port {
+            self.server.port = port;
+        }
+        if let Some(site_url) = &args.site_url {
+            self.server.site_url = Some(site_url.clone());
+        }
+        if let Some(url) = &args.database_url {
+            self.database.url = url.clone();
+        }
+        if let Some(dir) = &args.assets_dir {
+            self.server.assets_dir = dir.clone();
+        }
+        if args.mock_idp {
+            self.features.mock_idp = true;
+        }
+    }
+
+    /// Collects every problem, so they can all be fixed in one go.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        check(self.server.port != 0, "server.port can't be 0");
+        check(
+            ["http://", "https://"]
+                .iter()
+                .any(|scheme| self.site_url().starts_with(scheme)),
+            "server.site_url must start with http:// or https://",
+        );
+        check(
+            self.server.assets_dir.is_dir(),
+            "server.assets_dir must be a directory",
+        );
+        check(
+            ["postgres://", "postgresql://", "sqlite:"]
+                .iter()
+                .any(|scheme| self.database.url.starts_with(scheme)),
+            "database.url must be a postgres:// or sqlite: URL",
+        );
+        check(
+            self.session.lifetime_days > 0,
+            "session.lifetime_days must be at least 1",
+        );
+        check(
+            self.mail.smtp_password.is_none() || self.mail.smtp_username.is_some(),
+            "mail.smtp_password needs mail.smtp_username",
+        );
+        check(
+            self.security
+                .secret_key
+                .as_ref()
+                .is_none_or(|key| key.len() >= MIN_SECRET_KEY_LENGTH),
+            "security.secret_key must be at least 32 characters",
+        );
+        check(
+            self.security.login_max_failures > 0 && self.security.login_max_ip_failures > 0,
+            "security.login_max_failures and login_max_ip_failures must be at least 1",
+        );
+        check(
+            self.security.login_lockout_minutes > 0,
+            "security.login_lockout_minutes must be at least 1",
+        );
+        for (name, limit) in [
+            ("auth_rate_limit", self.security.auth_rate_limit),
+            ("cart_rate_limit", self.security.cart_rate_limit),
+        ] {
+            check(
+                limit.burst > 0 && limit.per_minute > 0,
+                &format!("security.{name} needs a burst and per_minute of at least 1"),
+            );
+        }
+        check(
+            self.oidc.issuer_url.is_some() == self.oidc.client_id.is_some(),
+            "oidc.issuer_url and oidc.client_id must be set together",
+        );
+        check(
+            !(self.features.mock_idp && self.oidc.issuer_url.is_some()),
+            "features.mock_idp can't be used with oidc.issuer_url",
+        );
+        check(
+            self.shop.shipping >= BigDecimal::zero(),
+            "shop.shipping can't be negative",
+        );
+        check(
+            self.shop.tax_rate >= BigDecimal::zero() && self.shop.tax_rate < BigDecimal::one(),
+            "shop.tax_rate must be a fraction from 0 up to 1",
+        );
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(problems)),
+        }
+    }
+
+    pub fn site_url(&self) -> String {
+        match &self.server.site_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => format!("http://localhost:{}", self.server.port),
+        }
+    }
+}
+
+/// Sets `field` from the variable `name`, if it is set.
+fn env<T>(name: &'static str, field: &mut T) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(value) = std::env::var(name) {
+        *field = value.parse().map_err(|e: T::Err| ConfigError::Env {
+            name,
+            message: e.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+fn env_opt<T>(name: &'static str, field: &mut Option<T>) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(value) = std::env::var(name) {
+        let value = value.parse().map_err(|e: T::Err| ConfigError::Env {
+            name,
+            message: e.to_string(),
+        })?;
+        *field = Some(value);
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    Env {
+        name: &'static str,
+        message: String,
+    },
+    Invalid(Vec<String>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => {
+                write!(f, "can't read {}: {error}", path.display())
+            }
+            ConfigError::Parse { path, error } => write!(f, "in {}: {error}", path.display()),
+            ConfigError::Env { name, message } => write!(f, "{name}: {message}"),
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assets_dir() -> String {
+        format!("{}/../assets", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    fn args(flags: &[&str]) -> Args {
+        let program = ["htmx-frontend"].iter().chain(flags);
+        Args::parse_from(program)
+    }
+
+    /// The only test touching the environment, as tests share it.
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let path = std::env::temp_dir().join(format!("plantomics-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+[server]
+bind = "127.0.0.1"
+port = 4000
+
+[database]
+url = "sqlite://shop.db"
+
+[shop]
+shipping = 4.5
+"#,
+        )
+        .unwrap();
+        let file = path.to_str().unwrap();
+        let assets = assets_dir();
+        std::env::set_var("PORT", "5000");
+        std::env::set_var("DATABASE_URL", "sqlite::memory:");
+
+        let config = Config::load(&args(&["--config", file, "--assets-dir", &assets])).unwrap();
+        assert_eq!(config.server.bind, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(config.shop.shipping.to_string(), "4.5");
+        assert_eq!(config.server.port, 5000);
+        assert_eq!(config.database.url, "sqlite::memory:");
+        assert_eq!(config.session.lifetime_days, 30);
+
+        let config = Config::load(&args(&[
+            "--config",
+            file,
+            "--assets-dir",
+            &assets,
+            "--port",
+            "6000",
+        ]));
+        assert_eq!(config.unwrap().server.port, 6000);
+
+        std::env::set_var("PORT", "many");
+        let config = Config::load(&args(&["--config", file, "--assets-dir", &assets]));
+        assert!(matches!(config, Err(ConfigError::Env { name: "PORT", .. })));
+        std::env::remove_var("PORT");
+        std::env::remove_var("DATABASE_URL");
+
+        std::fs::write(&path, "[server]\nprot = 4000\n").unwrap();
+        let config = Config::load(&args(&["--config", file]));
+        assert!(matches!(config, Err(ConfigError::Parse { .. })));
+        std::fs::remove_file(&path).unwrap();
+        let config = Config::load(&args(&["--config", file]));
+        assert!(matches!(config, Err(ConfigError::Read { .. })));
+    }
+
+    #[test]
+    fn validation_reports_every_problem() {
+        let mut config = Config::default();
+        config.server.assets_dir = PathBuf::from(assets_dir());
+        assert!(config.validate().is_ok());
+
+        config.server.port = 0;
+        config.security.secret_key = Some("too short".to_string());
+        config.oidc.client_id = Some("plantomics".to_string());
+        config.shop.tax_rate = BigDecimal::one();
+        let Err(ConfigError::Invalid(problems)) = config.validate() else {
+            panic!("invalid config was accepted");
+        };
+        assert_eq!(
+            problems,
+            [
+                "server.port can't be 0",
+                "security.secret_key must be at least 32 characters",
+                "oidc.issuer_url and oidc.client_id must be set together",
+                "shop.tax_rate must be a fraction from 0 up to 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_login::tower_sessions::Session;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Header HTMX requests carry the token in.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+/// Form field plain form posts carry the token in.
+pub const CSRF_FIELD: &str = "csrf_token";
+
+const SESSION_KEY: &str = "csrf_token";
+/// Largest form body read while looking for the token.
+const MAX_FORM_BYTES: usize = 64 * 1024;
+
+/// The session's CSRF token, creating one if the session has none yet.
+pub async fn token(session: &Session) -> String {
+    match session.get::<String>(SESSION_KEY).await {
+        Ok(Some(token)) => token,
+        Ok(None) => {
+            let token = Uuid::new_v4().simple().to_string();
+            if let Err(e) = session.insert(SESSION_KEY, &token).await {
+                warn!("Failed to store CSRF token: {e}");
+            }
+            token
+        }
+        Err(e) => {
+            warn!("Failed to read CSRF token: {e}");
+            String::new()
+        }
+    }
+}
+
+/// Why a request was refused.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// No token was sent, or not the session's.
+    BadToken,
+    /// The form was too large to look for the token in.
+    TooLarge,
+}
+
+/// Passes safe requests and state changing ones whose token, from the
+/// `X-CSRF-Token` header or a `csrf_token` form field, matches the
+/// session's. Form bodies are read and put back for the handler.
+pub async fn check(session: &Session, request: Request) -> Result<Request, Rejection> {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        return Ok(request);
+    }
+
+    let expected = match session.get::<String>(SESSION_KEY).await {
+        Ok(Some(token)) => token,
+        _ => return Err(Rejection::BadToken),
+    };
+
+    let header = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+    if let Some(sent) = header {
+        return match tokens_match(sent, &expected) {
+            true => Ok(request),
+            false => Err(Rejection::BadToken),
+        };
+    }
+
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return Err(Rejection::BadToken);
+    }
+
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_FORM_BYTES).await else {
+        return Err(Rejection::TooLarge);
+    };
+    let sent = form_urlencoded_field(&bytes, CSRF_FIELD);
+    match sent.is_some_and(|sent| tokens_match(&sent, &expected)) {
+        true => Ok(Request::from_parts(parts, Body::from(bytes))),
+        false => Err(Rejection::BadToken),
+    }
+}
+
+/// Middleware for page routes, answering with a plain 403.
+pub async fn verify(session: Session, request: Request, next: Next) -> Response {
+    match check(&session, request).await {
+        Ok(request) => next.run(request).await,
+        Err(Rejection::BadToken) => {
+            let message = "Your session has expired. Please reload the page and try again.";
+            (StatusCode::FORBIDDEN, message).into_response()
+        }
+        Err(Rejection::TooLarge) => StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    }
+}
+
+fn form_urlencoded_field(body: &[u8], name: &str) -> Option<String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .ok()?
+        .into_iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value)
+}
+
+/// Compares in constant time so the token can't be guessed a byte at a time.
+fn tokens_match(sent: &str, expected: &str) -> bool {
+    sent.len() == expected.len()
+        && sent
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{middleware::from_fn, routing::get, Router};
+    use axum_login::tower_sessions::{MemoryStore, SessionManagerLayer};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn post(token: Option<&str>, form: Option<&str>) -> Request {
+        let mut request = Request::builder().method(Method::POST).uri("/");
+        if let Some(token) = token {
+            request = request.header(CSRF_HEADER, token);
+        }
+        match form {
+            Some(form) => request
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(form.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn state_changes_need_the_session_token() {
+        let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+        let get = Request::builder().uri("/").body(Body::empty()).unwrap();
+        assert!(check(&session, get).await.is_ok());
+        // Sessions without a token yet can't pass.
+        assert_eq!(
+            check(&session, post(Some(""), None)).await.err(),
+            Some(Rejection::BadToken)
+        );
+
+        let token = token(&session).await;
+        // The same token for the rest of the session.
+        assert_eq!(super::token(&session).await, token);
+        assert!(check(&session, post(Some(&token), None)).await.is_ok());
+        for request in [
+            post(None, None),
+            post(Some("wrong"), None),
+            post(None, Some("csrf_token=wrong")),
+            // A header wins over the form field.
+            post(Some("wrong"), Some(&format!("csrf_token={token}"))),
+        ] {
+            assert_eq!(
+                check(&session, request).await.err(),
+                Some(Rejection::BadToken)
+            );
+        }
+
+        let form = format!("name=fern&csrf_token={token}");
+        let request = check(&session, post(None, Some(&form))).await.unwrap();
+        let body = to_bytes(request.into_body(), MAX_FORM_BYTES).await.unwrap();
+        assert_eq!(body, form.as_bytes());
+
+        let huge = format!("csrf_token={token}&{}", "a".repeat(MAX_FORM_BYTES));
+        assert_eq!(
+            check(&session, post(None, Some(&huge))).await.err(),
+            Some(Rejection::TooLarge)
+        );
+    }
+
+    #[tokio::test]
+    async fn pages_refuse_bad_tokens_with_a_403() {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|session: Session| async move { token(&session).await })
+                    .post(|body: String| async move { body }),
+            )
+            .route_layer(from_fn(verify))
+            .layer(SessionManagerLayer::new(MemoryStore::default()));
+
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].clone();
+        let token = to_bytes(response.into_body(), MAX_FORM_BYTES)
+            .await
+            .unwrap();
+        let token = String::from_utf8(token.to_vec()).unwrap();
+
+        let send = |header_token: Option<&str>, form: &str| {
+            let mut request = post(header_token, Some(form));
+            request.headers_mut().insert(header::COOKIE, cookie.clone());
+            app.clone().oneshot(request)
+        };
+        for (header_token, expected) in [
+            (None, StatusCode::FORBIDDEN),
+            (Some("wrong"), StatusCode::FORBIDDEN),
+            (Some(token.as_str()), StatusCode::OK),
+        ] {
+            let response = send(header_token, "").await.unwrap();
+            assert_eq!(response.status(), expected);
+        }
+        let form = format!("csrf_token={token}");
+        let response = send(None, &form).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), MAX_FORM_BYTES)
+            .await
+            .unwrap();
+        assert_eq!(body, form.as_bytes());
+    }
+}
@@ -0,0 +1,234 @@
+use bigdecimal::Zero;
+use maud::{html, Markup, DOCTYPE};
+use store_lib::{
+    account::{User, UserBackend},
+    mail::Email,
+    order::Order,
+    password_reset::RESET_TOKEN_MINUTES,
+    verification::VERIFY_TOKEN_HOURS,
+};
+
+use crate::utils::display_decimal;
+
+/// Builds an email from one template, rendered as HTML with a plain-text
+/// alternative for clients that don't show HTML.
+fn email(to: &str, subject: &str, text: String, content: Markup) -> Email {
+    let html = html! {
+        (DOCTYPE)
+        html {
+            head {
+                meta charset="utf-8";
+                title { (subject) }
+            }
+            body style="font-family: sans-serif; color: #363636; max-width: 600px; margin: 0 auto;" {
+                h1 style="color: #485fc7;" { "Plantomics" }
+                (content)
+                p style="color: #7a7a7a; font-size: 12px; margin-top: 32px;" {
+                    "Thanks for shopping with Plantomics."
+                }
+            }
+        }
+    };
+
+    Email {
+        html: Some(html.into_string()),
+        ..Email::new(to, subject, text)
+    }
+}
+
+/// Sent on signup, with the link that verifies the address.
+pub fn welcome(user: &User, token: &str, site_url: &str) -> Email {
+    let verify_url = verify_url(token, site_url);
+    email(
+        &user.email,
+        "Welcome to Plantomics",
+        format!(
+            "Hi {},\n\n\
+             Thanks for creating an account. Please confirm your email address so you can place orders:\n\n\
+             {verify_url}\n\n\
+             The link expires in {VERIFY_TOKEN_HOURS} hours.\n",
+            user.username
+        ),
+        html! {
+            p { "Hi " (user.username) "," }
+            p { "Thanks for creating an account. Please confirm your email address so you can place orders." }
+            p { a href=(verify_url) { "Confirm your email address" } }
+            p { "The link expires in " (VERIFY_TOKEN_HOURS) " hours." }
+        },
+    )
+}
+
+/// A fresh verification link, for when the first one expired or went astray.
+pub fn verify_email(user: &User, token: &str, site_url: &str) -> Email {
+    let verify_url = verify_url(token, site_url);
+    email(
+        &user.email,
+        "Confirm your email address",
+        format!(
+            "Hi {},\n\n\
+             Confirm your email address by following this link:\n\n\
+             {verify_url}\n\n\
+             The link expires in {VERIFY_TOKEN_HOURS} hours.\n",
+            user.username
+        ),
+        html! {
+            p { "Hi " (user.username) "," }
+            p { a href=(verify_url) { "Confirm your email address" } }
+            p { "The link expires in " (VERIFY_TOKEN_HOURS) " hours." }
+        },
+    )
+}
+
+pub fn password_reset(user: &User, token: &str, site_url: &str) -> Email {
+    let reset_url = format!("{site_url}/reset-password?token={token}");
+    email(
+        &user.email,
+        "Reset your Plantomics password",
+        format!(
+            "Hi {},\n\n\
+             Someone asked to reset the password for your account. If it was you, choose a new password here:\n\n\
+             {reset_url}\n\n\
+             The link works once and expires in {RESET_TOKEN_MINUTES} minutes. If you didn't ask, you can ignore this email.\n",
+            user.username
+        ),
+        html! {
+            p { "Hi " (user.username) "," }
+            p { "Someone asked to reset the password for your account. If it was you, choose a new password below." }
+            p { a href=(reset_url) { "Reset your password" } }
+            p {
+                "The link works once and expires in " (RESET_TOKEN_MINUTES) " minutes. "
+                "If you didn't ask, you can ignore this email."
+            }
+        },
+    )
+}
+
+pub fn order_confirmation(to: &str, order: &Order, site_url: &str) -> Email {
+    email(
+        to,
+        &format!("Your order {}", short_id(order)),
+        format!(
+            "Thanks for your order!\n\n{}\nView it at {}\n",
+            order_text(order),
+            order_url(order, site_url)
+        ),
+        html! {
+            p { "Thanks for your order! We'll let you know when it ships." }
+            (order_table(order))
+            p { a href=(order_url(order, site_url)) { "View your order" } }
+        },
+    )
+}
+
+pub fn order_shipped(to: &str, order: &Order, site_url: &str) -> Email {
+    email(
+        to,
+        &format!("Your order {} has shipped", short_id(order)),
+        format!(
+            "Good news, your order is on its way.\n\n{}\nView it at {}\n",
+            order_text(order),
+            order_url(order, site_url)
+        ),
+        html! {
+            p { "Good news, your order is on its way." }
+            (order_table(order))
+            p { a href=(order_url(order, site_url)) { "View your order" } }
+        },
+    )
+}
+
+pub fn order_refunded(to: &str, order: &Order) -> Email {
+    email(
+        to,
+        &format!("Your order {} has been refunded", short_id(order)),
+        format!(
+            "We've refunded {} to your original payment method.\n\n{}",
+            display_decimal(&order.total),
+            order_text(order)
+        ),
+        html! {
+            p {
+                "We've refunded " b { (display_decimal(&order.total)) }
+                " to your original payment method."
+            }
+            (order_table(order))
+        },
+    )
+}
+
+/// The address of the customer who placed `order`, if they still have an
+/// account.
+pub fn order_recipient(users: &UserBackend, order: &Order) -> Option<String> {
+    let users = users.lock().expect("users available");
+    users
+        .find_by_id(&order.user_id)
+        .map(|user| user.email.clone())
+}
+
+fn verify_url(token: &str, site_url: &str) -> String {
+    format!("{site_url}/verify-email?token={token}")
+}
+
+fn short_id(order: &Order) -> String {
+    order.order_id.to_string()[..8].to_uppercase()
+}
+
+fn order_url(order: &Order, site_url: &str) -> String {
+    format!("{site_url}/orders/{}", order.order_id)
+}
+
+fn order_text(order: &Order) -> String {
+    let mut text = String::new();
+    for line in &order.lines {
+        text.push_str(&format!(
+            "{} ({}) x {}  {}\n",
+            line.name,
+            line.variant,
+            line.number,
+            display_decimal(&line.total())
+        ));
+    }
+    text.push_str(&format!(
+        "\nSubtotal  {}\nShipping  {}\n",
+        display_decimal(&order.subtotal),
+        display_decimal(&order.shipping),
+    ));
+    if !order.tax.is_zero() {
+        text.push_str(&format!("Tax       {}\n", display_decimal(&order.tax)));
+    }
+    text.push_str(&format!("Total     {}\n", display_decimal(&order.total)));
+    text
+}
+
+fn order_table(order: &Order) -> Markup {
+    html! {
+        table style="width: 100%; border-collapse: collapse;" {
+            @for line in &order.lines {
+                tr {
+                    td { (line.name) " (" (line.variant) ") \u{d7} " (line.number) }
+                    td style="text-align: right;" { (display_decimal(&line.total())) }
+                }
+            }
+            tr {
+                td style="border-top: 1px solid #dbdbdb;" { "Subtotal" }
+                td style="border-top: 1px solid #dbdbdb; text-align: right;" {
+                    (display_decimal(&order.subtotal))
+                }
+            }
+            tr {
+                td { "Shipping" }
+                td style="text-align: right;" { (display_decimal(&order.shipping)) }
+            }
+            @if !order.tax.is_zero() {
+                tr {
+                    td { "Tax" }
+                    td style="text-align: right;" { (display_decimal(&order.tax)) }
+                }
+            }
+            tr {
+                td { b { "Total" } }
+                td style="text-align: right;" { b { (display_decimal(&order.total)) } }
+            }
+        }
+    }
+}
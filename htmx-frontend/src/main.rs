@@ -1,110 +1,258 @@
 mod api;
 mod components;
+mod config;
+mod csrf;
+mod emails;
 mod pages;
+mod pending_login;
+mod rate_limit;
+mod seed;
+mod sessions;
 mod utils;
 
 use crate::pages::account::login;
-use crate::pages::checkout::checkout;
-use crate::pages::shopping::shopping;
+use crate::pages::checkout::{checkout, order, place_order};
+use crate::pages::product::{more_reviews, notify_when_restocked, product, submit_review};
+use crate::pages::shopping::{remove_all, remove_item, shopping};
 use crate::pages::store::{add_to_cart, rock_list, store};
-use axum::routing::{get, post, put};
+use crate::pages::wishlist;
+use axum::handler::Handler;
+use axum::middleware::{from_fn, from_fn_with_state};
+use axum::routing::{delete, get, post, put};
 use axum::Router;
 use axum_login::{AuthManagerLayerBuilder, AuthSession};
-use pages::account::{create_account, create_account_post, login_post, logout};
-use store_lib::account::UserBackend;
+use chrono::Utc;
+use clap::Parser;
+use config::{Args, Command, Config};
+use pages::account::{
+    create_account, create_account_post, forgot_password, forgot_password_post, login_post, logout,
+    resend_verification, reset_password, reset_password_post, verify_email,
+};
+use pages::{admin, mock_idp, oidc, settings, two_factor};
+use rate_limit::{limit_pages, RateLimiter};
+use sessions::SessionConfig;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use store_lib::account::{User, UserBackend};
 use store_lib::cart::CartBackend;
-use store_lib::store::{Inventory, InventoryBackend, Product};
+use store_lib::lockout::{LockoutPolicy, LoginAttempts};
+use store_lib::mail::{FileMailer, Mailer, SmtpMailer};
+use store_lib::mock_idp::MockIdp;
+use store_lib::oidc::{OidcClient, OidcConfig};
+use store_lib::order::OrderBackend;
+use store_lib::outbox::Outbox;
+use store_lib::restock::RestockAlerts;
+use store_lib::review::ReviewBackend;
+use store_lib::session_store::SqlSessionStore;
+use store_lib::store::InventoryBackend;
+use store_lib::verification::EmailVerifier;
+use store_lib::wishlist::WishlistBackend;
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{info, warn};
 
-const PORT: &'static str = "8080";
+const OUTBOX_INTERVAL: Duration = Duration::from_secs(5);
+const MOCK_IDP_PATH: &str = "/mock-idp";
+const MOCK_CLIENT_ID: &str = "plantomics";
+const MOCK_CLIENT_SECRET: &str = "mock-client-secret";
 
 pub type Auth = AuthSession<UserBackend>;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let port = option_env!("PORT")
-        .map(str::to_string)
-        .or_else(|| std::env::args().skip(1).next())
-        .unwrap_or(String::from(PORT));
+    let args = Args::parse();
+    if let Some(Command::CheckFixtures { files }) = &args.command {
+        return seed::check(files);
+    }
+    let config = match Config::load(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Configuration error: {e}");
+            std::process::exit(2);
+        }
+    };
 
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::INFO)
         .init();
 
     // Asset Service
-    let asset_service = ServeDir::new("assets");
+    let asset_service = ServeDir::new(&config.server.assets_dir);
     let user_backend = UserBackend::new();
     let cart_backend = CartBackend::new();
     let inventory_backend = InventoryBackend::new();
-
-    // Add testing user for testing
-    user_backend
-        .lock()
-        .expect("users available")
-        .add(store_lib::account::Signup {
-            email: "michael@example.com".to_string(),
-            password: "password".to_string(),
-            username: "michael".to_string(),
-        })?;
-
-    // Add Some Inventory
-    {
-        let mut products = inventory_backend
-            .products
-            .lock()
-            .expect("products available");
-
-        let mut inventory = inventory_backend
-            .inventory
-            .lock()
-            .expect("inventory available");
-
-        for listing in (0..10).map(|_| Product::random()) {
-            inventory.insert(listing.listing_id, Inventory::new(12, 3, 15));
-            products.insert(listing.listing_id, listing);
-        }
+    let order_backend = OrderBackend::with_pricing(config.shop.pricing());
+    let review_backend = ReviewBackend::new();
+    let wishlist_backend = WishlistBackend::new();
+    inventory_backend.on_restock(Arc::new(wishlist_backend.clone()));
+    let site_url = config.site_url();
+    let outbox = Outbox::new(mailer(&config.mail)?);
+    let restock_alerts = RestockAlerts::new(Arc::new(outbox.clone()), &site_url);
+    inventory_backend.on_restock(Arc::new(restock_alerts.clone()));
+    if config.features.staff_two_factor {
+        let mut users = user_backend.lock().expect("users available");
+        users.set_staff_two_factor_required(true);
     }
 
+    seed::load(&args.seed, &user_backend, &inventory_backend)?;
+
     // Auth service.
-    let session_store = axum_login::tower_sessions::MemoryStore::default();
-    let session_layer = axum_login::tower_sessions::SessionManagerLayer::new(session_store);
+    let session_config = SessionConfig::new(&config.session);
+    let session_store = sessions::session_store(&config.database.url).await?;
+    tokio::spawn(sessions::delete_expired(session_store.clone()));
+    let session_layer = session_config.layer(session_store.clone());
     let auth_layer = AuthManagerLayerBuilder::new(user_backend.clone(), session_layer).build();
+    let oidc = oidc_config(&config, &site_url)
+        .map(OidcClient::new)
+        .transpose()?
+        .map(Arc::new);
+    let mock_idp_routes = match config.features.mock_idp {
+        true => {
+            let issuer = format!("{site_url}{MOCK_IDP_PATH}");
+            let idp = MockIdp::new(&issuer, MOCK_CLIENT_ID, MOCK_CLIENT_SECRET);
+            Router::new().nest(MOCK_IDP_PATH, mock_idp::routes(idp))
+        }
+        false => Router::new(),
+    };
 
     // App State
     let state = AppState {
         user_backend,
         cart_backend,
         inventory_backend,
+        order_backend,
+        review_backend,
+        wishlist_backend,
+        restock_alerts,
+        outbox: outbox.clone(),
+        site_url,
+        email_verifier: EmailVerifier::new(&secret_key(&config)),
+        login_attempts: LoginAttempts::new(lockout_policy(&config)),
+        session_config,
+        session_store,
+        oidc,
     };
 
-    let api_routes = api::api_routes();
+    tokio::spawn(deliver_mail(outbox));
+
+    let auth_limiter = RateLimiter::new("auth", config.security.auth_rate_limit);
+    let cart_limiter = RateLimiter::new("cart", config.security.cart_rate_limit);
+    let auth_limit = from_fn_with_state(auth_limiter.clone(), limit_pages);
+    let cart_limit = from_fn_with_state(cart_limiter.clone(), limit_pages);
+    let api_routes = api::api_routes(&auth_limiter, &cart_limiter);
 
     let pages = Router::new()
         .route("/rock-list/:id", get(rock_list))
-        .route("/add-to-cart/:id", put(add_to_cart))
+        .route("/add-to-cart/:id", put(add_to_cart).layer(cart_limit))
         .route("/", get(store))
-        .route("/shopping-cart", get(shopping))
-        .route("/checkout", get(checkout))
+        .route("/products/:id", get(product))
+        .route(
+            "/products/:id/reviews",
+            get(more_reviews).post(submit_review),
+        )
+        .route("/shopping-cart", get(shopping).delete(remove_all))
+        .route("/shopping-cart/:id", delete(remove_item))
+        .route("/checkout", get(checkout).post(place_order))
+        .route("/products/:id/notify", post(notify_when_restocked))
+        .route("/orders/:id", get(order))
+        .route("/wishlist", get(wishlist::wishlist))
+        .route(
+            "/wishlist/:id",
+            post(wishlist::toggle).delete(wishlist::remove_item),
+        )
+        .route("/wishlist/:id/move", post(wishlist::move_to_cart))
         .route("/login", get(login))
-        .route("/login", post(login_post))
+        .route("/login", post(login_post).layer(auth_limit.clone()))
+        .route(
+            "/login/two-factor",
+            get(two_factor::login).post(two_factor::login_post.layer(auth_limit.clone())),
+        )
+        .route("/login/oidc", get(oidc::start))
+        .route(
+            "/login/oidc/callback",
+            get(oidc::callback).layer(auth_limit.clone()),
+        )
         .route("/logout", post(logout))
         .route("/signup", get(create_account))
-        .route("/signup", post(create_account_post))
+        .route(
+            "/signup",
+            post(create_account_post).layer(auth_limit.clone()),
+        )
+        .route(
+            "/forgot-password",
+            get(forgot_password).post(forgot_password_post.layer(auth_limit.clone())),
+        )
+        .route(
+            "/reset-password",
+            get(reset_password).post(reset_password_post.layer(auth_limit.clone())),
+        )
+        .route(
+            "/verify-email",
+            get(verify_email).post(resend_verification.layer(auth_limit)),
+        )
+        .route("/account", get(settings::settings))
+        .route("/account/username", post(settings::change_username))
+        .route("/account/email", post(settings::change_email))
+        .route("/account/password", post(settings::change_password))
+        .route("/account/delete", post(settings::delete_account))
+        .route("/account/sessions", get(settings::sessions))
+        .route(
+            "/account/sessions/revoke-others",
+            post(settings::revoke_other_sessions),
+        )
+        .route(
+            "/account/sessions/:key/revoke",
+            post(settings::revoke_session),
+        )
+        .route(
+            "/account/two-factor",
+            get(two_factor::setup).post(two_factor::confirm),
+        )
+        .route(
+            "/account/two-factor/disable",
+            post(settings::disable_two_factor),
+        )
+        .route(
+            "/account/two-factor/recovery-codes",
+            post(two_factor::recovery_codes),
+        )
         .route("/status", get(status))
+        .route("/admin/inventory", get(admin::inventory))
+        .route("/admin/inventory/:id", get(admin::stock_ledger))
+        .route("/admin/inventory/:id", post(admin::adjust_stock))
+        .route(
+            "/admin/inventory/:id/threshold",
+            post(admin::set_low_stock_threshold),
+        )
+        .route("/admin/orders", get(admin::orders))
+        .route("/admin/orders/:id/ship", post(admin::ship_order))
+        .route("/admin/orders/:id/refund", post(admin::refund_order))
+        .route(
+            "/admin/security",
+            get(admin::security).post(admin::set_staff_two_factor),
+        )
+        .route("/admin/reviews", get(admin::reviews))
+        .route("/admin/reviews/:id", post(admin::moderate_review))
+        .route_layer(from_fn(csrf::verify))
         .nest("/api", api_routes)
+        .merge(mock_idp_routes)
         .layer(TraceLayer::new_for_http())
+        .layer(from_fn(sessions::track))
         .layer(auth_layer)
         .nest_service("/assets", asset_service)
         .with_state(state);
 
-    info!("Server started on port {port}");
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}"))
-        .await
-        .unwrap();
+    let address = SocketAddr::new(config.server.bind, config.server.port);
+    info!("Server started on {address}");
+    let listener = tokio::net::TcpListener::bind(address).await?;
 
-    axum::serve(listener, pages).await.unwrap();
+    axum::serve(
+        listener,
+        pages.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
     Ok(())
 }
 
@@ -113,6 +261,118 @@ struct AppState {
     user_backend: UserBackend,
     cart_backend: CartBackend,
     inventory_backend: InventoryBackend,
+    order_backend: OrderBackend,
+    review_backend: ReviewBackend,
+    wishlist_backend: WishlistBackend,
+    restock_alerts: RestockAlerts,
+    outbox: Outbox,
+    /// Prefix for links in emails.
+    site_url: String,
+    email_verifier: EmailVerifier,
+    login_attempts: LoginAttempts,
+    session_config: SessionConfig,
+    session_store: SqlSessionStore,
+    /// The identity provider users can log in with, if there is one.
+    oidc: Option<Arc<OidcClient>>,
+}
+
+impl AppState {
+    /// Cleans up after a deleted account and logs it out everywhere. Orders
+    /// and reviews are kept but no longer point at it.
+    async fn forget_account(&self, auth: &mut Auth, user: &User) {
+        self.order_backend.anonymise(&user.id);
+        self.review_backend.anonymise(&user.id);
+        self.cart_backend.clear(&user.id);
+        self.wishlist_backend.clear(&user.id);
+        self.restock_alerts.unsubscribe_all(&user.email);
+        if let Err(e) = auth.logout().await {
+            warn!("Failed to end session for deleted account: {e}");
+        }
+        if let Err(e) = self.session_store.revoke_all(&user.id).await {
+            warn!("Failed to revoke sessions of deleted account: {e}");
+        }
+    }
+}
+
+/// Hands queued mail to the mailer in the background, off the async
+/// threads since SMTP delivery blocks.
+async fn deliver_mail(outbox: Outbox) {
+    let mut interval = tokio::time::interval(OUTBOX_INTERVAL);
+    loop {
+        interval.tick().await;
+        let outbox = outbox.clone();
+        let delivery = tokio::task::spawn_blocking(move || outbox.deliver_due(Utc::now()));
+        if let Err(e) = delivery.await {
+            warn!("Mail delivery stopped unexpectedly: {e}");
+        }
+    }
+}
+
+/// Without a configured key a random one is used, so links sent before a
+/// restart stop working.
+fn secret_key(config: &Config) -> Vec<u8> {
+    match &config.security.secret_key {
+        Some(key) => key.clone().into_bytes(),
+        None => {
+            warn!("No secret key configured, using a random key");
+            rand::random::<[u8; 32]>().to_vec()
+        }
+    }
+}
+
+fn lockout_policy(config: &Config) -> LockoutPolicy {
+    LockoutPolicy {
+        max_account_failures: config.security.login_max_failures,
+        max_ip_failures: config.security.login_max_ip_failures,
+        lockout: chrono::Duration::minutes(config.security.login_lockout_minutes),
+    }
+}
+
+/// The identity provider to log in with, if there is one. The mock one
+/// is served by the shop itself.
+fn oidc_config(config: &Config, site_url: &str) -> Option<OidcConfig> {
+    let redirect_url = format!("{site_url}/login/oidc/callback");
+    if config.features.mock_idp {
+        info!("Logging in through the mock identity provider at {MOCK_IDP_PATH}");
+        return Some(OidcConfig {
+            name: "Mock IdP".to_string(),
+            issuer_url: format!("{site_url}{MOCK_IDP_PATH}"),
+            client_id: MOCK_CLIENT_ID.to_string(),
+            client_secret: Some(MOCK_CLIENT_SECRET.to_string()),
+            redirect_url,
+        });
+    }
+
+    let oidc = &config.oidc;
+    let (Some(issuer_url), Some(client_id)) = (&oidc.issuer_url, &oidc.client_id) else {
+        return None;
+    };
+    info!("Logging in through {issuer_url}");
+    Some(OidcConfig {
+        name: oidc.provider_name.clone(),
+        issuer_url: issuer_url.clone(),
+        client_id: client_id.clone(),
+        client_secret: oidc.client_secret.clone(),
+        redirect_url,
+    })
+}
+
+fn mailer(mail: &config::MailConfig) -> Result<Arc<dyn Mailer>, Box<dyn std::error::Error>> {
+    let Some(host) = &mail.smtp_host else {
+        info!("No SMTP host set, writing mail to {}/", mail.dir.display());
+        return Ok(Arc::new(FileMailer::new(&mail.dir, &mail.from)?));
+    };
+
+    let port = mail.smtp_port;
+    let mailer = match &mail.smtp_username {
+        Some(username) => {
+            let password = mail.smtp_password.clone().unwrap_or_default();
+            SmtpMailer::starttls(host, port, username.clone(), password, &mail.from)?
+        }
+        None => SmtpMailer::new(host, port, &mail.from)?,
+    };
+    info!("Sending mail through {host}:{port}");
+    Ok(Arc::new(mailer))
 }
 
 async fn status() -> &'static str {
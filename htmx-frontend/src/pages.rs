@@ -1,4 +1,11 @@
 pub mod account;
+pub mod admin;
 pub mod checkout;
+pub mod mock_idp;
+pub mod oidc;
+pub mod product;
+pub mod settings;
 pub mod shopping;
 pub mod store;
+pub mod two_factor;
+pub mod wishlist;
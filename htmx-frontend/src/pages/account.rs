@@ -1,27 +1,96 @@
 use axum::{
-    extract::{Form, State},
+    extract::{ConnectInfo, Form, Query, State},
     http::StatusCode,
-    response::{IntoResponse, Redirect},
+    response::{IntoResponse, Redirect, Response},
 };
-use axum_login::AuthSession;
+use axum_login::{tower_sessions::Session, AuthSession};
+use chrono::Utc;
 use maud::{html, Markup};
-use store_lib::account::{Credentials, Signup, UserBackend};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use store_lib::{
+    account::{Credentials, Signup, SignupErrors, User, UserBackend, UserError},
+    password_reset::{ForgotPassword, ResetError, ResetPassword},
+    verification::VerifyEmail,
+};
 use tracing::{info, warn};
 
-use crate::{components::PageWrapper, AppState, Auth};
+use crate::{
+    components::{csrf_field, notification, Color, PageWrapper},
+    emails, pending_login, sessions, AppState, Auth,
+};
+
+/// Shown under new password fields, matching `account::check_password`.
+pub const PASSWORD_RULES: &str = "At least 8 characters, with a letter and a number or symbol.";
 
-pub async fn login(page: PageWrapper) -> Markup {
-    page.render(login_page(login_form()))
+pub async fn login(page: PageWrapper, State(s): State<AppState>) -> Markup {
+    let provider = s.oidc.as_ref().map(|oidc| oidc.name());
+    let form = login_form("", provider, page.csrf_token());
+    page.render(login_page(form))
 }
 
-pub async fn login_post(mut auth: Auth, Form(creds): Form<Credentials>) -> Redirect {
-    let Ok(Some(user)) = auth.authenticate(creds).await else {
-        return Redirect::to("/login");
+/// Logs in, or shows the form again saying why not. Failures count towards
+/// locking the account and the client's address. Accounts with 2FA go on to
+/// ask for a code.
+pub async fn login_post(
+    page: PageWrapper,
+    mut auth: Auth,
+    session: Session,
+    State(AppState {
+        login_attempts,
+        session_config,
+        oidc,
+        ..
+    }): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Form(creds): Form<Credentials>,
+) -> Response {
+    let email = creds.email.clone();
+    let now = Utc::now();
+    let failed = |status: StatusCode, message: String| async move {
+        let content = html! {
+            (notification(&message, Color::Danger, true).await)
+            (login_form(&email, oidc.as_ref().map(|oidc| oidc.name()), page.csrf_token()))
+        };
+        (status, page.render(login_page(content))).into_response()
     };
 
-    match auth.login(&user).await {
-        Ok(_) => Redirect::to("/"),
-        Err(_) => Redirect::to("/login"),
+    if let Err(locked) = login_attempts.check(&creds.email, addr.ip(), now) {
+        let message = format!(
+            "Too many failed logins. Try again in {} minutes.",
+            locked.minutes_left(now)
+        );
+        return failed(StatusCode::TOO_MANY_REQUESTS, message).await;
+    }
+
+    match auth.authenticate(creds.clone()).await {
+        Ok(Some(user)) if user.two_factor_enabled => {
+            match pending_login::start(&session, &user, creds.remember, now).await {
+                true => Redirect::to("/login/two-factor").into_response(),
+                false => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(user)) => {
+            login_attempts.record_success(&creds.email);
+            match auth.login(&user).await {
+                Ok(_) => {
+                    if creds.remember {
+                        sessions::remember(&session, session_config.lifetime);
+                    }
+                    Redirect::to("/").into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => {
+            login_attempts.record_failure(&creds.email, addr.ip(), now);
+            let message = "Invalid email or password.".to_string();
+            failed(StatusCode::UNAUTHORIZED, message).await
+        }
+        Err(e) => {
+            warn!("Login failed: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
     }
 }
 
@@ -39,16 +108,19 @@ pub fn login_page(content: Markup) -> Markup {
     }
 }
 
-fn login_form() -> Markup {
+/// The login form, with a button for the identity provider if there is
+/// one.
+fn login_form(email: &str, provider: Option<&str>, csrf_token: &str) -> Markup {
     html!(
         form method="post" action="/login" {
+            (csrf_field(csrf_token))
             h2.is-size-3 {
                 "Sign In"
             }
             .field  {
                 label.label for="email" { "Email" }
                 .control {
-                    input.input name="email" id="email" {}
+                    input.input name="email" id="email" type="email" value=(email) {}
                 }
             }
             .field {
@@ -58,72 +130,268 @@ fn login_form() -> Markup {
                 }
             }
 
-            button.button.is-link {
-                "Login"
+            .field {
+                .control {
+                    label.checkbox {
+                        input type="checkbox" name="remember" value="true";
+                        " Remember me"
+                    }
+                }
             }
+
+            .field.is-grouped.is-align-items-center {
+                .control {
+                    button.button.is-link {
+                        "Login"
+                    }
+                }
+                .control {
+                    a href="/forgot-password" { "Forgot password?" }
+                }
+            }
+        }
+        @if let Some(provider) = provider {
+            hr;
+            a.button.is-fullwidth href="/login/oidc" { "Log in with " (provider) }
         }
     )
 }
 
 pub async fn create_account(page: PageWrapper) -> Markup {
-    page.render(login_page(create_account_form()))
+    let form = create_account_form(None, &SignupErrors::default(), page.csrf_token());
+    page.render(login_page(form))
 }
 
-pub fn add_user(store: UserBackend, signup: Signup) -> Result<(), store_lib::account::UserError> {
+pub fn add_user(store: UserBackend, signup: Signup) -> Result<User, UserError> {
     let mut user_store = store.lock().expect("user store threading issue");
-    user_store.add(signup.clone())
+    user_store.add(signup)
 }
 
-#[axum_macros::debug_handler]
+/// Creates the account and logs in, or shows the form again with what was
+/// wrong next to each field.
 pub async fn create_account_post(
+    page: PageWrapper,
     State(s): State<AppState>,
     mut auth: AuthSession<UserBackend>,
     Form(signup): Form<Signup>,
 ) -> impl IntoResponse {
     info!("Creating account for: {}", signup.email);
-    let Ok(_) = add_user(s.user_backend, signup.clone()) else {
-        warn!("failed to store new user");
-        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-    };
-
-    info!("Authenticating user");
-    let auth_result = match auth.authenticate(signup.into()).await {
-        Ok(Some(ref u)) => auth.login(u).await,
-        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    let user = match add_user(s.user_backend.clone(), signup.clone()) {
+        Ok(user) => user,
+        Err(UserError::InvalidSignup(errors)) => {
+            let form = create_account_form(Some(&signup), &errors, page.csrf_token());
+            return (StatusCode::BAD_REQUEST, page.render(login_page(form))).into_response();
+        }
+        Err(e) => {
+            warn!("failed to store new user: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
     };
 
-    if auth_result.is_err() {
+    let token = s.email_verifier.token(&user, Utc::now());
+    s.outbox
+        .enqueue(emails::welcome(&user, &token, &s.site_url));
+    if auth.login(&user).await.is_err() {
         return StatusCode::INTERNAL_SERVER_ERROR.into_response();
     }
 
     Redirect::to("/").into_response()
 }
 
-fn create_account_form() -> Markup {
-    html!(
-        form method="post" action="/signup" {
-            h2.is-size-3 {
-                "Create Account"
+/// Follows the link from a verification email.
+pub async fn verify_email(
+    page: PageWrapper,
+    auth: Auth,
+    State(s): State<AppState>,
+    Query(VerifyEmail { token }): Query<VerifyEmail>,
+) -> Markup {
+    let verified = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.verify_email(&s.email_verifier, &token, Utc::now())
+    };
+
+    let content = match verified {
+        Ok(_) => html! {
+            (notification("Your email address is confirmed.", Color::Success, true).await)
+            a href="/" { "Continue Shopping" }
+        },
+        Err(e) => html! {
+            (notification(&format!("We couldn't confirm your email address: {e}."), Color::Danger, true).await)
+            @if auth.user.as_ref().is_some_and(|u| !u.verified) {
+                (resend_verification_form(page.csrf_token()))
             }
-            .field  {
-                label.label for="username" { "Username" }
-                .control {
-                    input.input name="username" id="username" {}
-                }
+        },
+    };
+    page.render(verify_page(content))
+}
+
+/// Mails a new verification link to the logged in user.
+pub async fn resend_verification(
+    page: PageWrapper,
+    auth: Auth,
+    State(s): State<AppState>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+    if user.verified {
+        return Redirect::to("/").into_response();
+    }
+
+    let token = s.email_verifier.token(&user, Utc::now());
+    s.outbox
+        .enqueue(emails::verify_email(&user, &token, &s.site_url));
+    let message = format!("We've sent a new confirmation link to {}.", user.email);
+    page.render(verify_page(notification(&message, Color::Info, true).await))
+        .into_response()
+}
+
+pub fn resend_verification_form(csrf_token: &str) -> Markup {
+    html! {
+        form method="post" action="/verify-email" {
+            (csrf_field(csrf_token))
+            button.button.is-small type="submit" { "Send a new confirmation link" }
+        }
+    }
+}
+
+fn verify_page(content: Markup) -> Markup {
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Confirm Your Email" }
+                (content)
             }
+        }
+    }
+}
+
+pub async fn forgot_password(page: PageWrapper) -> Markup {
+    let form = forgot_password_form(page.csrf_token());
+    page.render(login_page(form))
+}
+
+/// Mails a reset link if the address has an account. The reply is the same
+/// either way so the form can't be used to find out who has an account.
+pub async fn forgot_password_post(
+    page: PageWrapper,
+    State(s): State<AppState>,
+    Form(ForgotPassword { email }): Form<ForgotPassword>,
+) -> Markup {
+    let reset = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.request_password_reset(&email, Utc::now())
+    };
+    if let Some((user, token)) = reset {
+        s.outbox
+            .enqueue(emails::password_reset(&user, &token, &s.site_url));
+    }
+
+    let message = format!("If {email} has an account, we've sent it a link to reset the password.");
+    page.render(login_page(notification(&message, Color::Info, true).await))
+}
+
+#[derive(Deserialize)]
+pub struct ResetLink {
+    token: String,
+}
+
+pub async fn reset_password(
+    page: PageWrapper,
+    Query(ResetLink { token }): Query<ResetLink>,
+) -> Markup {
+    let form = reset_password_form(&token, page.csrf_token());
+    page.render(login_page(form))
+}
+
+pub async fn reset_password_post(
+    page: PageWrapper,
+    State(s): State<AppState>,
+    Form(reset): Form<ResetPassword>,
+) -> Response {
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.reset_password(&reset.token, &reset.password, Utc::now())
+    };
+
+    let content = match result {
+        Ok(_) => html! {
+            (notification("Your password has been changed. Please log in with the new one.", Color::Success, true).await)
+            a href="/login" { "Log in" }
+        },
+        Err(e) => {
+            let message = format!("Your password was not changed: {e}.");
+            // The link still works if only the password was refused.
+            let retry = match e {
+                ResetError::User(_) => reset_password_form(&reset.token, page.csrf_token()),
+                ResetError::Invalid | ResetError::Expired => html! {
+                    a href="/forgot-password" { "Request a new link" }
+                },
+            };
+            let content = html! {
+                (notification(&message, Color::Danger, true).await)
+                (retry)
+            };
+            return (StatusCode::BAD_REQUEST, page.render(login_page(content))).into_response();
+        }
+    };
+    page.render(login_page(content)).into_response()
+}
+
+fn forgot_password_form(csrf_token: &str) -> Markup {
+    html!(
+        form method="post" action="/forgot-password" {
+            (csrf_field(csrf_token))
+            h2.is-size-3 { "Forgot Password" }
+            p.mb-4 { "Enter your email and we'll send you a link to choose a new password." }
             .field {
                 label.label for="email" { "Email" }
                 .control {
-                    input.input name="email" id="email" type="email" {}
+                    input.input name="email" id="email" type="email" required {}
                 }
             }
+            button.button.is-link { "Send Reset Link" }
+        }
+    )
+}
+
+fn reset_password_form(token: &str, csrf_token: &str) -> Markup {
+    html!(
+        form method="post" action="/reset-password" {
+            (csrf_field(csrf_token))
+            h2.is-size-3 { "Choose a New Password" }
+            input type="hidden" name="token" value=(token);
             .field {
-                label.label for="password" { "Password" }
+                label.label for="password" { "New Password" }
                 .control {
-                    input.input name="password" id="password" type="password" {}
+                    input.input name="password" id="password" type="password" required {}
                 }
+                p.help { (PASSWORD_RULES) }
+            }
+            button.button.is-link { "Change Password" }
+        }
+    )
+}
+
+fn create_account_form(signup: Option<&Signup>, errors: &SignupErrors, csrf_token: &str) -> Markup {
+    let value = |field: fn(&Signup) -> &String| signup.map(|s| field(s).as_str()).unwrap_or("");
+
+    html!(
+        form method="post" action="/signup" {
+            (csrf_field(csrf_token))
+            h2.is-size-3 {
+                "Create Account"
             }
+            (signup_field("username", "Username", "text", value(|s| &s.username), &errors.username, None))
+            (signup_field("email", "Email", "email", value(|s| &s.email), &errors.email, None))
+            (signup_field(
+                "password",
+                "Password",
+                "password",
+                "",
+                &errors.password,
+                Some(PASSWORD_RULES),
+            ))
             button.button.is-link {
                 "Create"
             }
@@ -131,6 +399,37 @@ fn create_account_form() -> Markup {
     )
 }
 
+fn signup_field(
+    name: &str,
+    label: &str,
+    kind: &str,
+    value: &str,
+    error: &Option<String>,
+    help: Option<&str>,
+) -> Markup {
+    html! {
+        .field {
+            label.label for=(name) { (label) }
+            .control {
+                input.input.is-danger[error.is_some()] name=(name) id=(name) type=(kind) value=(value) required;
+            }
+            @if let Some(error) = error {
+                p.help.is-danger { (capitalise(error)) "." }
+            } @else if let Some(help) = help {
+                p.help { (help) }
+            }
+        }
+    }
+}
+
+fn capitalise(message: &str) -> String {
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
 pub async fn logout(mut auth: Auth) -> impl IntoResponse {
     match auth.logout().await {
         Ok(_) => Redirect::to("/login").into_response(),
@@ -0,0 +1,621 @@
+use axum::{
+    extract::{Form, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use maud::{html, Markup};
+use store_lib::{
+    account::UserBackend,
+    mail::Email,
+    order::{Order, OrderError, OrderStatus},
+    review::{ModerateReview, Review, ReviewStatus},
+    store::{
+        AdjustStock, AdjustmentReason, InventoryBackend, LowStockThreshold, StockStatus, Variant,
+    },
+    two_factor::StaffTwoFactorPolicy,
+};
+use uuid::Uuid;
+
+use crate::{
+    components::{csrf_field, notification, rating::stars, Color, PageWrapper},
+    emails,
+    utils::display_decimal,
+    AppState, Auth,
+};
+
+enum Denied {
+    LoggedOut,
+    NotAdmin,
+    NeedsTwoFactor,
+}
+
+impl IntoResponse for Denied {
+    fn into_response(self) -> Response {
+        match self {
+            Denied::LoggedOut => Redirect::to("/login").into_response(),
+            Denied::NotAdmin => StatusCode::FORBIDDEN.into_response(),
+            Denied::NeedsTwoFactor => Redirect::to("/account/two-factor").into_response(),
+        }
+    }
+}
+
+fn require_admin(auth: &Auth) -> Result<Uuid, Denied> {
+    match &auth.user {
+        None => Err(Denied::LoggedOut),
+        Some(user) if !user.is_admin() => Err(Denied::NotAdmin),
+        Some(user) => {
+            let user_store = auth.backend.lock().expect("user store threading issue");
+            match user_store.needs_two_factor(user) {
+                true => Err(Denied::NeedsTwoFactor),
+                false => Ok(user.id),
+            }
+        }
+    }
+}
+
+pub async fn inventory(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        inventory_backend, ..
+    }): State<AppState>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    let content = inventory_page(&inventory_backend, None, page.csrf_token()).await;
+    page.render(content).into_response()
+}
+
+pub async fn adjust_stock(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        inventory_backend, ..
+    }): State<AppState>,
+    Path(variant_id): Path<Uuid>,
+    Form(adjustment): Form<AdjustStock>,
+) -> Response {
+    let admin = match require_admin(&auth) {
+        Ok(admin) => admin,
+        Err(denied) => return denied.into_response(),
+    };
+
+    match inventory_backend.adjust(variant_id, admin, adjustment) {
+        Ok(_) => Redirect::to("/admin/inventory").into_response(),
+        Err(e) => {
+            let message = format!("Stock was not adjusted: {e}");
+            let content =
+                inventory_page(&inventory_backend, Some(&message), page.csrf_token()).await;
+            page.render(content).into_response()
+        }
+    }
+}
+
+pub async fn set_low_stock_threshold(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        inventory_backend, ..
+    }): State<AppState>,
+    Path(variant_id): Path<Uuid>,
+    Form(threshold): Form<LowStockThreshold>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    match inventory_backend.set_low_stock_threshold(&variant_id, threshold) {
+        Ok(_) => Redirect::to("/admin/inventory").into_response(),
+        Err(e) => {
+            let message = format!("Threshold was not changed: {e}");
+            let content =
+                inventory_page(&inventory_backend, Some(&message), page.csrf_token()).await;
+            page.render(content).into_response()
+        }
+    }
+}
+
+pub async fn stock_ledger(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        user_backend,
+        ..
+    }): State<AppState>,
+    Path(variant_id): Path<Uuid>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    let Some((product, variant)) = inventory_backend.variant(&variant_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let ledger = inventory_backend.ledger(&variant_id);
+    let users = user_backend.lock().expect("users available");
+
+    page.render(html! {
+        .section {
+            .container {
+                a href="/admin/inventory" { "← Inventory" }
+                h2.title.is-3 { "Stock History: " (product.name) }
+                p.subtitle.is-6 { (variant.label()) " \u{b7} " (variant.sku) }
+                p.block {
+                    "Reconstructed free stock: "
+                    b { (inventory_backend.reconstruct_free(&variant_id)) }
+                }
+                table.table.is-fullwidth.is-striped {
+                    thead {
+                        tr {
+                            th { "When" }
+                            th { "Who" }
+                            th { "Reason" }
+                            th.has-text-right { "Change" }
+                        }
+                    }
+                    tbody {
+                        @for entry in &ledger {
+                            tr {
+                                td { (entry.created_at.format("%Y-%m-%d %H:%M:%S")) }
+                                td {
+                                    @match entry.user_id.and_then(|id| users.find_by_id(&id)) {
+                                        Some(user) => (user.email),
+                                        None => "System",
+                                    }
+                                }
+                                td { (entry.reason) }
+                                td.has-text-right { (format!("{:+}", entry.delta)) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    })
+    .into_response()
+}
+
+pub async fn reviews(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        review_backend,
+        ..
+    }): State<AppState>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    let content = reviews_page(&inventory_backend, &review_backend.all(), page.csrf_token());
+    page.render(content).into_response()
+}
+
+pub async fn moderate_review(
+    auth: Auth,
+    State(AppState { review_backend, .. }): State<AppState>,
+    Path(review_id): Path<Uuid>,
+    Form(ModerateReview { status }): Form<ModerateReview>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    match review_backend.moderate(&review_id, status) {
+        Ok(_) => Redirect::to("/admin/reviews").into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub async fn security(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState { user_backend, .. }): State<AppState>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    let required = {
+        let user_store = user_backend.lock().expect("users available");
+        user_store.staff_two_factor_required()
+    };
+    let content = security_page(required, page.csrf_token());
+    page.render(content).into_response()
+}
+
+/// Requires staff to use 2FA, or stops requiring it. Staff without it are
+/// sent to set it up before they can manage the store.
+pub async fn set_staff_two_factor(
+    auth: Auth,
+    State(AppState { user_backend, .. }): State<AppState>,
+    Form(StaffTwoFactorPolicy { required }): Form<StaffTwoFactorPolicy>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    {
+        let mut user_store = user_backend.lock().expect("users available");
+        user_store.set_staff_two_factor_required(required);
+    }
+    Redirect::to("/admin/security").into_response()
+}
+
+fn security_page(required: bool, csrf_token: &str) -> Markup {
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Security" }
+                .box {
+                    h3.title.is-5 {
+                        "Two-Factor Authentication for Staff "
+                        @if required {
+                            span.tag.is-success.is-light { "Required" }
+                        } @else {
+                            span.tag.is-light { "Optional" }
+                        }
+                    }
+                    p.mb-3 {
+                        "When required, staff without two-factor authentication have to set it up before they can manage the store, and can't turn it off."
+                    }
+                    form method="post" action="/admin/security" {
+                        (csrf_field(csrf_token))
+                        input type="hidden" name="required" value=(!required);
+                        @if required {
+                            button.button type="submit" { "Make Optional" }
+                        } @else {
+                            button.button.is-link type="submit" { "Require" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub async fn orders(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        order_backend,
+        user_backend,
+        ..
+    }): State<AppState>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    let content = orders_page(&order_backend.all(), &user_backend, None, page.csrf_token()).await;
+    page.render(content).into_response()
+}
+
+/// Ships an order and lets the customer know.
+pub async fn ship_order(
+    page: PageWrapper,
+    auth: Auth,
+    State(s): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    let shipped = s.order_backend.ship(&order_id, &s.inventory_backend);
+    order_updated(
+        page,
+        &s,
+        shipped.map(|order| {
+            emails::order_recipient(&s.user_backend, &order)
+                .map(|to| emails::order_shipped(&to, &order, &s.site_url))
+        }),
+    )
+    .await
+}
+
+/// Refunds an order and lets the customer know.
+pub async fn refund_order(
+    page: PageWrapper,
+    auth: Auth,
+    State(s): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> Response {
+    if let Err(denied) = require_admin(&auth) {
+        return denied.into_response();
+    }
+
+    let refunded = s.order_backend.refund(&order_id);
+    order_updated(
+        page,
+        &s,
+        refunded.map(|order| {
+            emails::order_recipient(&s.user_backend, &order)
+                .map(|to| emails::order_refunded(&to, &order))
+        }),
+    )
+    .await
+}
+
+async fn order_updated(
+    page: PageWrapper,
+    s: &AppState,
+    result: Result<Option<Email>, OrderError>,
+) -> Response {
+    match result {
+        Ok(email) => {
+            if let Some(email) = email {
+                s.outbox.enqueue(email);
+            }
+            Redirect::to("/admin/orders").into_response()
+        }
+        Err(OrderError::NotFound) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            let message = format!("Order was not updated: {e}");
+            let orders = s.order_backend.all();
+            let content =
+                orders_page(&orders, &s.user_backend, Some(&message), page.csrf_token()).await;
+            page.render(content).into_response()
+        }
+    }
+}
+
+async fn orders_page(
+    orders: &[Order],
+    user_backend: &UserBackend,
+    error: Option<&str>,
+    csrf_token: &str,
+) -> Markup {
+    let customers: Vec<Option<String>> = {
+        let user_store = user_backend.lock().expect("users available");
+        orders
+            .iter()
+            .map(|order| {
+                user_store
+                    .find_by_id(&order.user_id)
+                    .map(|u| u.email.clone())
+            })
+            .collect()
+    };
+
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Orders" }
+                @if let Some(error) = error {
+                    (notification(error, Color::Danger, true).await)
+                }
+                @if orders.is_empty() {
+                    p { "No orders have been placed yet." }
+                } @else {
+                    table.table.is-fullwidth.is-striped {
+                        thead {
+                            tr {
+                                th { "Order" }
+                                th { "Customer" }
+                                th { "Placed" }
+                                th.has-text-right { "Items" }
+                                th.has-text-right { "Total" }
+                                th { "Status" }
+                                th {}
+                            }
+                        }
+                        tbody {
+                            @for (order, customer) in orders.iter().zip(&customers) {
+                                tr {
+                                    td { code { (order.order_id.to_string()[..8]) } }
+                                    td {
+                                        @match customer {
+                                            Some(email) => (email),
+                                            None => span.has-text-grey { "Deleted account" },
+                                        }
+                                    }
+                                    td { (order.placed_at.format("%Y-%m-%d %H:%M")) }
+                                    td.has-text-right {
+                                        (order.lines.iter().map(|line| line.number).sum::<usize>())
+                                    }
+                                    td.has-text-right { (display_decimal(&order.total)) }
+                                    td { span.tag { (order.status) } }
+                                    td.has-text-right {
+                                        .buttons.is-right {
+                                            @if order.status == OrderStatus::Placed {
+                                                form method="post" action=(format!("/admin/orders/{}/ship", order.order_id)) {
+                                                    (csrf_field(csrf_token))
+                                                    button.button.is-small.is-link type="submit" { "Ship" }
+                                                }
+                                            }
+                                            @if order.status != OrderStatus::Refunded {
+                                                form.ml-1 method="post" action=(format!("/admin/orders/{}/refund", order.order_id)) {
+                                                    (csrf_field(csrf_token))
+                                                    button.button.is-small type="submit" { "Refund" }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn reviews_page(
+    inventory_backend: &InventoryBackend,
+    reviews: &[Review],
+    csrf_token: &str,
+) -> Markup {
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Reviews" }
+                @if reviews.is_empty() {
+                    p { "No reviews have been written yet." }
+                }
+                @for review in reviews {
+                    .box {
+                        .level.is-mobile {
+                            .level-left {
+                                div {
+                                    p.has-text-grey.is-size-7 {
+                                        @if let Some(product) = inventory_backend.product(&review.listing_id) {
+                                            (product.name)
+                                        }
+                                        " \u{b7} " (review.author)
+                                        " \u{b7} " (review.created_at.format("%Y-%m-%d"))
+                                    }
+                                    p { (stars(review.rating as f32)) " " b { (review.title) } }
+                                    p { (review.body) }
+                                }
+                            }
+                            .level-right {
+                                span.tag.mr-2 { (review.status) }
+                                @for status in [ReviewStatus::Approved, ReviewStatus::Hidden] {
+                                    @if review.status != status {
+                                        form method="post" action=(format!("/admin/reviews/{}", review.review_id)) {
+                                            (csrf_field(csrf_token))
+                                            input type="hidden" name="status" value=(format!("{:?}", status));
+                                            button.button.is-small.ml-1 type="submit" {
+                                                @match status {
+                                                    ReviewStatus::Approved => "Approve",
+                                                    _ => "Hide",
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn inventory_page(
+    inventory_backend: &InventoryBackend,
+    error: Option<&str>,
+    csrf_token: &str,
+) -> Markup {
+    let levels = inventory_backend.stock_levels();
+
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Inventory" }
+                @if let Some(error) = error {
+                    (notification(error, Color::Danger, true).await)
+                }
+                (low_stock_alerts(inventory_backend))
+                table.table.is-fullwidth.is-striped {
+                    thead {
+                        tr {
+                            th { "Product" }
+                            th { "Variant" }
+                            th { "SKU" }
+                            th.has-text-right { "Free" }
+                            th.has-text-right { "Ordered" }
+                            th.has-text-right { "Sent" }
+                            th { "Low Stock At" }
+                            th { "Adjust" }
+                        }
+                    }
+                    tbody {
+                        @for (product, variant, stock) in &levels {
+                            tr {
+                                td {
+                                    a href=(ledger_url(variant)) { (product.name) }
+                                }
+                                td { (variant.label()) }
+                                td { code { (variant.sku) } }
+                                td.has-text-right { (stock.free) }
+                                td.has-text-right { (stock.ordered) }
+                                td.has-text-right { (stock.sent) }
+                                td { (threshold_form(variant, stock.low_stock_threshold, csrf_token)) }
+                                td { (adjust_form(variant, csrf_token)) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn ledger_url(variant: &Variant) -> String {
+    format!("/admin/inventory/{}", variant.variant_id)
+}
+
+fn low_stock_alerts(inventory_backend: &InventoryBackend) -> Markup {
+    let alerts = inventory_backend.low_stock_alerts();
+    html! {
+        @if !alerts.is_empty() {
+            .message.is-warning {
+                .message-header { "Low Stock" }
+                .message-body {
+                    ul {
+                        @for (product, variant, stock) in &alerts {
+                            li {
+                                a href=(ledger_url(variant)) {
+                                    (product.name)
+                                    @if product.has_options() {
+                                        " (" (variant.label()) ")"
+                                    }
+                                }
+                                @match stock.status() {
+                                    StockStatus::OutOfStock => " is out of stock",
+                                    _ => (format!(" has only {} left", stock.free)),
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn threshold_form(variant: &Variant, threshold: usize, csrf_token: &str) -> Markup {
+    html! {
+        form.field.has-addons method="post" action=(format!("{}/threshold", ledger_url(variant))) {
+            (csrf_field(csrf_token))
+            .control {
+                input.input.is-small name="threshold" type="number" min="0" value=(threshold) required;
+            }
+            .control {
+                button.button.is-small type="submit" { "Set" }
+            }
+        }
+    }
+}
+
+fn adjust_form(variant: &Variant, csrf_token: &str) -> Markup {
+    html! {
+        form.field.has-addons method="post" action=(ledger_url(variant)) {
+            (csrf_field(csrf_token))
+            .control {
+                input.input.is-small name="delta" type="number" value="0" required;
+            }
+            .control {
+                .select.is-small {
+                    select name="reason" {
+                        @for reason in AdjustmentReason::ALL {
+                            option value=(format!("{:?}", reason)) { (reason) }
+                        }
+                    }
+                }
+            }
+            .control {
+                button.button.is-small.is-link type="submit" { "Apply" }
+            }
+        }
+    }
+}
@@ -1,26 +1,119 @@
-use std::collections::HashMap;
-
-use bigdecimal::BigDecimal;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use bigdecimal::Zero;
 use maud::{html, Markup};
 use store_lib::{
+    account::User,
     cart::{Cart, CartItem},
-    store::Product,
+    order::{Order, Pricing},
 };
 use uuid::Uuid;
 
 use crate::{
-    components::{text_field, PageWrapper},
+    components::{csrf_field, notification, text_field, Color, PageWrapper},
+    emails,
+    pages::account::resend_verification_form,
     utils::display_decimal,
+    AppState, Auth,
 };
 
-pub async fn checkout(page: PageWrapper) -> Markup {
-    page.render(page_body().await)
+pub async fn checkout(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        cart_backend,
+        order_backend,
+        ..
+    }): State<AppState>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let cart = cart_backend.cart(&user.id);
+    let pricing = order_backend.pricing();
+    let content = page_body(&user, &cart, &pricing, None, page.csrf_token()).await;
+    page.render(content).into_response()
+}
+
+/// Places the order and shows its confirmation, or the checkout again with
+/// the reason it could not be placed.
+pub async fn place_order(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        cart_backend,
+        inventory_backend,
+        order_backend,
+        outbox,
+        site_url,
+        ..
+    }): State<AppState>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let cart = cart_backend.cart(&user.id);
+    match order_backend.place(&user, &cart, &inventory_backend) {
+        Ok(order) => {
+            cart_backend.clear(&user.id);
+            outbox.enqueue(emails::order_confirmation(&user.email, &order, &site_url));
+            Redirect::to(&format!("/orders/{}", order.order_id)).into_response()
+        }
+        Err(e) => {
+            let message = format!("Your order was not placed: {e}");
+            let pricing = order_backend.pricing();
+            let content =
+                page_body(&user, &cart, &pricing, Some(&message), page.csrf_token()).await;
+            page.render(content).into_response()
+        }
+    }
 }
 
-async fn page_body() -> Markup {
+pub async fn order(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState { order_backend, .. }): State<AppState>,
+    Path(order_id): Path<Uuid>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    match order_backend.order(&order_id) {
+        Some(order) if order.user_id == user.id => {
+            page.render(order_confirmation(&order)).into_response()
+        }
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn page_body(
+    user: &User,
+    cart: &Cart,
+    pricing: &Pricing,
+    error: Option<&str>,
+    csrf_token: &str,
+) -> Markup {
     html! {
         .section {
             .container {
+                @if let Some(error) = error {
+                    (notification(error, Color::Danger, true).await)
+                }
+                @if !user.verified {
+                    .notification.is-warning.is-light {
+                        p.mb-2 {
+                            "Please confirm your email address before placing an order. "
+                            "We sent a link to " b { (user.email) } "."
+                        }
+                        (resend_verification_form(csrf_token))
+                    }
+                }
                 .columns {
                     .column.is-two-thirds {
                         h2.title.is-3 { "Shipping" }
@@ -30,7 +123,7 @@ async fn page_body() -> Markup {
                         .box { (payment_form().await) }
                     }
                     .column {
-                        (order_summary().await)
+                        (order_summary(cart, pricing, user.verified, csrf_token).await)
                     }
                 }
             }
@@ -38,76 +131,121 @@ async fn page_body() -> Markup {
     }
 }
 
-async fn review_item(item: &Product) -> Markup {
+async fn review_item(item: &CartItem) -> Markup {
     html! {
         .columns.is-mobile {
             .column {
                 .image.is-48x48.is-flex.is-align-items-center {
-                    img src=(format!("/assets/images/{}", item.image));
+                    img src=(format!("/assets/images/{}", item.listing.image));
                 }
             }
             .column.is-half {
-                (item.name)
+                (item.listing.name)
+                @if item.listing.has_options() {
+                    br;
+                    span.is-size-7 { (item.variant.label()) }
+                }
+                @if item.number > 1 {
+                    " \u{d7} " (item.number)
+                }
             }
             .column.has-text-right{
-                (display_decimal(&item.price))
+                (display_decimal(&item.total()))
             }
         }
     }
 }
 
-pub async fn order_summary() -> Markup {
-    let cart = Cart {
-        items: HashMap::from([
-            (
-                Uuid::new_v4(),
-                CartItem {
-                    listing: Product::random(),
-                    number: 3,
-                },
-            ),
-            (
-                Uuid::new_v4(),
-                CartItem {
-                    listing: Product::random(),
-                    number: 2,
-                },
-            ),
-            (
-                Uuid::new_v4(),
-                CartItem {
-                    listing: Product::random(),
-                    number: 1,
-                },
-            ),
-        ]),
-    };
-
+pub async fn order_summary(
+    cart: &Cart,
+    pricing: &Pricing,
+    can_order: bool,
+    csrf_token: &str,
+) -> Markup {
+    let subtotal = cart.subtotal();
+    let tax = pricing.tax(&subtotal);
     html! {
         h2.is-size-4 { "Order Summary" }
             .box {
                 @for item in cart.items.values() {
-                    (review_item(&item.listing).await)
+                    (review_item(item).await)
                 }
                 hr;
                 .level.is-mobile {
                     .level-left {
-                        (format!("Subtotal ({} Items)", cart.items.len()))
+                        (format!("Subtotal ({} Items)", cart.count()))
                     }
-                    .level-right {(display_decimal(&cart.subtotal()))}
+                    .level-right {(display_decimal(&subtotal))}
                 }
                 .level.is-mobile {
                     .level-left {"Fedex Standard Shipping"}
-                    .level-right {"$6.99"}
+                    .level-right {(display_decimal(&pricing.shipping))}
+                }
+                @if !tax.is_zero() {
+                    .level.is-mobile {
+                        .level-left {"Tax"}
+                        .level-right {(display_decimal(&tax))}
+                    }
                 }
                 hr;
                 .level.is-mobile {
                     .level-left {"Total Cost:"}
                     .level-right {
-                        (display_decimal(&(cart.subtotal() + BigDecimal::new(799.into(), 2))))
+                        (display_decimal(&pricing.total(&subtotal)))
+                    }
+                }
+                form method="post" action="/checkout" {
+                    (csrf_field(csrf_token))
+                    button.button.is-link.is-fullwidth type="submit" disabled[cart.items.is_empty() || !can_order] {
+                        "Place Order"
                     }
                 }
-                button.button.is-link.is-fullwidth { "Place Order" }
+        }
+    }
+}
+
+fn order_confirmation(order: &Order) -> Markup {
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Thank you for your order!" }
+                p.subtitle.is-6 {
+                    "Order " code { (order.order_id) }
+                    " placed " (order.placed_at.format("%B %-d, %Y"))
+                    span.tag.ml-2 { (order.status) }
+                }
+                .box {
+                    @for line in &order.lines {
+                        .level.is-mobile {
+                            .level-left {
+                                a href=(format!("/products/{}", line.listing_id)) { (line.name) }
+                                span.ml-2.is-size-7 { (line.variant) " \u{d7} " (line.number) }
+                            }
+                            .level-right { (display_decimal(&line.total())) }
+                        }
+                    }
+                    hr;
+                    .level.is-mobile {
+                        .level-left { "Subtotal" }
+                        .level-right { (display_decimal(&order.subtotal)) }
+                    }
+                    .level.is-mobile {
+                        .level-left { "Shipping" }
+                        .level-right { (display_decimal(&order.shipping)) }
+                    }
+                    @if !order.tax.is_zero() {
+                        .level.is-mobile {
+                            .level-left { "Tax" }
+                            .level-right { (display_decimal(&order.tax)) }
+                        }
+                    }
+                    .level.is-mobile {
+                        .level-left { b { "Total" } }
+                        .level-right { b { (display_decimal(&order.total)) } }
+                    }
+                }
+                a href="/" { "Continue Shopping" }
+            }
         }
     }
 }
@@ -0,0 +1,151 @@
+use axum::{
+    extract::{Form, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use maud::{html, Markup, DOCTYPE};
+use serde::{Deserialize, Serialize};
+use store_lib::mock_idp::{AuthorizeRequest, MockIdp, MockIdpError, TokenRequest};
+
+/// The mock identity provider, for trying external logins without a real
+/// one. Nest it where its issuer URL points.
+pub fn routes<S>(idp: MockIdp) -> Router<S> {
+    Router::new()
+        .route("/.well-known/openid-configuration", get(metadata))
+        .route("/jwks", get(jwks))
+        .route("/authorize", get(authorize).post(authorize_post))
+        .route("/token", post(token))
+        .with_state(idp)
+}
+
+#[derive(Deserialize)]
+struct MockLogin {
+    #[serde(flatten)]
+    request: AuthorizeRequest,
+    email: String,
+    /// Whether to say the address is verified, to try both cases.
+    email_verified: Option<String>,
+}
+
+#[derive(Serialize)]
+struct OAuthError {
+    error: &'static str,
+    error_description: String,
+}
+
+async fn metadata(State(idp): State<MockIdp>) -> Response {
+    match idp.metadata() {
+        Ok(metadata) => Json(metadata).into_response(),
+        Err(e) => error(e),
+    }
+}
+
+async fn jwks(State(idp): State<MockIdp>) -> Response {
+    Json(idp.jwks()).into_response()
+}
+
+/// Asks who to log in as.
+async fn authorize(
+    State(idp): State<MockIdp>,
+    Query(request): Query<AuthorizeRequest>,
+) -> Response {
+    if let Err(e) = idp.check(&request) {
+        return error(e);
+    }
+    login_page(&request).into_response()
+}
+
+async fn authorize_post(State(idp): State<MockIdp>, Form(login): Form<MockLogin>) -> Response {
+    let verified = login.email_verified.is_some();
+    match idp.authorize(&login.request, &login.email, verified, Utc::now()) {
+        Ok(redirect) => Redirect::to(&redirect).into_response(),
+        Err(e) => error(e),
+    }
+}
+
+async fn token(
+    State(idp): State<MockIdp>,
+    headers: HeaderMap,
+    Form(request): Form<TokenRequest>,
+) -> Response {
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    match idp.token(request, authorization, Utc::now()) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => error(e),
+    }
+}
+
+/// An OAuth error response.
+fn error(e: MockIdpError) -> Response {
+    let status = match e {
+        MockIdpError::InvalidClient => StatusCode::UNAUTHORIZED,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    let body = OAuthError {
+        error: e.code(),
+        error_description: e.to_string(),
+    };
+    (status, Json(body)).into_response()
+}
+
+fn login_page(request: &AuthorizeRequest) -> Markup {
+    let hidden = [
+        ("response_type", Some(&request.response_type)),
+        ("client_id", Some(&request.client_id)),
+        ("redirect_uri", Some(&request.redirect_uri)),
+        ("state", Some(&request.state)),
+        ("nonce", request.nonce.as_ref()),
+        ("code_challenge", request.code_challenge.as_ref()),
+        (
+            "code_challenge_method",
+            request.code_challenge_method.as_ref(),
+        ),
+    ];
+    html! {
+        (DOCTYPE)
+        html {
+            head {
+                meta charset="utf-8";
+                title { "Mock Identity Provider" }
+                link rel="stylesheet" href="/assets/stylesheets/global.css";
+            }
+            body {
+                .container.my-6 {
+                    .columns {
+                        .column.is-one-third {
+                            .box {
+                                form method="post" action="authorize" {
+                                    h2.is-size-3 { "Mock Identity Provider" }
+                                    p.mb-4 { "For development only. Log in as any address." }
+                                    @for (name, value) in hidden {
+                                        @if let Some(value) = value {
+                                            input type="hidden" name=(name) value=(value);
+                                        }
+                                    }
+                                    .field {
+                                        label.label for="email" { "Email" }
+                                        .control {
+                                            input.input name="email" id="email" type="email" required autofocus;
+                                        }
+                                    }
+                                    .field {
+                                        label.checkbox {
+                                            input type="checkbox" name="email_verified" value="true" checked;
+                                            " Email is verified"
+                                        }
+                                    }
+                                    button.button.is-link type="submit" { "Log In" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
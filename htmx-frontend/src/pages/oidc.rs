@@ -0,0 +1,141 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_login::tower_sessions::Session;
+use chrono::Utc;
+use maud::html;
+use serde::Deserialize;
+use store_lib::{account::UserError, oidc::OidcLogin};
+use tracing::{info, warn};
+
+use crate::{
+    components::{notification, Color, PageWrapper},
+    pages::account::login_page,
+    pending_login, AppState, Auth,
+};
+
+const SESSION_KEY: &str = "oidc_login";
+
+/// What the identity provider sends back, a code or why there isn't one.
+#[derive(Deserialize)]
+pub struct Callback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Sends the user to the identity provider to log in, or to link it to
+/// their account if they already are logged in.
+pub async fn start(page: PageWrapper, session: Session, State(s): State<AppState>) -> Response {
+    let Some(oidc) = s.oidc else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let (url, login) = match oidc.start().await {
+        Ok(started) => started,
+        Err(e) => {
+            warn!("Failed to start external login: {e}");
+            let message = format!("{} can't be reached right now.", oidc.name());
+            return failed(page, StatusCode::BAD_GATEWAY, &message).await;
+        }
+    };
+    match session.insert(SESSION_KEY, login).await {
+        Ok(()) => Redirect::to(&url).into_response(),
+        Err(e) => {
+            warn!("Failed to store external login: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Finishes an external login. The first time it links the login to an
+/// account, found by a verified address or made new. Accounts with 2FA go
+/// on to ask for a code.
+pub async fn callback(
+    page: PageWrapper,
+    mut auth: Auth,
+    session: Session,
+    State(s): State<AppState>,
+    Query(callback): Query<Callback>,
+) -> Response {
+    let Some(oidc) = s.oidc.clone() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let login = session
+        .remove::<OidcLogin>(SESSION_KEY)
+        .await
+        .ok()
+        .flatten();
+    let (Some(login), Some(code), Some(state)) = (login, callback.code, callback.state) else {
+        if let Some(error) = callback.error {
+            info!("External login ended with {error}");
+        }
+        let message = "The login was cancelled or took too long. Please try again.";
+        return failed(page, StatusCode::BAD_REQUEST, message).await;
+    };
+
+    let profile = match oidc.finish(login, &state, &code).await {
+        Ok(profile) => profile,
+        Err(e) => {
+            warn!("External login failed: {e}");
+            let message = format!("We couldn't log you in with {}.", oidc.name());
+            return failed(page, StatusCode::BAD_REQUEST, &message).await;
+        }
+    };
+
+    if let Some(user) = &auth.user {
+        let result = {
+            let mut user_store = s.user_backend.lock().expect("user store threading issue");
+            user_store.link_identity(&user.id, profile.identity)
+        };
+        return match result {
+            Ok(_) => Redirect::to("/account").into_response(),
+            Err(e) => {
+                let message = format!("{} wasn't linked: {e}.", oidc.name());
+                failed(page, StatusCode::CONFLICT, &message).await
+            }
+        };
+    }
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.login_external(&profile)
+    };
+    match result {
+        Ok(user) if user.two_factor_enabled => {
+            match pending_login::start(&session, &user, false, Utc::now()).await {
+                true => Redirect::to("/login/two-factor").into_response(),
+                false => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(user) => match auth.login(&user).await {
+            Ok(_) => Redirect::to("/").into_response(),
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        },
+        Err(UserError::EmailTaken) => {
+            let message = format!(
+                "An account already uses that email address. Log in with your password, then link {} in your account settings.",
+                oidc.name()
+            );
+            failed(page, StatusCode::CONFLICT, &message).await
+        }
+        Err(UserError::InvalidEmail) => {
+            let message = format!("{} didn't share a usable email address.", oidc.name());
+            failed(page, StatusCode::BAD_REQUEST, &message).await
+        }
+        Err(e) => {
+            warn!("External login failed: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn failed(page: PageWrapper, status: StatusCode, message: &str) -> Response {
+    let content = html! {
+        (notification(message, Color::Danger, true).await)
+        a href="/login" { "Back to login" }
+    };
+    (status, page.render(login_page(content))).into_response()
+}
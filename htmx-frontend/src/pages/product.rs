@@ -0,0 +1,366 @@
+use axum::{
+    extract::{Form, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use maud::{html, Markup};
+use store_lib::{
+    restock::RestockSubscription,
+    review::{NewReview, RatingSummary, ReviewError, ReviewPage, ReviewQuery},
+    store::{Product, ProductSpecs, StockStatus, Variant},
+};
+use uuid::Uuid;
+
+use crate::{
+    components::{
+        notification,
+        rating::{rating_summary, stars},
+        Color, PageWrapper,
+    },
+    pages::{store::stock_badge, wishlist::heart},
+    utils::display_decimal,
+    AppState, Auth,
+};
+
+pub async fn product(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        order_backend,
+        review_backend,
+        wishlist_backend,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+) -> Response {
+    let Some(product) = inventory_backend.product(&listing_id) else {
+        return (StatusCode::NOT_FOUND, page.render(not_found())).into_response();
+    };
+
+    let status = inventory_backend.stock_status(&listing_id);
+    let variants = product
+        .variants
+        .iter()
+        .map(|v| (v, inventory_backend.variant_status(&v.variant_id)))
+        .collect::<Vec<_>>();
+    let reviews = review_backend.reviews(&listing_id, &ReviewQuery::default());
+    let can_review = auth.user.as_ref().is_some_and(|user| {
+        order_backend.has_purchased(&user.id, &listing_id)
+            && !review_backend.has_reviewed(&user.id, &listing_id)
+    });
+
+    let wishlisted = auth
+        .user
+        .as_ref()
+        .map(|user| wishlist_backend.contains(&user.id, &listing_id));
+
+    page.render(html! {
+        (product_page(&product, status, &variants, reviews.summary, wishlisted))
+        @if !status.available() {
+            (restock_form(&product, auth.user.as_ref().map(|user| user.email.as_str())))
+        }
+        (reviews_section(&product, &reviews, can_review).await)
+    })
+    .into_response()
+}
+
+/// Signs an address up for a one-off email when the product is restocked.
+pub async fn notify_when_restocked(
+    State(AppState {
+        inventory_backend,
+        restock_alerts,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+    Form(RestockSubscription { email }): Form<RestockSubscription>,
+) -> Markup {
+    if inventory_backend.product(&listing_id).is_none() {
+        return notification("That item is no longer available", Color::Danger, true).await;
+    }
+
+    match restock_alerts.subscribe(listing_id, &email) {
+        Ok(()) => {
+            notification(
+                "We'll email you as soon as it's back in stock",
+                Color::Success,
+                true,
+            )
+            .await
+        }
+        Err(e) => notification(&format!("Sorry, {e}"), Color::Warning, true).await,
+    }
+}
+
+/// The next page of approved reviews, for the "More Reviews" button.
+pub async fn more_reviews(
+    State(AppState { review_backend, .. }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+    Query(query): Query<ReviewQuery>,
+) -> Markup {
+    review_items(&listing_id, &review_backend.reviews(&listing_id, &query))
+}
+
+/// Submits the review form, swapping it for a thank-you note or for the form
+/// again with the problem shown.
+pub async fn submit_review(
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        order_backend,
+        review_backend,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+    Form(review): Form<NewReview>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    if inventory_backend.product(&listing_id).is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    match review_backend.submit(&user, listing_id, &order_backend, review) {
+        Ok(_) => html! {
+            .notification.is-success.is-light {
+                "Thanks for your review! It will appear once it has been approved."
+            }
+        }
+        .into_response(),
+        Err(
+            e @ (ReviewError::InvalidRating | ReviewError::InvalidTitle | ReviewError::BodyTooLong),
+        ) => review_form(&listing_id, Some(&e.to_string()))
+            .await
+            .into_response(),
+        Err(e) => notification(&format!("Sorry, {e}"), Color::Warning, true)
+            .await
+            .into_response(),
+    }
+}
+
+fn product_page(
+    product: &Product,
+    status: StockStatus,
+    variants: &[(&Variant, StockStatus)],
+    rating: RatingSummary,
+    wishlisted: Option<bool>,
+) -> Markup {
+    html! {
+        .section {
+            .container {
+                a href="/" { "← Back to the store" }
+                .columns.mt-4 {
+                    .column.is-half {
+                        figure.image {
+                            img src=(format!("/assets/images/{}", product.image)) alt=(product.name);
+                        }
+                    }
+                    .column {
+                        p.has-text-grey { (product.category) }
+                        h2.title.is-2 { (product.name) }
+                        p.block { a href="#reviews" { (rating_summary(rating)) } }
+                        p.subtitle.is-4 {
+                            @if product.has_options() { "From " }
+                            (display_decimal(&product.price))
+                        }
+                        p.block { (stock_badge(status)) }
+                        .content { p { (product.description) } }
+                        .tags {
+                            @for tag in &product.tags {
+                                span.tag { (tag) }
+                            }
+                        }
+                        @if product.has_options() {
+                            (variant_select(variants))
+                        }
+                        button.button.is-link
+                            disabled[!status.available()]
+                            hx-put=(format!("/add-to-cart/{}", product.listing_id))
+                            hx-include="[name='variant_id']"
+                            hx-target="#notifications"
+                            hx-swap="afterbegin" {
+                            "Add to Cart"
+                        }
+                        (heart(&product.listing_id, wishlisted))
+                        h3.title.is-5.mt-5 { "Details" }
+                        (spec_table(&product.specs))
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn variant_select(variants: &[(&Variant, StockStatus)]) -> Markup {
+    let first_available = variants.iter().position(|(_, status)| status.available());
+
+    html! {
+        .field {
+            label.label for="variant_id" { "Options" }
+            .control {
+                .select {
+                    select name="variant_id" id="variant_id" {
+                        @for (i, (variant, status)) in variants.iter().enumerate() {
+                            option
+                                value=(variant.variant_id)
+                                disabled[!status.available()]
+                                selected[Some(i) == first_available] {
+                                (variant.label()) " \u{2013} " (display_decimal(&variant.price))
+                                @match status {
+                                    StockStatus::InStock => {},
+                                    StockStatus::LowStock(n) => (format!(" (only {n} left)")),
+                                    StockStatus::OutOfStock => " (out of stock)",
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn spec_table(specs: &ProductSpecs) -> Markup {
+    let rows = [
+        ("Light", specs.light.map(|l| l.to_string())),
+        ("Watering", specs.watering.map(|w| w.to_string())),
+        (
+            "Pet Safe",
+            specs
+                .pet_safe
+                .map(|safe| if safe { "Yes" } else { "No" }.to_string()),
+        ),
+        ("Size", specs.size.clone()),
+        ("Hardness", specs.hardness.map(|h| format!("{h} Mohs"))),
+    ];
+
+    html! {
+        table.table.is-fullwidth {
+            tbody {
+                @for (label, value) in rows {
+                    @if let Some(value) = value {
+                        tr {
+                            th { (label) }
+                            td { (value) }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn reviews_section(product: &Product, reviews: &ReviewPage, can_review: bool) -> Markup {
+    html! {
+        .section #reviews {
+            .container {
+                h3.title.is-4 { "Customer Reviews" }
+                p.block { (rating_summary(reviews.summary)) }
+                @if can_review {
+                    (review_form(&product.listing_id, None).await)
+                }
+                @if reviews.total == 0 {
+                    p.has-text-grey { "Be the first to review " (product.name) "." }
+                }
+                (review_items(&product.listing_id, reviews))
+            }
+        }
+    }
+}
+
+fn review_items(listing_id: &Uuid, reviews: &ReviewPage) -> Markup {
+    html! {
+        @for review in &reviews.reviews {
+            article.box {
+                p { (stars(review.rating as f32)) " " b { (review.title) } }
+                p.has-text-grey.is-size-7 {
+                    (review.author) " \u{b7} " (review.created_at.format("%B %-d, %Y"))
+                }
+                @if !review.body.is_empty() {
+                    p.mt-2 { (review.body) }
+                }
+            }
+        }
+        @if let Some(next_page) = reviews.next_page {
+            button.button.is-fullwidth
+                hx-get=(format!("/products/{listing_id}/reviews?page={next_page}"))
+                hx-swap="outerHTML" {
+                "More Reviews"
+            }
+        }
+    }
+}
+
+async fn review_form(listing_id: &Uuid, error: Option<&str>) -> Markup {
+    html! {
+        form.box #review-form
+            hx-post=(format!("/products/{listing_id}/reviews"))
+            hx-swap="outerHTML" {
+            h4.title.is-5 { "Write a Review" }
+            @if let Some(error) = error {
+                (notification(error, Color::Danger, true).await)
+            }
+            .field {
+                label.label for="rating" { "Rating" }
+                .control {
+                    .select {
+                        select name="rating" id="rating" {
+                            @for rating in (1..=5).rev() {
+                                option value=(rating) { (format!("{rating} stars")) }
+                            }
+                        }
+                    }
+                }
+            }
+            .field {
+                label.label for="title" { "Title" }
+                .control {
+                    input.input name="title" id="title" type="text" maxlength="120" required;
+                }
+            }
+            .field {
+                label.label for="body" { "Review" }
+                .control {
+                    textarea.textarea name="body" id="body" maxlength="5000" {}
+                }
+            }
+            button.button.is-link type="submit" { "Submit Review" }
+        }
+    }
+}
+
+fn restock_form(product: &Product, email: Option<&str>) -> Markup {
+    html! {
+        .section.pt-0 {
+            .container {
+                .box {
+                    h4.title.is-5 { "Out of stock" }
+                    p.block { "Leave your email and we'll let you know when " (product.name) " is back." }
+                    form.field.has-addons
+                        hx-post=(format!("/products/{}/notify", product.listing_id))
+                        hx-target="#notifications"
+                        hx-swap="afterbegin" {
+                        .control.is-expanded {
+                            input.input name="email" type="email" placeholder="you@example.com" value=[email] required;
+                        }
+                        .control {
+                            button.button.is-link type="submit" { "Notify Me" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn not_found() -> Markup {
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Product not found" }
+                a href="/" { "Continue Shopping" }
+            }
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use axum::{
+    extract::{Form, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_login::tower_sessions::Session;
+use chrono::Utc;
+use maud::{html, Markup};
+use store_lib::{
+    account::{ChangeEmail, ChangePassword, ChangeUsername, DeleteAccount, User, UserError},
+    session_store::SessionInfo,
+    two_factor::ConfirmPassword,
+};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    components::{csrf_field, notification, Color, PageWrapper},
+    emails,
+    pages::account::{resend_verification_form, PASSWORD_RULES},
+    sessions, AppState, Auth,
+};
+
+pub async fn settings(page: PageWrapper, auth: Auth, State(s): State<AppState>) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let content = settings_page(&user, external_login(&s, &user), None, page.csrf_token()).await;
+    page.render(content).into_response()
+}
+
+/// The identity provider's name and whether the user has linked it, if
+/// there is one.
+fn external_login(s: &AppState, user: &User) -> Option<(String, bool)> {
+    let oidc = s.oidc.as_ref()?;
+    let user_store = s.user_backend.lock().expect("user store threading issue");
+    let linked = !user_store.identities(&user.id).is_empty();
+    Some((oidc.name().to_string(), linked))
+}
+
+pub async fn change_username(
+    page: PageWrapper,
+    auth: Auth,
+    State(s): State<AppState>,
+    Form(ChangeUsername { username }): Form<ChangeUsername>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.change_username(&user.id, &username)
+    };
+    if let Ok(renamed) = &result {
+        s.review_backend
+            .rename_author(&renamed.id, &renamed.username);
+    }
+    updated(page, &s, &user, result, "Your username has been changed.").await
+}
+
+/// Moves the account to a new address and mails it a verification link.
+pub async fn change_email(
+    page: PageWrapper,
+    auth: Auth,
+    State(s): State<AppState>,
+    Form(ChangeEmail { email }): Form<ChangeEmail>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.change_email(&user.id, &email)
+    };
+    let mut message = String::new();
+    if let Ok(ref updated) = result {
+        if !updated.verified {
+            let token = s.email_verifier.token(updated, Utc::now());
+            s.outbox
+                .enqueue(emails::verify_email(updated, &token, &s.site_url));
+        }
+        message = format!(
+            "Your email has been changed. We've sent a confirmation link to {}.",
+            updated.email
+        );
+    }
+    updated(page, &s, &user, result, &message).await
+}
+
+/// Changes the password and logs in again, since the change ends every
+/// session that used the old one.
+pub async fn change_password(
+    page: PageWrapper,
+    mut auth: Auth,
+    State(s): State<AppState>,
+    Form(change): Form<ChangePassword>,
+) -> Response {
+    let Some(user) = auth.user.clone() else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.change_password(&user.id, &change.current_password, &change.new_password)
+    };
+    if let Ok(ref updated) = result {
+        if let Err(e) = auth.login(updated).await {
+            warn!("Failed to renew session after password change: {e}");
+            return Redirect::to("/login").into_response();
+        }
+    }
+    updated(page, &s, &user, result, "Your password has been changed.").await
+}
+
+/// Deletes the account after checking the password. Orders and reviews
+/// are kept but no longer point at the account.
+pub async fn delete_account(
+    page: PageWrapper,
+    mut auth: Auth,
+    State(s): State<AppState>,
+    Form(DeleteAccount { password }): Form<DeleteAccount>,
+) -> Response {
+    let Some(user) = auth.user.clone() else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.delete(&user.id, &password)
+    };
+    match result {
+        Ok(_) => {
+            s.forget_account(&mut auth, &user).await;
+            Redirect::to("/").into_response()
+        }
+        Err(e) => updated(page, &s, &user, Err(e), "").await,
+    }
+}
+
+pub async fn disable_two_factor(
+    page: PageWrapper,
+    auth: Auth,
+    State(s): State<AppState>,
+    Form(ConfirmPassword { password }): Form<ConfirmPassword>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.disable_two_factor(&user.id, &password)
+    };
+    let message = "Two-factor authentication is off.";
+    updated(page, &s, &user, result, message).await
+}
+
+/// Shows the settings again with the outcome of a change.
+async fn updated(
+    page: PageWrapper,
+    s: &AppState,
+    user: &User,
+    result: Result<User, UserError>,
+    success: &str,
+) -> Response {
+    match result {
+        Ok(updated) => {
+            let content = settings_page(
+                &updated,
+                external_login(s, &updated),
+                Some((success, Color::Success)),
+                page.csrf_token(),
+            )
+            .await;
+            page.render(content).into_response()
+        }
+        Err(UserError::PasswordHashingFailed(e)) => {
+            warn!("Account update failed: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            let message = format!("Nothing was changed: {e}.");
+            let external = external_login(s, user);
+            let message = Some((message.as_str(), Color::Danger));
+            let content = settings_page(user, external, message, page.csrf_token()).await;
+            (StatusCode::BAD_REQUEST, page.render(content)).into_response()
+        }
+    }
+}
+
+async fn settings_page(
+    user: &User,
+    external_login: Option<(String, bool)>,
+    message: Option<(&str, Color)>,
+    csrf_token: &str,
+) -> Markup {
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Account Settings" }
+                @if let Some((message, color)) = message {
+                    (notification(message, color, true).await)
+                }
+                .columns {
+                    .column.is-half {
+                        .box {
+                            form method="post" action="/account/username" {
+                                (csrf_field(csrf_token))
+                                h3.title.is-5 { "Username" }
+                                .field.has-addons {
+                                    .control.is-expanded {
+                                        input.input name="username" value=(user.username) required;
+                                    }
+                                    .control {
+                                        button.button.is-link type="submit" { "Save" }
+                                    }
+                                }
+                            }
+                        }
+                        .box {
+                            form method="post" action="/account/email" {
+                                (csrf_field(csrf_token))
+                                h3.title.is-5 {
+                                    "Email "
+                                    @if user.verified {
+                                        span.tag.is-success.is-light { "Verified" }
+                                    } @else {
+                                        span.tag.is-warning.is-light { "Unverified" }
+                                    }
+                                }
+                                .field.has-addons {
+                                    .control.is-expanded {
+                                        input.input name="email" type="email" value=(user.email) required;
+                                    }
+                                    .control {
+                                        button.button.is-link type="submit" { "Save" }
+                                    }
+                                }
+                                p.help { "You'll need to confirm a new address before placing orders." }
+                            }
+                            @if !user.verified {
+                                .mt-3 { (resend_verification_form(csrf_token)) }
+                            }
+                        }
+                        .box {
+                            form method="post" action="/account/password" {
+                                (csrf_field(csrf_token))
+                                h3.title.is-5 { "Password" }
+                                .field {
+                                    label.label for="current_password" { "Current Password" }
+                                    .control {
+                                        input.input name="current_password" id="current_password" type="password" required;
+                                    }
+                                }
+                                .field {
+                                    label.label for="new_password" { "New Password" }
+                                    .control {
+                                        input.input name="new_password" id="new_password" type="password" required;
+                                    }
+                                    p.help { (PASSWORD_RULES) }
+                                }
+                                button.button.is-link type="submit" { "Change Password" }
+                            }
+                        }
+                        (two_factor_box(user, csrf_token))
+                        @if let Some((provider, linked)) = external_login {
+                            .box {
+                                h3.title.is-5 {
+                                    (provider) " "
+                                    @if linked {
+                                        span.tag.is-success.is-light { "Linked" }
+                                    } @else {
+                                        span.tag.is-light { "Not linked" }
+                                    }
+                                }
+                                @if linked {
+                                    p { "You can log in with " (provider) " as well as your password." }
+                                } @else {
+                                    p.mb-3 { "Link " (provider) " to log in with it as well as your password." }
+                                    a.button.is-link href="/login/oidc" { "Link " (provider) }
+                                }
+                            }
+                        }
+                        .box {
+                            h3.title.is-5 { "Sessions" }
+                            p.mb-3 { "See where you're logged in and log out other devices." }
+                            a.button.is-link href="/account/sessions" { "Manage Sessions" }
+                        }
+                        .box {
+                            form method="post" action="/account/delete"
+                                onsubmit="return confirm('Delete your account? This can\'t be undone.')" {
+                                (csrf_field(csrf_token))
+                                h3.title.is-5.has-text-danger { "Delete Account" }
+                                p.mb-3 {
+                                    "Your orders are kept for our records but will no longer be linked to you."
+                                }
+                                .field.has-addons {
+                                    .control.is-expanded {
+                                        input.input name="password" type="password" placeholder="Password" required;
+                                    }
+                                    .control {
+                                        button.button.is-danger type="submit" { "Delete" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn two_factor_box(user: &User, csrf_token: &str) -> Markup {
+    html! {
+        .box {
+            h3.title.is-5 {
+                "Two-Factor Authentication "
+                @if user.two_factor_enabled {
+                    span.tag.is-success.is-light { "On" }
+                } @else {
+                    span.tag.is-light { "Off" }
+                }
+            }
+            @if let Some(left) = user.recovery_codes_left() {
+                p.mb-3 { "You have " (left) " unused recovery codes." }
+                form.mb-3 method="post" action="/account/two-factor/recovery-codes" {
+                    (csrf_field(csrf_token))
+                    .field.has-addons {
+                        .control.is-expanded {
+                            input.input name="password" type="password" placeholder="Password" required;
+                        }
+                        .control {
+                            button.button.is-link type="submit" { "New Recovery Codes" }
+                        }
+                    }
+                }
+                form method="post" action="/account/two-factor/disable" {
+                    (csrf_field(csrf_token))
+                    .field.has-addons {
+                        .control.is-expanded {
+                            input.input name="password" type="password" placeholder="Password" required;
+                        }
+                        .control {
+                            button.button.is-danger type="submit" { "Turn Off" }
+                        }
+                    }
+                }
+            } @else {
+                p.mb-3 { "Ask for a code from an authenticator app as well as your password when logging in." }
+                a.button.is-link href="/account/two-factor" { "Set Up" }
+            }
+        }
+    }
+}
+
+/// Lists where the user is logged in.
+pub async fn sessions(
+    page: PageWrapper,
+    auth: Auth,
+    session: Session,
+    State(s): State<AppState>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+    sessions_page(page, &user, &session, &s, None).await
+}
+
+pub async fn revoke_session(
+    page: PageWrapper,
+    mut auth: Auth,
+    session: Session,
+    State(s): State<AppState>,
+    Path(key): Path<Uuid>,
+) -> Response {
+    let Some(user) = auth.user.clone() else {
+        return Redirect::to("/login").into_response();
+    };
+    if sessions::current_key(&session).await == Some(key) {
+        if let Err(e) = auth.logout().await {
+            warn!("Failed to end session: {e}");
+        }
+        return Redirect::to("/login").into_response();
+    }
+
+    let message = match s.session_store.revoke(&user.id, &key).await {
+        Ok(true) => ("That session has been logged out.", Color::Success),
+        Ok(false) => ("That session has already ended.", Color::Info),
+        Err(e) => {
+            warn!("Failed to revoke session: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    sessions_page(page, &user, &session, &s, Some(message)).await
+}
+
+pub async fn revoke_other_sessions(
+    page: PageWrapper,
+    auth: Auth,
+    session: Session,
+    State(s): State<AppState>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+    let Some(current) = sessions::current_key(&session).await else {
+        return Redirect::to("/account/sessions").into_response();
+    };
+
+    match s.session_store.revoke_others(&user.id, &current).await {
+        Ok(revoked) => {
+            let message = format!("Logged out {revoked} other sessions.");
+            sessions_page(page, &user, &session, &s, Some((&message, Color::Success))).await
+        }
+        Err(e) => {
+            warn!("Failed to revoke sessions: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn sessions_page(
+    page: PageWrapper,
+    user: &User,
+    session: &Session,
+    s: &AppState,
+    message: Option<(&str, Color)>,
+) -> Response {
+    let active = match s.session_store.user_sessions(&user.id).await {
+        Ok(active) => active,
+        Err(e) => {
+            warn!("Failed to list sessions: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let current = sessions::current_key(session).await;
+
+    let csrf_token = page.csrf_token();
+    let content = html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Sessions" }
+                @if let Some((message, color)) = message {
+                    (notification(message, color, true).await)
+                }
+                .box {
+                    table.table.is-fullwidth {
+                        thead {
+                            tr {
+                                th { "Device" }
+                                th { "IP Address" }
+                                th { "Logged In" }
+                                th { "Last Active" }
+                                th {}
+                            }
+                        }
+                        tbody {
+                            @for info in &active {
+                                (session_row(info, current == Some(info.key), csrf_token))
+                            }
+                        }
+                    }
+                    @if active.len() > 1 {
+                        form method="post" action="/account/sessions/revoke-others" {
+                            (csrf_field(csrf_token))
+                            button.button.is-danger type="submit" { "Log Out Other Sessions" }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    page.render(content).into_response()
+}
+
+fn session_row(info: &SessionInfo, current: bool, csrf_token: &str) -> Markup {
+    html! {
+        tr {
+            td {
+                @if info.user_agent.is_empty() { "Unknown" } @else { (info.user_agent) }
+                @if current {
+                    " " span.tag.is-info.is-light { "This session" }
+                }
+            }
+            td { (info.ip) }
+            td { (info.created.format("%Y-%m-%d %H:%M")) }
+            td { (info.last_seen.format("%Y-%m-%d %H:%M")) }
+            td {
+                form method="post" action=(format!("/account/sessions/{}/revoke", info.key)) {
+                    (csrf_field(csrf_token))
+                    button.button.is-small type="submit" {
+                        @if current { "Log Out" } @else { "Revoke" }
+                    }
+                }
+            }
+        }
+    }
+}
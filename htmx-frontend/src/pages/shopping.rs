@@ -1,28 +1,64 @@
-use std::collections::HashMap;
+use crate::{components::PageWrapper, utils::display_decimal, AppState, Auth};
 
-use crate::{components::PageWrapper, utils::display_decimal};
-
-use maud::{html, Markup};
-use store_lib::{
-    cart::{Cart, CartItem},
-    store::Product,
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
 };
+use maud::{html, Markup};
+use store_lib::cart::{Cart, CartItem};
 use uuid::Uuid;
 
-pub async fn shopping(page: PageWrapper) -> Markup {
-    page.render(order_page().await)
+pub async fn shopping(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState { cart_backend, .. }): State<AppState>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    page.render(order_page(&cart_backend.cart(&user.id)).await)
+        .into_response()
+}
+
+/// Removes one line and re-renders the cart in place.
+pub async fn remove_item(
+    auth: Auth,
+    State(AppState { cart_backend, .. }): State<AppState>,
+    Path(variant_id): Path<Uuid>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    order_page(&cart_backend.remove(user.id, &variant_id))
+        .await
+        .into_response()
+}
+
+pub async fn remove_all(
+    auth: Auth,
+    State(AppState { cart_backend, .. }): State<AppState>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    cart_backend.clear(&user.id);
+    order_page(&Cart::default()).await.into_response()
 }
 
-async fn order_page() -> Markup {
+async fn order_page(cart: &Cart) -> Markup {
     html! {
-        .section {
+        .section #cart-page {
             .container {
                 .columns {
                     .column.is-two-thirds {
-                        (cart().await)
+                        (cart_items(cart).await)
                     }
                     .column {
-                        (order_summary().await)
+                        (order_summary(cart).await)
                     }
                 }
             }
@@ -30,50 +66,34 @@ async fn order_page() -> Markup {
     }
 }
 
-async fn cart() -> Markup {
+async fn cart_items(cart: &Cart) -> Markup {
+    let mut items: Vec<&CartItem> = cart.items.values().collect();
+    items.sort_by(|a, b| a.listing.name.cmp(&b.listing.name));
+
     html! {
         .level {
             .level-left {
                 h2.title.is-3 { "Shopping Cart" }
             }
             .level-right {
-                button.button.is-warning.is-outlined { "Remove All"}
+                button.button.is-warning.is-outlined
+                    disabled[items.is_empty()]
+                    hx-delete="/shopping-cart"
+                    hx-target="#cart-page"
+                    hx-swap="outerHTML" { "Remove All"}
             }
         }
         a.is-link.is-outlined href="/" { "Continue Shopping" }
-        (shopping_cart_item(Product::random()).await)
-        (shopping_cart_item(Product::random()).await)
-        (shopping_cart_item(Product::random()).await)
+        @if items.is_empty() {
+            p.my-4 { "Your cart is empty." }
+        }
+        @for item in items {
+            (shopping_cart_item(item).await)
+        }
     }
 }
 
-pub async fn order_summary() -> Markup {
-    let cart = Cart {
-        items: HashMap::from([
-            (
-                Uuid::new_v4(),
-                CartItem {
-                    listing: Product::random(),
-                    number: 3,
-                },
-            ),
-            (
-                Uuid::new_v4(),
-                CartItem {
-                    listing: Product::random(),
-                    number: 2,
-                },
-            ),
-            (
-                Uuid::new_v4(),
-                CartItem {
-                    listing: Product::random(),
-                    number: 1,
-                },
-            ),
-        ]),
-    };
-
+pub async fn order_summary(cart: &Cart) -> Markup {
     html! {
         h2.is-size-4 { "Order Summary" }
             .box {
@@ -88,7 +108,7 @@ pub async fn order_summary() -> Markup {
                 hr;
                 .level.is-mobile.is-size-5 {
                     .level-left {
-                        (format!("Subtotal ( {} items)", cart.items.len()))
+                        (format!("Subtotal ( {} items)", cart.count()))
                     }
                     .level-right {
                         (display_decimal(&cart.subtotal()))
@@ -99,38 +119,40 @@ pub async fn order_summary() -> Markup {
     }
 }
 
-async fn shopping_cart_item(item: Product) -> Markup {
+async fn shopping_cart_item(item: &CartItem) -> Markup {
     html! {
         .box {
             .media {
                 .media-left {
                     .image.is-96x96.is-flex.is-align-items-center {
-                        img src=(format!("/assets/images/{}", item.image));
+                        img src=(format!("/assets/images/{}", item.listing.image));
                     }
                 }
                 .media-content.columns {
                     .column.is-third {
-                        b { (item.name) }
+                        b { (item.listing.name) }
+                        @if item.listing.has_options() {
+                            br;
+                            span.is-size-7 { (item.variant.label()) }
+                        }
                         br;
-                        (item.description)
+                        (item.listing.description)
                     }
                     .column {
                         b { "Quantity" }
                         br;
-                        .field.is-grouped {
-                            button.button {"-"}
-                            input.counter.input.shrink.has-text-centered value="1" type="text";
-                            button.button {"+"}
-                        }
+                        (item.number)
                     }
                     .column {
                         b { "Price" }
                         br;
-                        "$"(item.price)
+                        (display_decimal(&item.variant.price))
                     }
                 }
                 .media-right {
-                    a {
+                    a hx-delete=(format!("/shopping-cart/{}", item.variant.variant_id))
+                        hx-target="#cart-page"
+                        hx-swap="outerHTML" {
                         span.mr-2 {"Remove"}
                         .delete {}
                     }
@@ -1,18 +1,36 @@
 use crate::{
-    components::{notification, Color, PageWrapper},
+    components::{notification, rating::rating_summary, Color, PageWrapper},
     utils::display_decimal,
 };
-use axum::extract::Path;
+use axum::extract::{Form, Path, Query, State};
 use maud::{html, Markup};
-use store_lib::store::Product;
+use serde::Deserialize;
+use store_lib::{
+    listing::{Facets, ListingPage, ListingQuery, ListingSort},
+    review::RatingSummary,
+    store::{InventoryError, Product, StockStatus},
+    wishlist::Wishlist,
+};
 use tracing::info;
 use uuid::Uuid;
 
-pub async fn store(page: PageWrapper) -> Markup {
-    page.render(page_body().await)
+use crate::{pages::wishlist::heart, AppState, Auth};
+
+pub async fn store(
+    page: PageWrapper,
+    auth: Auth,
+    State(state): State<AppState>,
+    Query(query): Query<ListingQuery>,
+) -> Markup {
+    let wishlist = auth
+        .user
+        .map(|user| state.wishlist_backend.wishlist(&user.id));
+    page.render(page_body(&state, wishlist.as_ref(), query).await)
 }
 
-async fn page_body() -> Markup {
+async fn page_body(state: &AppState, wishlist: Option<&Wishlist>, query: ListingQuery) -> Markup {
+    let rocks = state.inventory_backend.listings(&query);
+
     html! {
         section.hero {
             .hero-body {
@@ -20,19 +38,139 @@ async fn page_body() -> Markup {
                 h4.subtitle { "The best house plants you can find" }
             }
         }
-        .container {
-            section.block {
-                h3.is-6 { "Available Rocks" }
-                (rock_scroller().await)
+        #storefront.container {
+            .columns {
+                .column.is-one-fifth {
+                    (facet_sidebar(&rocks.facets, &query))
+                }
+                section.column.block {
+                    .level.is-mobile {
+                        .level-left {
+                            @if let Some(q) = query.search_terms() {
+                                h3.is-6 { "Results for \u{201c}" (q) "\u{201d}" }
+                            } @else if let Some(category) = &query.category {
+                                h3.is-6 { "Available " (category) }
+                            } @else {
+                                h3.is-6 { "Available Plants and Rocks" }
+                            }
+                        }
+                        .level-right { (sort_select(&query)) }
+                    }
+                    (rock_scroller(state, wishlist, &rocks, &query).await)
+                }
             }
         }
     }
 }
 
-async fn rock_listing(rock: &Product) -> Markup {
+/// Reloads the storefront in the chosen order, keeping the other filters.
+/// The sidebar is swapped along with the grid so its links and price form
+/// carry the new sort too.
+fn sort_select(query: &ListingQuery) -> Markup {
+    let unsorted = store_url(&ListingQuery {
+        sort: None,
+        ..query.clone()
+    });
+    let current = query.sort();
+    let options = ListingSort::ALL
+        .into_iter()
+        .filter(|sort| *sort != ListingSort::Relevance || query.search_terms().is_some());
+
+    html! {
+        .select.is-small {
+            select name="sort" aria-label="Sort by"
+                hx-get=(unsorted)
+                hx-select="#storefront"
+                hx-target="#storefront"
+                hx-swap="outerHTML"
+                hx-push-url="true" {
+                @for sort in options {
+                    option value=(sort.as_str()) selected[sort == current] { (sort.label()) }
+                }
+            }
+        }
+    }
+}
+
+fn facet_sidebar(facets: &Facets, query: &ListingQuery) -> Markup {
+    let category = |value: Option<&str>| {
+        store_url(&ListingQuery {
+            category: value.map(str::to_string),
+            ..query.clone()
+        })
+    };
+    let tag = |value: Option<&str>| {
+        store_url(&ListingQuery {
+            tag: value.map(str::to_string),
+            ..query.clone()
+        })
+    };
+
+    html! {
+        aside.menu {
+            p.menu-label { "Categories" }
+            ul.menu-list {
+                li { a.is-active[query.category.is_none()] href=(category(None)) { "All" } }
+                @for facet in &facets.categories {
+                    li {
+                        a.is-active[query.category.as_ref() == Some(&facet.value)]
+                            href=(category(Some(&facet.value))) {
+                            (facet.value) " (" (facet.count) ")"
+                        }
+                    }
+                }
+            }
+            p.menu-label { "Tags" }
+            .tags {
+                @for facet in &facets.tags {
+                    @if query.tag.as_ref() == Some(&facet.value) {
+                        a.tag.is-link href=(tag(None)) { (facet.value) " \u{d7}" }
+                    } @else {
+                        a.tag href=(tag(Some(&facet.value))) { (facet.value) " (" (facet.count) ")" }
+                    }
+                }
+            }
+            p.menu-label { "Price" }
+            form method="get" action="/" {
+                @for (name, value) in [("q", &query.q), ("category", &query.category), ("tag", &query.tag)] {
+                    @if let Some(value) = value {
+                        input type="hidden" name=(name) value=(value);
+                    }
+                }
+                @if let Some(sort) = query.sort {
+                    input type="hidden" name="sort" value=(sort.as_str());
+                }
+                .field.has-addons {
+                    .control {
+                        input.input.is-small name="min_price" type="number" min="0" step="0.01"
+                            placeholder="Min" value=[query.min_price.as_ref()];
+                    }
+                    .control {
+                        input.input.is-small name="max_price" type="number" min="0" step="0.01"
+                            placeholder="Max" value=[query.max_price.as_ref()];
+                    }
+                }
+                .field {
+                    label.checkbox {
+                        input type="checkbox" name="in_stock" value="true" checked[query.in_stock];
+                        " In stock only (" (facets.in_stock) ")"
+                    }
+                }
+                button.button.is-small.is-link type="submit" { "Apply" }
+            }
+        }
+    }
+}
+
+async fn rock_listing(
+    rock: &Product,
+    status: StockStatus,
+    rating: RatingSummary,
+    wishlisted: Option<bool>,
+) -> Markup {
     html! {
         .card {
-            .card-image {
+            a.card-image href=(product_url(rock)) {
                 .figure {
                     img.img src=(format!("/assets/images/{}", rock.image));
                 }
@@ -40,13 +178,18 @@ async fn rock_listing(rock: &Product) -> Markup {
             .card-content {
                 .media {
                     .media-content {
-                        h4.title.is-5 { (rock.name) }
+                        h4.title.is-5 { a href=(product_url(rock)) { (rock.name) } }
                         h6.subtitle.is-6.has-text-gray-light {
+                            @if rock.has_options() { "From " }
                             (display_decimal(&rock.price))
                         }
+                        p { (rating_summary(rating)) }
+                        (stock_badge(status))
                     }
                     .media-right {
+                        (heart(&rock.listing_id, wishlisted))
                         button.is-size-4 title="Add to cart"
+                        disabled[!status.available()]
                         hx-put=(format!("/add-to-cart/{}", rock.listing_id))
                         hx-target="#notifications"
                         hx-swap="afterbegin"
@@ -60,32 +203,139 @@ async fn rock_listing(rock: &Product) -> Markup {
     }
 }
 
-pub async fn add_to_cart(Path(id): Path<Uuid>) -> Markup {
-    info!("Listing item {} added to a cart", id);
-    notification("Added to cart!", Color::Success, true).await
+pub fn product_url(product: &Product) -> String {
+    format!("/products/{}", product.listing_id)
 }
 
-pub async fn rock_list(Path(page): Path<usize>) -> Markup {
-    let rocks: Vec<Product> = (0..12).map(|_| Product::random()).collect();
+pub fn stock_badge(status: StockStatus) -> Markup {
     html! {
-        @for rock in &rocks {
-            .cell { (rock_listing(rock).await) }
+        @match status {
+            StockStatus::InStock => {},
+            StockStatus::LowStock(n) => span.tag.is-warning.is-light { "Only " (n) " left" },
+            StockStatus::OutOfStock => span.tag.is-danger.is-light { "Out of stock" },
         }
-        #replaceMe.level.cell.is-full-cell.mx-auto {
-            button.button.mb-2
-                hx-target="#replaceMe"
-                hx-swap="outerHTML"
-                hx-get=(format!("/rock-list/{}", page + 1)) {
-                    "Load More"
-                }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChosenVariant {
+    variant_id: Option<Uuid>,
+}
+
+/// Adds one of a listing to the signed-in user's cart. Product pages send the
+/// chosen `variant_id`, storefront cards use the default variant.
+pub async fn add_to_cart(
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        cart_backend,
+        ..
+    }): State<AppState>,
+    Path(id): Path<Uuid>,
+    chosen: Option<Form<ChosenVariant>>,
+) -> Markup {
+    let Some(user) = auth.user else {
+        return notification("Log in to add items to your cart", Color::Info, true).await;
+    };
+
+    let variant_id = chosen.and_then(|Form(chosen)| chosen.variant_id);
+    match cart_backend.add(user.id, &inventory_backend, &id, variant_id, 1) {
+        Ok(_) => {
+            info!("Listing item {} added to a cart", id);
+            notification("Added to cart!", Color::Success, true).await
+        }
+        Err(InventoryError::InsufficientStock) => {
+            notification("Sorry, that item is out of stock", Color::Warning, true).await
+        }
+        Err(InventoryError::NotFound | InventoryError::ManualSale) => {
+            notification("That item is no longer available", Color::Danger, true).await
         }
     }
 }
 
-async fn rock_scroller() -> Markup {
+pub async fn rock_list(
+    auth: Auth,
+    State(state): State<AppState>,
+    Path(page): Path<usize>,
+    Query(query): Query<ListingQuery>,
+) -> Markup {
+    let query = ListingQuery { page, ..query };
+    let rocks = state.inventory_backend.listings(&query);
+    let wishlist = auth
+        .user
+        .map(|user| state.wishlist_backend.wishlist(&user.id));
+    rock_cells(&state, wishlist.as_ref(), &rocks, &query).await
+}
+
+async fn rock_cells(
+    state: &AppState,
+    wishlist: Option<&Wishlist>,
+    rocks: &ListingPage,
+    query: &ListingQuery,
+) -> Markup {
+    html! {
+        @for rock in &rocks.listings {
+            .cell {
+                (rock_listing(
+                    rock,
+                    state.inventory_backend.stock_status(&rock.listing_id),
+                    state.review_backend.summary(&rock.listing_id),
+                    wishlist.map(|w| w.contains(&rock.listing_id)),
+                ).await)
+            }
+        }
+        @if rocks.total == 0 {
+            .cell.is-full-cell.has-text-centered.my-6 {
+                "Nothing matched your search."
+            }
+        }
+        @if let Some(next_page) = rocks.next_page {
+            #replaceMe.level.cell.is-full-cell.mx-auto {
+                button.button.mb-2
+                    hx-target="#replaceMe"
+                    hx-swap="outerHTML"
+                    hx-get=(next_page_url(next_page, query)) {
+                        "Load More"
+                    }
+            }
+        }
+    }
+}
+
+fn query_string(query: &ListingQuery) -> String {
+    serde_urlencoded::to_string(query).unwrap_or_default()
+}
+
+/// The storefront with `query` applied, starting again from the first page.
+fn store_url(query: &ListingQuery) -> String {
+    format!(
+        "/?{}",
+        query_string(&ListingQuery {
+            page: 1,
+            ..query.clone()
+        })
+    )
+}
+
+fn next_page_url(page: usize, query: &ListingQuery) -> String {
+    format!(
+        "/rock-list/{page}?{}",
+        query_string(&ListingQuery {
+            page,
+            ..query.clone()
+        })
+    )
+}
+
+async fn rock_scroller(
+    state: &AppState,
+    wishlist: Option<&Wishlist>,
+    rocks: &ListingPage,
+    query: &ListingQuery,
+) -> Markup {
     html! {
         #rocks.grid.is-col-min-10.is-row-gap-2.mx-2 {
-            (rock_list(Path(1)).await)
+            (rock_cells(state, wishlist, rocks, query).await)
         }
     }
 }
@@ -0,0 +1,277 @@
+use axum::{
+    extract::{ConnectInfo, Form, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_login::tower_sessions::Session;
+use chrono::Utc;
+use maud::{html, Markup, PreEscaped};
+use qrcode::{render::svg, QrCode};
+use std::net::SocketAddr;
+use store_lib::{
+    account::UserError,
+    two_factor::{ConfirmPassword, TwoFactorCode, TwoFactorSetup},
+};
+use tracing::warn;
+
+use crate::{
+    components::{csrf_field, notification, Color, PageWrapper},
+    pages::account::login_page,
+    pending_login, sessions, AppState, Auth,
+};
+
+/// The second login step, asking for a code.
+pub async fn login(page: PageWrapper, session: Session) -> Response {
+    if pending_login::pending(&session, Utc::now()).await.is_none() {
+        return Redirect::to("/login").into_response();
+    }
+    let form = code_form(page.csrf_token());
+    page.render(login_page(form)).into_response()
+}
+
+/// Finishes logging in with an app or recovery code. Wrong codes count
+/// towards locking the account like wrong passwords do.
+pub async fn login_post(
+    page: PageWrapper,
+    mut auth: Auth,
+    session: Session,
+    State(s): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Form(TwoFactorCode { code }): Form<TwoFactorCode>,
+) -> Response {
+    let now = Utc::now();
+    let Some(pending) = pending_login::pending(&session, now).await else {
+        return Redirect::to("/login").into_response();
+    };
+    let failed = |status: StatusCode, message: String| async move {
+        let content = html! {
+            (notification(&message, Color::Danger, true).await)
+            (code_form(page.csrf_token()))
+        };
+        (status, page.render(login_page(content))).into_response()
+    };
+
+    if let Err(locked) = s.login_attempts.check(&pending.email, addr.ip(), now) {
+        pending_login::clear(&session).await;
+        let message = format!(
+            "Too many failed logins. Try again in {} minutes.",
+            locked.minutes_left(now)
+        );
+        return failed(StatusCode::TOO_MANY_REQUESTS, message).await;
+    }
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.check_two_factor(&pending.user_id, &code, now)
+    };
+    match result {
+        Ok(user) => {
+            s.login_attempts.record_success(&pending.email);
+            pending_login::clear(&session).await;
+            match auth.login(&user).await {
+                Ok(_) => {
+                    if pending.remember {
+                        sessions::remember(&session, s.session_config.lifetime);
+                    }
+                    Redirect::to("/").into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Err(UserError::InvalidCode) => {
+            s.login_attempts
+                .record_failure(&pending.email, addr.ip(), now);
+            let message = "That code is incorrect or has already been used.".to_string();
+            failed(StatusCode::UNAUTHORIZED, message).await
+        }
+        Err(e) => {
+            warn!("Two-factor login failed: {e}");
+            pending_login::clear(&session).await;
+            Redirect::to("/login").into_response()
+        }
+    }
+}
+
+fn code_form(csrf_token: &str) -> Markup {
+    html! {
+        form method="post" action="/login/two-factor" {
+            (csrf_field(csrf_token))
+            h2.is-size-3 { "Two-Factor Authentication" }
+            p.mb-4 {
+                "Enter the code from your authenticator app, or one of your recovery codes."
+            }
+            .field {
+                label.label for="code" { "Code" }
+                .control {
+                    input.input name="code" id="code" autocomplete="one-time-code" autofocus required;
+                }
+            }
+            button.button.is-link type="submit" { "Verify" }
+        }
+    }
+}
+
+/// Shows the QR code for setting up an authenticator app.
+pub async fn setup(page: PageWrapper, auth: Auth, State(s): State<AppState>) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+    if user.two_factor_enabled {
+        return Redirect::to("/account").into_response();
+    }
+
+    let setup = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.begin_two_factor(&user.id)
+    };
+    match setup {
+        Ok(setup) => {
+            let content = setup_page(&setup, None, page.csrf_token()).await;
+            page.render(content).into_response()
+        }
+        Err(e) => {
+            warn!("Failed to start two-factor setup: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Turns 2FA on once the first code checks out, then shows the recovery
+/// codes.
+pub async fn confirm(
+    page: PageWrapper,
+    auth: Auth,
+    State(s): State<AppState>,
+    Form(TwoFactorCode { code }): Form<TwoFactorCode>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        match user_store.confirm_two_factor(&user.id, &code, Utc::now()) {
+            // Show the same secret again to try another code.
+            Err(UserError::InvalidCode) => Err(user_store.begin_two_factor(&user.id)),
+            result => Ok(result),
+        }
+    };
+    match result {
+        Ok(Ok((_, codes))) => page.render(recovery_codes_page(&codes)).into_response(),
+        Ok(Err(_)) => Redirect::to("/account").into_response(),
+        Err(Ok(setup)) => {
+            let message = "That code didn't match. Check your app and try again.";
+            let content = setup_page(&setup, Some(message), page.csrf_token()).await;
+            (StatusCode::BAD_REQUEST, page.render(content)).into_response()
+        }
+        Err(Err(e)) => {
+            warn!("Failed to show two-factor setup again: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Replaces the recovery codes after checking the password.
+pub async fn recovery_codes(
+    page: PageWrapper,
+    auth: Auth,
+    State(s): State<AppState>,
+    Form(ConfirmPassword { password }): Form<ConfirmPassword>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    let result = {
+        let mut user_store = s.user_backend.lock().expect("user store threading issue");
+        user_store.new_recovery_codes(&user.id, &password)
+    };
+    match result {
+        Ok(codes) => page.render(recovery_codes_page(&codes)).into_response(),
+        Err(e) => {
+            let message = format!("No new codes were made: {e}.");
+            let content = html! {
+                (notification(&message, Color::Danger, true).await)
+                a href="/account" { "Back to account settings" }
+            };
+            (StatusCode::BAD_REQUEST, page.render(login_page(content))).into_response()
+        }
+    }
+}
+
+fn qr_code(uri: &str) -> Markup {
+    match QrCode::new(uri) {
+        Ok(code) => {
+            let image = code
+                .render::<svg::Color>()
+                .min_dimensions(200, 200)
+                .quiet_zone(true)
+                .build();
+            PreEscaped(image)
+        }
+        Err(e) => {
+            warn!("Failed to draw QR code: {e}");
+            html! {}
+        }
+    }
+}
+
+async fn setup_page(setup: &TwoFactorSetup, error: Option<&str>, csrf_token: &str) -> Markup {
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Set Up Two-Factor Authentication" }
+                @if let Some(error) = error {
+                    (notification(error, Color::Danger, true).await)
+                }
+                .columns {
+                    .column.is-half {
+                        .box {
+                            p.mb-3 { "Scan this code with your authenticator app." }
+                            .has-text-centered.mb-3 { (qr_code(&setup.uri)) }
+                            p.mb-4 {
+                                "Or enter this key by hand: "
+                                code { (setup.secret) }
+                            }
+                            form method="post" action="/account/two-factor" {
+                                (csrf_field(csrf_token))
+                                .field {
+                                    label.label for="code" { "Code from your app" }
+                                    .control {
+                                        input.input name="code" id="code" autocomplete="one-time-code" required;
+                                    }
+                                }
+                                button.button.is-link type="submit" { "Turn On" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn recovery_codes_page(codes: &[String]) -> Markup {
+    html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Recovery Codes" }
+                .columns {
+                    .column.is-half {
+                        .box {
+                            p.mb-3 {
+                                "Keep these somewhere safe. Each one logs you in once if you lose your authenticator app. They won't be shown again."
+                            }
+                            ul.mb-4 {
+                                @for code in codes {
+                                    li { code { (code) } }
+                                }
+                            }
+                            a.button.is-link href="/account" { "Done" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
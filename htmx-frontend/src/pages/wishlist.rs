@@ -0,0 +1,191 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use maud::{html, Markup};
+use store_lib::{store::InventoryBackend, store::InventoryError, wishlist::Wishlist};
+use uuid::Uuid;
+
+use crate::{
+    components::{notification, Color, PageWrapper},
+    pages::store::{product_url, stock_badge},
+    utils::display_decimal,
+    AppState, Auth,
+};
+
+pub async fn wishlist(
+    page: PageWrapper,
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        wishlist_backend,
+        ..
+    }): State<AppState>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return Redirect::to("/login").into_response();
+    };
+
+    // Back-in-stock notices are shown once.
+    let wishlist = wishlist_backend.wishlist(&user.id);
+    wishlist_backend.dismiss_notices(&user.id);
+
+    page.render(html! {
+        .section {
+            .container {
+                h2.title.is-3 { "Wishlist" }
+                @for notice in &wishlist.notices {
+                    (notification(&format!("{} is back in stock!", notice.name), Color::Success, true).await)
+                }
+                (wishlist_items(&inventory_backend, &wishlist, None).await)
+            }
+        }
+    })
+    .into_response()
+}
+
+/// Flips a listing on or off the wishlist and swaps in the updated heart.
+pub async fn toggle(
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        wishlist_backend,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let Some(product) = inventory_backend.product(&listing_id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let wishlisted = wishlist_backend.toggle(user.id, &product);
+    heart(&listing_id, Some(wishlisted)).into_response()
+}
+
+pub async fn move_to_cart(
+    auth: Auth,
+    State(AppState {
+        cart_backend,
+        inventory_backend,
+        wishlist_backend,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let error = match wishlist_backend.move_to_cart(
+        user.id,
+        &listing_id,
+        &cart_backend,
+        &inventory_backend,
+    ) {
+        Ok(_) => None,
+        Err(InventoryError::InsufficientStock) => Some("Sorry, that item is out of stock"),
+        Err(InventoryError::NotFound | InventoryError::ManualSale) => {
+            Some("That item is no longer available")
+        }
+    };
+
+    let wishlist = wishlist_backend.wishlist(&user.id);
+    wishlist_items(&inventory_backend, &wishlist, error)
+        .await
+        .into_response()
+}
+
+pub async fn remove_item(
+    auth: Auth,
+    State(AppState {
+        inventory_backend,
+        wishlist_backend,
+        ..
+    }): State<AppState>,
+    Path(listing_id): Path<Uuid>,
+) -> Response {
+    let Some(user) = auth.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let wishlist = wishlist_backend.remove(user.id, &listing_id);
+    wishlist_items(&inventory_backend, &wishlist, None)
+        .await
+        .into_response()
+}
+
+/// The wishlist toggle. `wishlisted` is `None` for visitors, whose heart
+/// leads to the login page instead.
+pub fn heart(listing_id: &Uuid, wishlisted: Option<bool>) -> Markup {
+    html! {
+        @match wishlisted {
+            None => {
+                a.button.is-white.is-size-5 href="/login" title="Log in to save to your wishlist" {
+                    "\u{2661}"
+                }
+            }
+            Some(wishlisted) => {
+                button.button.is-white.is-size-5.has-text-danger
+                    title=(if wishlisted { "Remove from wishlist" } else { "Save to wishlist" })
+                    hx-post=(format!("/wishlist/{listing_id}"))
+                    hx-swap="outerHTML" {
+                    @if wishlisted { "\u{2665}" } @else { "\u{2661}" }
+                }
+            }
+        }
+    }
+}
+
+async fn wishlist_items(
+    inventory_backend: &InventoryBackend,
+    wishlist: &Wishlist,
+    error: Option<&str>,
+) -> Markup {
+    html! {
+        #wishlist {
+            @if let Some(error) = error {
+                (notification(error, Color::Warning, true).await)
+            }
+            @if wishlist.items.is_empty() {
+                p.block { "Nothing saved yet. Tap the heart on any plant or rock to keep it here." }
+                a href="/" { "Continue Shopping" }
+            }
+            @for item in &wishlist.items {
+                @if let Some(product) = inventory_backend.product(&item.listing_id) {
+                    .box {
+                        .media {
+                            .media-left {
+                                .image.is-96x96.is-flex.is-align-items-center {
+                                    img src=(format!("/assets/images/{}", product.image));
+                                }
+                            }
+                            .media-content {
+                                a href=(product_url(&product)) { b { (product.name) } }
+                                br;
+                                @if product.has_options() { "From " }
+                                (display_decimal(&product.price))
+                                " "
+                                (stock_badge(inventory_backend.stock_status(&product.listing_id)))
+                            }
+                            .media-right.buttons {
+                                button.button.is-link
+                                    disabled[!inventory_backend.stock_status(&product.listing_id).available()]
+                                    hx-post=(format!("/wishlist/{}/move", product.listing_id))
+                                    hx-target="#wishlist"
+                                    hx-swap="outerHTML" { "Move to Cart" }
+                                button.button
+                                    hx-delete=(format!("/wishlist/{}", product.listing_id))
+                                    hx-target="#wishlist"
+                                    hx-swap="outerHTML" { "Remove" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use axum_login::tower_sessions::Session;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use store_lib::account::User;
+use tracing::warn;
+use uuid::Uuid;
+
+/// How long the second login step waits for a code.
+const PENDING_LOGIN_MINUTES: i64 = 5;
+const SESSION_KEY: &str = "pending_login";
+
+/// A login whose password was right, waiting on a 2FA code. Kept in the
+/// session between the two steps.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PendingLogin {
+    pub user_id: Uuid,
+    pub email: String,
+    /// Whether to remember the login once the code is in.
+    pub remember: bool,
+    expires: DateTime<Utc>,
+}
+
+pub async fn start(session: &Session, user: &User, remember: bool, now: DateTime<Utc>) -> bool {
+    let pending = PendingLogin {
+        user_id: user.id,
+        email: user.email.clone(),
+        remember,
+        expires: now + Duration::minutes(PENDING_LOGIN_MINUTES),
+    };
+    match session.insert(SESSION_KEY, pending).await {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Failed to store pending login: {e}");
+            false
+        }
+    }
+}
+
+/// The login waiting on a code, unless it has expired.
+pub async fn pending(session: &Session, now: DateTime<Utc>) -> Option<PendingLogin> {
+    session
+        .get::<PendingLogin>(SESSION_KEY)
+        .await
+        .ok()
+        .flatten()
+        .filter(|pending| pending.expires > now)
+}
+
+pub async fn clear(session: &Session) {
+    if let Err(e) = session.remove::<PendingLogin>(SESSION_KEY).await {
+        warn!("Failed to clear pending login: {e}");
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::Auth;
+
+/// Buckets kept before idle ones are dropped.
+const MAX_BUCKETS: usize = 10_000;
+/// What a full limiter is cut back to, so eviction runs once per batch of
+/// new clients rather than on each one.
+const KEEP_BUCKETS: usize = MAX_BUCKETS * 3 / 4;
+
+/// A token bucket: up to `burst` requests at once, refilled at `per_minute`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl RateLimit {
+    fn refill_per_second(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+/// Limits one group of routes. Logged in users get a bucket each, everyone
+/// else shares one per IP address. Share a limiter between routes to give
+/// them a common budget.
+#[derive(Clone)]
+pub struct RateLimiter {
+    name: &'static str,
+    limit: RateLimit,
+    buckets: Arc<Mutex<HashMap<Client, Bucket>>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Client {
+    User(Uuid),
+    Ip(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(name: &'static str, limit: RateLimit) -> Self {
+        Self {
+            name,
+            limit,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Takes a token for the client, or says how long until one is free.
+    pub fn take(&self, auth: &Auth, addr: SocketAddr) -> Result<(), Duration> {
+        let client = match &auth.user {
+            Some(user) => Client::User(user.id),
+            None => Client::Ip(addr.ip()),
+        };
+        let taken = self.take_at(client, Instant::now());
+        if taken.is_err() {
+            warn!("Rate limited {} request from {}", self.name, addr.ip());
+        }
+        taken
+    }
+
+    fn take_at(&self, client: Client, now: Instant) -> Result<(), Duration> {
+        let burst = f64::from(self.limit.burst);
+        let refill = self.limit.refill_per_second();
+
+        let mut buckets = self.buckets.lock().expect("rate limiter threads");
+        if buckets.len() >= MAX_BUCKETS {
+            evict(&mut buckets, now, burst, refill);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+
+        bucket.tokens = (bucket.tokens + refill * (now - bucket.updated).as_secs_f64()).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        if refill <= 0.0 {
+            return Err(Duration::from_secs(60));
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / refill))
+    }
+}
+
+/// Drops buckets that have refilled, then the least recently used ones
+/// until the map is back to `KEEP_BUCKETS`.
+fn evict(buckets: &mut HashMap<Client, Bucket>, now: Instant, burst: f64, refill: f64) {
+    buckets.retain(|_, b| b.tokens + refill * (now - b.updated).as_secs_f64() < burst);
+    if buckets.len() <= KEEP_BUCKETS {
+        return;
+    }
+
+    let excess = buckets.len() - KEEP_BUCKETS;
+    let mut oldest: Vec<_> = buckets.iter().map(|(c, b)| (b.updated, *c)).collect();
+    oldest.sort_unstable_by_key(|(updated, _)| *updated);
+    for (_, client) in oldest.into_iter().take(excess) {
+        buckets.remove(&client);
+    }
+}
+
+/// Adds `Retry-After`, in whole seconds, to a rate limited response.
+pub fn retry_after(mut response: Response, wait: Duration) -> Response {
+    let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    if let Ok(value) = HeaderValue::from_str(&seconds.max(1).to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
+
+/// Middleware for page routes, answering with a plain 429.
+pub async fn limit_pages(
+    State(limiter): State<RateLimiter>,
+    auth: Auth,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match limiter.take(&auth, addr) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let message = "Too many requests. Please wait a moment and try again.";
+            retry_after(
+                (StatusCode::TOO_MANY_REQUESTS, message).into_response(),
+                wait,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{
+        body::Body, extract::connect_info::MockConnectInfo, middleware::from_fn_with_state,
+        routing::get, Router,
+    };
+    use axum_login::{
+        tower_sessions::{MemoryStore, SessionManagerLayer},
+        AuthManagerLayerBuilder,
+    };
+    use store_lib::account::UserBackend;
+    use tower::ServiceExt;
+
+    #[test]
+    fn users_and_addresses_get_their_own_buckets() {
+        let limiter = RateLimiter::new(
+            "test",
+            RateLimit {
+                burst: 2,
+                per_minute: 60,
+            },
+        );
+        let ip = Client::Ip([203, 0, 113, 7].into());
+        let user = Client::User(Uuid::new_v4());
+        let now = Instant::now();
+
+        assert!(limiter.take_at(ip, now).is_ok());
+        assert!(limiter.take_at(ip, now).is_ok());
+        assert_eq!(limiter.take_at(ip, now), Err(Duration::from_secs(1)));
+        assert!(limiter.take_at(user, now).is_ok());
+        assert!(limiter
+            .take_at(Client::Ip([203, 0, 113, 8].into()), now)
+            .is_ok());
+        // A token a second comes back.
+        assert!(limiter.take_at(ip, now + Duration::from_secs(1)).is_ok());
+
+        let closed = RateLimiter::new(
+            "test",
+            RateLimit {
+                burst: 0,
+                per_minute: 0,
+            },
+        );
+        assert_eq!(closed.take_at(ip, now), Err(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn a_full_limiter_evicts_its_oldest_buckets_in_one_batch() {
+        let limiter = RateLimiter::new(
+            "test",
+            RateLimit {
+                burst: 2,
+                per_minute: 1,
+            },
+        );
+        let start = Instant::now();
+        let client = |n: u32| Client::Ip(n.to_be_bytes().into());
+        for n in 0..=MAX_BUCKETS as u32 {
+            let now = start + Duration::from_millis(n.into());
+            limiter.take_at(client(n), now).unwrap();
+        }
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), KEEP_BUCKETS + 1);
+        assert!(!buckets.contains_key(&client(0)));
+        assert!(buckets.contains_key(&client(MAX_BUCKETS as u32)));
+    }
+
+    #[test]
+    fn retry_after_rounds_up_to_whole_seconds() {
+        let header =
+            |wait| retry_after(Response::default(), wait).headers()[header::RETRY_AFTER].clone();
+        assert_eq!(header(Duration::from_millis(1200)), "2");
+        assert_eq!(header(Duration::ZERO), "1");
+    }
+
+    #[tokio::test]
+    async fn limited_pages_get_a_429_with_retry_after() {
+        let limiter = RateLimiter::new(
+            "test",
+            RateLimit {
+                burst: 1,
+                per_minute: 1,
+            },
+        );
+        let session_layer = SessionManagerLayer::new(MemoryStore::default());
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(from_fn_with_state(limiter, limit_pages))
+            .layer(AuthManagerLayerBuilder::new(UserBackend::new(), session_layer).build())
+            .layer(MockConnectInfo(SocketAddr::from(([203, 0, 113, 7], 443))));
+        let request = || Request::builder().uri("/").body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::{Path, PathBuf};
+
+use store_lib::{
+    account::UserBackend,
+    fixture::{Fixture, FixtureError},
+    store::InventoryBackend,
+};
+use tracing::info;
+
+/// Loads each fixture in turn. Already loaded users and products are
+/// skipped, so the same files can be given on every start.
+pub fn load(
+    files: &[PathBuf],
+    users: &UserBackend,
+    inventory: &InventoryBackend,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for path in files {
+        let report = read(path)?
+            .apply(users, inventory)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        info!("Seeded from {}: {report}", path.display());
+    }
+    Ok(())
+}
+
+/// Loads the fixtures into empty stores, twice, to show they are valid and
+/// safe to load again.
+pub fn check(files: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    let users = UserBackend::new();
+    let inventory = InventoryBackend::new();
+    for _ in 0..2 {
+        for path in files {
+            let report = read(path)?
+                .apply(&users, &inventory)
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+            println!("{}: {report}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// JSON for `.json` files, YAML for anything else.
+fn read(path: &Path) -> Result<Fixture, Box<dyn std::error::Error>> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+    let fixture = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => Fixture::from_json(&text),
+        _ => Fixture::from_yaml(&text),
+    };
+    fixture.map_err(|e: FixtureError| format!("{}: {e}", path.display()).into())
+}
@@ -0,0 +1,230 @@
+use std::{net::SocketAddr, time::Duration};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use axum_login::tower_sessions::{
+    cookie::{time, SameSite},
+    ExpiredDeletion, Expiry, Session, SessionManagerLayer,
+};
+use chrono::Utc;
+use store_lib::session_store::{SessionInfo, SqlSessionStore, SESSION_INFO_KEY};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{config::SessionSettings, Auth};
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How stale `last_seen` gets before a request updates it, so not every
+/// request writes the session.
+const ACTIVITY_MINUTES: i64 = 1;
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// Session cookie settings.
+#[derive(Clone, Copy)]
+pub struct SessionConfig {
+    /// How long a remembered login lasts without being used.
+    pub lifetime: time::Duration,
+    /// Only send the cookie over HTTPS.
+    pub secure: bool,
+}
+
+impl SessionConfig {
+    pub fn new(settings: &SessionSettings) -> Self {
+        Self {
+            lifetime: time::Duration::days(settings.lifetime_days),
+            secure: settings.secure_cookie,
+        }
+    }
+
+    /// Sessions end with the browser unless the login is remembered. Lax
+    /// same-site keeps the cookie on links followed from emails.
+    pub fn layer(&self, store: SqlSessionStore) -> SessionManagerLayer<SqlSessionStore> {
+        SessionManagerLayer::new(store)
+            .with_secure(self.secure)
+            .with_http_only(true)
+            .with_same_site(SameSite::Lax)
+            .with_expiry(Expiry::OnSessionEnd)
+    }
+}
+
+/// Connects to a Postgres or SQLite database.
+pub async fn session_store(url: &str) -> Result<SqlSessionStore, Box<dyn std::error::Error>> {
+    Ok(SqlSessionStore::connect(url).await?)
+}
+
+/// Keeps the session for `lifetime` of inactivity instead of ending it
+/// when the browser closes.
+pub fn remember(session: &Session, lifetime: time::Duration) {
+    session.set_expiry(Some(Expiry::OnInactivity(lifetime)));
+}
+
+/// Deletes expired sessions in the background.
+pub async fn delete_expired(store: SqlSessionStore) {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = store.delete_expired().await {
+            warn!("Failed to delete expired sessions: {e}");
+        }
+    }
+}
+
+/// Middleware recording when, where from and with what each logged in
+/// session is used, for the sessions page.
+pub async fn track(
+    auth: Auth,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(user) = &auth.user {
+        let now = Utc::now();
+        let user_agent: String = request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .chars()
+            .take(MAX_USER_AGENT_LENGTH)
+            .collect();
+        let ip = addr.ip().to_string();
+
+        let info = match session.get::<SessionInfo>(SESSION_INFO_KEY).await {
+            Ok(Some(info)) if info.user_id == user.id => {
+                let stale = now - info.last_seen >= chrono::Duration::minutes(ACTIVITY_MINUTES);
+                let moved = info.ip != ip || info.user_agent != user_agent;
+                (stale || moved).then_some(SessionInfo {
+                    last_seen: now,
+                    ip,
+                    user_agent,
+                    ..info
+                })
+            }
+            _ => Some(SessionInfo {
+                key: Uuid::new_v4(),
+                user_id: user.id,
+                created: now,
+                last_seen: now,
+                ip,
+                user_agent,
+            }),
+        };
+        if let Some(info) = info {
+            if let Err(e) = session.insert(SESSION_INFO_KEY, info).await {
+                warn!("Failed to record session activity: {e}");
+            }
+        }
+    }
+    next.run(request).await
+}
+
+/// The key naming this session on the sessions page.
+pub async fn current_key(session: &Session) -> Option<Uuid> {
+    let info = session.get::<SessionInfo>(SESSION_INFO_KEY).await.ok()??;
+    Some(info.key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{
+        body::{to_bytes, Body},
+        extract::connect_info::MockConnectInfo,
+        http::{HeaderValue, StatusCode},
+        middleware::from_fn,
+        routing::{get, post},
+        Router,
+    };
+    use axum_login::AuthManagerLayerBuilder;
+    use store_lib::account::{Signup, UserBackend};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn sessions_are_tracked_until_revoked() {
+        let path = std::env::temp_dir().join(format!("sessions-{}.db", Uuid::new_v4()));
+        let store = session_store(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .unwrap();
+        let users = UserBackend::new();
+        let user = users
+            .lock()
+            .unwrap()
+            .add(Signup {
+                email: "fern@example.com".to_string(),
+                password: "password1".to_string(),
+                username: "fern".to_string(),
+            })
+            .unwrap();
+        let config = SessionConfig::new(&SessionSettings::default());
+        let app = Router::new()
+            .route(
+                "/login",
+                post(|mut auth: Auth| async move {
+                    let user = auth
+                        .backend
+                        .lock()
+                        .unwrap()
+                        .find_by_email("fern@example.com")
+                        .cloned();
+                    auth.login(&user.unwrap()).await.unwrap();
+                }),
+            )
+            .route(
+                "/key",
+                get(|session: Session| async move {
+                    current_key(&session)
+                        .await
+                        .map(|key| key.to_string())
+                        .unwrap_or_default()
+                }),
+            )
+            .layer(from_fn(track))
+            .layer(AuthManagerLayerBuilder::new(users, config.layer(store.clone())).build())
+            .layer(MockConnectInfo(SocketAddr::from(([203, 0, 113, 7], 443))));
+
+        let login = || async {
+            let request = Request::post("/login").body(Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+            HeaderValue::from_str(cookie.split(';').next().unwrap()).unwrap()
+        };
+        let key = |cookie: HeaderValue| async {
+            let request = Request::get("/key")
+                .header(header::COOKIE, cookie)
+                .header(header::USER_AGENT, "curl")
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = to_bytes(response.into_body(), 64).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        let first = login().await;
+        let first_key = key(first.clone()).await;
+        assert!(!first_key.is_empty());
+        assert_eq!(key(first.clone()).await, first_key);
+        let second = login().await;
+        let second_key = key(second.clone()).await;
+        assert_ne!(second_key, first_key);
+
+        let sessions = store.user_sessions(&user.id).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions
+            .iter()
+            .all(|s| s.ip == "203.0.113.7" && s.user_agent == "curl"));
+
+        let revoked = first_key.parse().unwrap();
+        assert!(store.revoke(&user.id, &revoked).await.unwrap());
+        assert_eq!(key(first).await, "");
+        assert_eq!(key(second).await, second_key);
+        assert_eq!(store.user_sessions(&user.id).await.unwrap().len(), 1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -6,19 +6,49 @@ use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, Salt
 use argon2::Argon2;
 use axum_login::tracing::info;
 use axum_login::{AuthUser, AuthnBackend, UserId};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 use uuid::Uuid;
 
+use crate::mail::parse_address;
+use crate::oidc::{ExternalIdentity, ExternalProfile};
+use crate::password_reset::{PasswordResets, ResetError};
+use crate::two_factor::{TwoFactor, TwoFactorSetup};
+use crate::verification::{EmailVerifier, VerifyError};
+
 #[derive(Serialize, Clone, Debug, TS)]
 #[ts(export)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
     pub username: String,
+    pub role: Role,
+    /// Whether the user has followed the link mailed to `email`.
+    pub verified: bool,
+    /// Whether logging in also needs a code from an authenticator app.
+    pub two_factor_enabled: bool,
     #[serde(skip)]
     password: String,
+    #[serde(skip)]
+    #[ts(skip)]
+    two_factor: Option<TwoFactor>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum Role {
+    Customer,
+    Admin,
+}
+
+impl Role {
+    /// Roles that can manage the store, which may be made to use 2FA.
+    pub fn is_staff(&self) -> bool {
+        *self != Role::Customer
+    }
 }
 
 #[derive(Debug, TS)]
@@ -27,6 +57,20 @@ pub enum UserError {
     #[ts(skip)]
     PasswordHashingFailed(argon2::password_hash::Error),
     NotFound,
+    InvalidEmail,
+    InvalidUsername,
+    EmailTaken,
+    UsernameTaken,
+    WeakPassword(&'static str),
+    WrongPassword,
+    InvalidSignup(SignupErrors),
+    InvalidCode,
+    TwoFactorOn,
+    TwoFactorOff,
+    /// Staff have to keep 2FA on while it is required for them.
+    TwoFactorRequired,
+    /// The external login already belongs to another account.
+    IdentityLinked,
 }
 
 impl std::fmt::Display for UserError {
@@ -34,10 +78,64 @@ impl std::fmt::Display for UserError {
         let error = match self {
             UserError::NotFound => "not found",
             UserError::PasswordHashingFailed(_) => "error",
+            UserError::InvalidEmail => "invalid email address",
+            UserError::InvalidUsername => "username must be 1 to 32 characters",
+            UserError::EmailTaken => "email address is already in use",
+            UserError::UsernameTaken => "username is already taken",
+            UserError::WeakPassword(rule) => rule,
+            UserError::WrongPassword => "current password is incorrect",
+            UserError::InvalidCode => "code is incorrect or has already been used",
+            UserError::TwoFactorOn => "two-factor authentication is already on",
+            UserError::TwoFactorOff => "two-factor authentication is off",
+            UserError::TwoFactorRequired => "two-factor authentication is required for staff",
+            UserError::IdentityLinked => "that login is linked to another account",
+            UserError::InvalidSignup(errors) => {
+                let errors: Vec<&str> = [&errors.username, &errors.email, &errors.password]
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect();
+                return write!(f, "{}", errors.join(", "));
+            }
         };
 
-        writeln!(f, "{}", error)
+        write!(f, "{}", error)
+    }
+}
+
+/// What is wrong with each field of a rejected signup, so the form can show
+/// the errors next to the inputs.
+#[derive(Serialize, Default, Clone, Debug, TS)]
+#[ts(export)]
+pub struct SignupErrors {
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub password: Option<String>,
+}
+
+impl SignupErrors {
+    pub fn is_empty(&self) -> bool {
+        self.username.is_none() && self.email.is_none() && self.password.is_none()
+    }
+}
+
+pub const MAX_USERNAME_LENGTH: usize = 32;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Checks a new password against the strength rules.
+pub fn check_password(password: &str) -> Result<(), UserError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(UserError::WeakPassword(
+            "password must be at least 8 characters",
+        ));
+    }
+    let letters = password.chars().filter(|c| c.is_alphabetic()).count();
+    if letters == 0 || letters == password.chars().count() {
+        return Err(UserError::WeakPassword(
+            "password needs a letter and a number or symbol",
+        ));
     }
+    Ok(())
 }
 
 impl std::error::Error for UserError {}
@@ -48,25 +146,62 @@ impl From<argon2::password_hash::Error> for UserError {
     }
 }
 
+/// Users are keyed by their address in lower case, so it matches however
+/// it is typed.
+fn email_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn hash_password(password: &str) -> Result<String, UserError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
 impl User {
     fn new(username: String, email: String, password: String) -> Result<Self, UserError> {
-        let argon2 = Argon2::default();
-        let salt = SaltString::generate(&mut OsRng);
-        let password = argon2.hash_password(password.as_bytes(), &salt)?;
+        let password = hash_password(&password)?;
         info!("Created user {username}");
         Ok(User {
             id: Uuid::new_v4(),
             username,
             email,
-            password: password.to_string(),
+            role: Role::Customer,
+            verified: false,
+            two_factor_enabled: false,
+            password,
+            two_factor: None,
         })
     }
 
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// Unused recovery codes, or `None` without 2FA.
+    pub fn recovery_codes_left(&self) -> Option<usize> {
+        self.two_factor
+            .as_ref()
+            .filter(|two_factor| two_factor.is_confirmed())
+            .map(TwoFactor::recovery_codes_left)
+    }
+
     fn authenticate(&self, password: String) -> Result<(), UserError> {
         let password_hash = PasswordHash::new(&self.password)?;
         Argon2::default().verify_password(password.as_bytes(), &password_hash)?;
         Ok(())
     }
+
+    /// Confirms the user knows their password before a sensitive change.
+    fn confirm_password(&self, password: &str) -> Result<(), UserError> {
+        match self.authenticate(password.to_string()) {
+            Err(UserError::PasswordHashingFailed(argon2::password_hash::Error::Password)) => {
+                Err(UserError::WrongPassword)
+            }
+            result => result,
+        }
+    }
 }
 
 impl AuthUser for User {
@@ -86,6 +221,10 @@ impl AuthUser for User {
 pub struct Credentials {
     pub email: String,
     pub password: String,
+    /// Keep the session after the browser closes.
+    #[serde(default)]
+    #[ts(as = "Option<bool>", optional)]
+    pub remember: bool,
 }
 
 impl From<Signup> for Credentials {
@@ -93,10 +232,36 @@ impl From<Signup> for Credentials {
         Credentials {
             email: value.email.clone(),
             password: value.password.clone(),
+            remember: false,
         }
     }
 }
 
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct ChangeUsername {
+    pub username: String,
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct ChangeEmail {
+    pub email: String,
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct ChangePassword {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct DeleteAccount {
+    pub password: String,
+}
+
 #[derive(Deserialize, Clone, TS)]
 #[ts(export)]
 pub struct Signup {
@@ -108,6 +273,10 @@ pub struct Signup {
 #[derive(Clone)]
 pub struct UserStore {
     users: HashMap<String, User>,
+    resets: PasswordResets,
+    staff_two_factor_required: bool,
+    /// External logins and the accounts they log in to.
+    identities: HashMap<ExternalIdentity, Uuid>,
 }
 
 #[derive(Clone)]
@@ -119,6 +288,12 @@ impl UserBackend {
     }
 }
 
+impl Default for UserBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Deref for UserBackend {
     type Target = Arc<Mutex<UserStore>>;
 
@@ -135,25 +310,25 @@ impl AuthnBackend for UserBackend {
 
     async fn authenticate(
         &self,
-        Self::Credentials { email, password }: Self::Credentials,
+        Self::Credentials {
+            email, password, ..
+        }: Self::Credentials,
     ) -> Result<Option<Self::User>, Self::Error> {
         let user_store = self.lock().expect("user backend threads");
-        let user = user_store.users.get(&email).ok_or(UserError::NotFound)?;
+        let Some(user) = user_store.users.get(&email_key(&email)) else {
+            return Ok(None);
+        };
 
-        match user.authenticate(password) {
+        match user.confirm_password(&password) {
             Ok(_) => Ok(Some(user.clone())),
-            Err(UserError::NotFound) => Ok(None),
+            Err(UserError::WrongPassword) => Ok(None),
             Err(e) => Err(e),
         }
     }
 
     async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<Self::User>, Self::Error> {
         let user_store = self.lock().expect("user backend threads");
-        Ok(user_store
-            .users
-            .iter()
-            .find(|(_, u)| u.id == *user_id)
-            .map(|(_, u)| u.clone()))
+        Ok(user_store.find_by_id(user_id).cloned())
     }
 }
 
@@ -162,20 +337,394 @@ pub enum LoginError {
     InvalidUsername,
 }
 
+impl Default for UserStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl UserStore {
     pub fn new() -> Self {
         UserStore {
             users: HashMap::new(),
+            resets: PasswordResets::default(),
+            staff_two_factor_required: false,
+            identities: HashMap::new(),
         }
     }
 
-    pub fn add(&mut self, signup: Signup) -> Result<(), UserError> {
-        let user = User::new(signup.username, signup.email, signup.password)?;
-        self.users.insert(user.email.clone(), user);
-        info!("Users {:?}", self.users);
+    /// Creates an account, refusing taken usernames and emails and weak
+    /// passwords with an error for each field at fault.
+    pub fn add(&mut self, signup: Signup) -> Result<User, UserError> {
+        let username = self.check_username(&signup.username, None);
+        let email = self.check_email(&signup.email, None);
+        let password = check_password(&signup.password);
+
+        let (Ok(username), Ok(email), Ok(())) = (&username, &email, &password) else {
+            return Err(UserError::InvalidSignup(SignupErrors {
+                username: username.err().map(|e| e.to_string()),
+                email: email.err().map(|e| e.to_string()),
+                password: password.err().map(|e| e.to_string()),
+            }));
+        };
+
+        let user = User::new(username.clone(), email.clone(), signup.password)?;
+        self.users.insert(user.email.clone(), user.clone());
+        Ok(user)
+    }
+
+    /// The trimmed username, if it is valid and free for `id` to use.
+    fn check_username(&self, username: &str, id: Option<&Uuid>) -> Result<String, UserError> {
+        let username = username.trim();
+        if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+            return Err(UserError::InvalidUsername);
+        }
+        let taken = self
+            .users
+            .values()
+            .any(|u| Some(&u.id) != id && u.username.to_lowercase() == username.to_lowercase());
+        if taken {
+            return Err(UserError::UsernameTaken);
+        }
+        Ok(username.to_string())
+    }
+
+    /// The parsed address in lower case, if it is valid and free for `id`
+    /// to use.
+    fn check_email(&self, email: &str, id: Option<&Uuid>) -> Result<String, UserError> {
+        let email = parse_address(email)
+            .map_err(|_| UserError::InvalidEmail)?
+            .to_string()
+            .to_lowercase();
+        let taken = self.users.get(&email).is_some_and(|u| Some(&u.id) != id);
+        if taken {
+            return Err(UserError::EmailTaken);
+        }
+        Ok(email)
+    }
+
+    pub fn find_by_email(&self, email: &str) -> Option<&User> {
+        self.users.get(&email_key(email))
+    }
+
+    pub fn find_by_id(&self, id: &Uuid) -> Option<&User> {
+        self.users.values().find(|u| u.id == *id)
+    }
+
+    pub fn set_role(&mut self, email: &str, role: Role) -> Result<(), UserError> {
+        let user = self
+            .users
+            .get_mut(&email_key(email))
+            .ok_or(UserError::NotFound)?;
+        user.role = role;
+        info!("Set role of {email} to {role:?}");
         Ok(())
     }
 
+    /// Marks an address verified without a token, for seeded accounts.
+    pub fn set_verified(&mut self, email: &str) -> Result<(), UserError> {
+        let user = self
+            .users
+            .get_mut(&email_key(email))
+            .ok_or(UserError::NotFound)?;
+        user.verified = true;
+        Ok(())
+    }
+
+    /// Marks the user a verification token was issued for as verified, as
+    /// long as it was sent to their current address.
+    pub fn verify_email(
+        &mut self,
+        verifier: &EmailVerifier,
+        token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<User, VerifyError> {
+        let verified = verifier.verify(token, now)?;
+        let user = self
+            .users
+            .get_mut(&verified.email)
+            .filter(|user| user.id == verified.user_id)
+            .ok_or(VerifyError::Stale)?;
+        user.verified = true;
+        info!("Verified email for {}", user.username);
+        Ok(user.clone())
+    }
+
+    fn find_mut(&mut self, id: &Uuid) -> Result<&mut User, UserError> {
+        self.users
+            .values_mut()
+            .find(|u| u.id == *id)
+            .ok_or(UserError::NotFound)
+    }
+
+    pub fn change_username(&mut self, id: &Uuid, username: &str) -> Result<User, UserError> {
+        let username = self.check_username(username, Some(id))?;
+        let user = self.find_mut(id)?;
+        info!("Renamed {} to {username}", user.username);
+        user.username = username.to_string();
+        Ok(user.clone())
+    }
+
+    /// Moves the account to a new address, which has to be verified again.
+    pub fn change_email(&mut self, id: &Uuid, email: &str) -> Result<User, UserError> {
+        let email = self.check_email(email, Some(id))?;
+        let old_email = self.find_mut(id)?.email.clone();
+        if old_email == email {
+            return self.find_by_id(id).cloned().ok_or(UserError::NotFound);
+        }
+
+        let mut user = self.users.remove(&old_email).ok_or(UserError::NotFound)?;
+        user.email = email;
+        user.verified = false;
+        info!("Changed email for {}", user.username);
+        self.users.insert(user.email.clone(), user.clone());
+        Ok(user)
+    }
+
+    /// Changes the password. Like a reset, this ends sessions logged in with
+    /// the old one, so callers should log the user in again.
+    pub fn change_password(
+        &mut self,
+        id: &Uuid,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<User, UserError> {
+        let user = self.find_mut(id)?;
+        user.confirm_password(current_password)?;
+        check_password(new_password)?;
+        user.password = hash_password(new_password)?;
+        info!("Changed password for {}", user.username);
+        Ok(user.clone())
+    }
+
+    /// Removes the account. Anonymising what refers to it is up to the
+    /// caller.
+    pub fn delete(&mut self, id: &Uuid, password: &str) -> Result<User, UserError> {
+        let user = self.find_mut(id)?;
+        user.confirm_password(password)?;
+        let email = user.email.clone();
+        let user = self.users.remove(&email).ok_or(UserError::NotFound)?;
+        self.identities.retain(|_, id| *id != user.id);
+        info!("Deleted account for {}", user.username);
+        Ok(user)
+    }
+
+    /// A reset token for the account registered to `email`, if there is one.
+    pub fn request_password_reset(
+        &mut self,
+        email: &str,
+        now: DateTime<Utc>,
+    ) -> Option<(User, String)> {
+        let user = self.users.get(&email_key(email))?.clone();
+        let token = self.resets.issue(user.id, now);
+        info!("Issued password reset for {}", user.username);
+        Some((user, token))
+    }
+
+    /// Sets a new password using a token from `request_password_reset`.
+    /// Changing the hash also ends every session logged in with the old one.
+    pub fn reset_password(
+        &mut self,
+        token: &str,
+        password: &str,
+        now: DateTime<Utc>,
+    ) -> Result<User, ResetError> {
+        // Checked first so a rejected password doesn't use up the link.
+        check_password(password)?;
+        let user_id = self.resets.redeem(token, now)?;
+        let user = self
+            .users
+            .values_mut()
+            .find(|u| u.id == user_id)
+            .ok_or(ResetError::Invalid)?;
+        user.password = hash_password(password)?;
+        // The link arrived, so the address is good.
+        user.verified = true;
+        info!("Reset password for {}", user.username);
+        Ok(user.clone())
+    }
+
+    /// Starts setting up an authenticator app, carrying on with a setup
+    /// that wasn't finished.
+    pub fn begin_two_factor(&mut self, id: &Uuid) -> Result<TwoFactorSetup, UserError> {
+        let user = self.find_mut(id)?;
+        if user.two_factor_enabled {
+            return Err(UserError::TwoFactorOn);
+        }
+        let two_factor = user.two_factor.get_or_insert_with(TwoFactor::new);
+        Ok(two_factor.setup(&user.email))
+    }
+
+    /// Turns 2FA on once a first code shows the app works, returning the
+    /// recovery codes to show the user.
+    pub fn confirm_two_factor(
+        &mut self,
+        id: &Uuid,
+        code: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(User, Vec<String>), UserError> {
+        let user = self.find_mut(id)?;
+        if user.two_factor_enabled {
+            return Err(UserError::TwoFactorOn);
+        }
+        let two_factor = user.two_factor.as_mut().ok_or(UserError::TwoFactorOff)?;
+        let codes = two_factor
+            .confirm(code, now)
+            .ok_or(UserError::InvalidCode)?;
+        user.two_factor_enabled = true;
+        info!("Turned on two-factor authentication for {}", user.username);
+        Ok((user.clone(), codes))
+    }
+
+    /// The second login step, taking an app code or a recovery code.
+    pub fn check_two_factor(
+        &mut self,
+        id: &Uuid,
+        code: &str,
+        now: DateTime<Utc>,
+    ) -> Result<User, UserError> {
+        let user = self.find_mut(id)?;
+        let two_factor = user
+            .two_factor
+            .as_mut()
+            .filter(|two_factor| two_factor.is_confirmed())
+            .ok_or(UserError::TwoFactorOff)?;
+        if !two_factor.check(code, now) {
+            return Err(UserError::InvalidCode);
+        }
+        Ok(user.clone())
+    }
+
+    /// Replaces the recovery codes after checking the password.
+    pub fn new_recovery_codes(
+        &mut self,
+        id: &Uuid,
+        password: &str,
+    ) -> Result<Vec<String>, UserError> {
+        let user = self.find_mut(id)?;
+        user.confirm_password(password)?;
+        let two_factor = user
+            .two_factor
+            .as_mut()
+            .filter(|two_factor| two_factor.is_confirmed())
+            .ok_or(UserError::TwoFactorOff)?;
+        info!("Issued new recovery codes for {}", user.username);
+        Ok(two_factor.issue_recovery_codes())
+    }
+
+    /// Turns 2FA off after checking the password, unless it is required.
+    pub fn disable_two_factor(&mut self, id: &Uuid, password: &str) -> Result<User, UserError> {
+        let required = self.staff_two_factor_required;
+        let user = self.find_mut(id)?;
+        user.confirm_password(password)?;
+        if !user.two_factor_enabled {
+            return Err(UserError::TwoFactorOff);
+        }
+        if required && user.role.is_staff() {
+            return Err(UserError::TwoFactorRequired);
+        }
+        user.two_factor = None;
+        user.two_factor_enabled = false;
+        info!("Turned off two-factor authentication for {}", user.username);
+        Ok(user.clone())
+    }
+
+    pub fn staff_two_factor_required(&self) -> bool {
+        self.staff_two_factor_required
+    }
+
+    pub fn set_staff_two_factor_required(&mut self, required: bool) {
+        self.staff_two_factor_required = required;
+        info!("Set two-factor authentication required for staff to {required}");
+    }
+
+    /// Whether `user` has to set up 2FA before managing the store.
+    pub fn needs_two_factor(&self, user: &User) -> bool {
+        self.staff_two_factor_required && user.role.is_staff() && !user.two_factor_enabled
+    }
+
+    /// The account an external login belongs to. The first time it is used
+    /// it is linked to the account with the same address, if the provider
+    /// verified it, or else a new account is made. New accounts get a random
+    /// password, which a password reset replaces.
+    pub fn login_external(&mut self, profile: &ExternalProfile) -> Result<User, UserError> {
+        if let Some(id) = self.identities.get(&profile.identity) {
+            return self.find_by_id(id).cloned().ok_or(UserError::NotFound);
+        }
+
+        let email = profile.email.as_deref().ok_or(UserError::InvalidEmail)?;
+        let existing = self.users.get(&email_key(email)).map(|u| u.id);
+        if let Some(id) = existing {
+            // Otherwise anyone could claim an account by giving its address.
+            if !profile.email_verified {
+                return Err(UserError::EmailTaken);
+            }
+            self.link_identity(&id, profile.identity.clone())?;
+            let user = self.find_mut(&id)?;
+            user.verified = true;
+            return Ok(user.clone());
+        }
+
+        let email = self.check_email(email, None)?;
+        let base = profile
+            .username
+            .as_deref()
+            .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+        let username = self.free_username(base);
+        let password = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+        let mut user = User::new(username, email, password)?;
+        user.verified = profile.email_verified;
+        self.users.insert(user.email.clone(), user.clone());
+        self.identities.insert(profile.identity.clone(), user.id);
+        info!("Linked {} to {}", profile.identity.issuer, user.username);
+        Ok(user)
+    }
+
+    /// Lets the user log in with an external login as well.
+    pub fn link_identity(
+        &mut self,
+        id: &Uuid,
+        identity: ExternalIdentity,
+    ) -> Result<User, UserError> {
+        let user = self.find_by_id(id).cloned().ok_or(UserError::NotFound)?;
+        match self.identities.get(&identity) {
+            Some(linked) if linked != id => return Err(UserError::IdentityLinked),
+            Some(_) => {}
+            None => {
+                info!("Linked {} to {}", identity.issuer, user.username);
+                self.identities.insert(identity, user.id);
+            }
+        }
+        Ok(user)
+    }
+
+    /// The external logins linked to the user.
+    pub fn identities(&self, id: &Uuid) -> Vec<ExternalIdentity> {
+        self.identities
+            .iter()
+            .filter(|(_, user_id)| *user_id == id)
+            .map(|(identity, _)| identity.clone())
+            .collect()
+    }
+
+    /// `username`, or failing that the first free one numbered after it.
+    fn free_username(&self, username: &str) -> String {
+        let base: String = username
+            .trim()
+            .chars()
+            .take(MAX_USERNAME_LENGTH - 4)
+            .collect();
+        let base = if base.is_empty() {
+            "user".to_string()
+        } else {
+            base
+        };
+        std::iter::once(base.clone())
+            .chain((2..).map(|n| format!("{base}{n}")))
+            .find(|username| self.check_username(username, None).is_ok())
+            .expect("a free username")
+    }
+
     pub fn validate(&self, username: String, password: String) -> Result<(), LoginError> {
         let Some(stored_user) = self.users.get(&username) else {
             return Err(LoginError::InvalidUsername);
@@ -190,3 +739,171 @@ impl UserStore {
         Ok(())
     }
 }
+
+/// A signup for tests, with password "password1" and the address's local
+/// part as username.
+#[cfg(test)]
+pub(crate) fn signup(email: &str) -> Signup {
+    Signup {
+        email: email.to_string(),
+        password: "password1".to_string(),
+        username: email.split('@').next().unwrap().to_string(),
+    }
+}
+
+/// Signs up a user with a verified address, for tests.
+#[cfg(test)]
+pub(crate) fn verified_user(users: &mut UserStore, email: &str) -> User {
+    let user = users.add(signup(email)).unwrap();
+    users.set_verified(&user.email).unwrap();
+    users.find_by_email(&user.email).unwrap().clone()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn signup_rejects_duplicates_and_weak_passwords() {
+        let mut users = UserStore::default();
+        users.add(signup("fern@example.com")).unwrap();
+
+        let Err(UserError::InvalidSignup(errors)) = users.add(Signup {
+            email: "FERN@example.com".to_string(),
+            password: "password".to_string(),
+            username: " Fern ".to_string(),
+        }) else {
+            panic!("duplicate signup was accepted");
+        };
+        assert_eq!(
+            errors.username.as_deref(),
+            Some("username is already taken")
+        );
+        assert_eq!(
+            errors.email.as_deref(),
+            Some("email address is already in use")
+        );
+        assert!(errors.password.is_some());
+
+        assert!(check_password("short1").is_err());
+        assert!(check_password("12345678").is_err());
+        assert!(check_password("correct horse").is_ok());
+    }
+
+    #[test]
+    fn addresses_match_whatever_their_case() {
+        let users = UserBackend::new();
+        let id = users
+            .lock()
+            .unwrap()
+            .add(signup("Fern@Example.com"))
+            .unwrap()
+            .id;
+
+        let mut user_store = users.lock().unwrap();
+        let user = user_store.find_by_email(" FERN@example.COM ").unwrap();
+        assert_eq!(user.email, "fern@example.com");
+        user_store.set_verified("FERN@EXAMPLE.COM").unwrap();
+        assert!(user_store
+            .request_password_reset("fern@EXAMPLE.com", Utc::now())
+            .is_some());
+        let user = user_store.change_email(&id, "Fern@Plants.example").unwrap();
+        assert_eq!(user.email, "fern@plants.example");
+        assert!(matches!(
+            user_store.add(signup("FERN@plants.EXAMPLE")),
+            Err(UserError::InvalidSignup(_))
+        ));
+        drop(user_store);
+
+        let credentials = Credentials {
+            email: "FERN@plants.example".to_string(),
+            password: "password1".to_string(),
+            remember: false,
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let user = runtime.block_on(users.authenticate(credentials)).unwrap();
+        assert_eq!(user.map(|u| u.id), Some(id));
+    }
+
+    #[test]
+    fn changing_email_requires_reverification() {
+        let mut users = UserStore::default();
+        users.add(signup("fern@example.com")).unwrap();
+        users.add(signup("moss@example.com")).unwrap();
+        users.set_verified("fern@example.com").unwrap();
+        let id = users.find_by_email("fern@example.com").unwrap().id;
+
+        assert!(matches!(
+            users.change_email(&id, "moss@example.com"),
+            Err(UserError::EmailTaken)
+        ));
+        assert!(matches!(
+            users.change_email(&id, "fern"),
+            Err(UserError::InvalidEmail)
+        ));
+
+        let user = users.change_email(&id, "fern@plants.example").unwrap();
+        assert!(!user.verified);
+        assert!(users.find_by_email("fern@example.com").is_none());
+        assert_eq!(users.find_by_email("fern@plants.example").unwrap().id, id);
+
+        assert!(matches!(
+            users.change_password(&id, "wrong", "new password"),
+            Err(UserError::WrongPassword)
+        ));
+        users
+            .change_password(&id, "password1", "new password")
+            .unwrap();
+        assert!(matches!(
+            users.delete(&id, "password1"),
+            Err(UserError::WrongPassword)
+        ));
+        users.delete(&id, "new password").unwrap();
+        assert!(users.find_by_id(&id).is_none());
+    }
+
+    #[test]
+    fn external_logins_link_only_verified_addresses() {
+        let mut users = UserStore::default();
+        users.add(signup("fern@example.com")).unwrap();
+        let fern = users.find_by_email("fern@example.com").unwrap().id;
+        let profile = |subject: &str, email: &str, email_verified| ExternalProfile {
+            identity: ExternalIdentity {
+                issuer: "https://id.example.com".to_string(),
+                subject: subject.to_string(),
+            },
+            email: Some(email.to_string()),
+            email_verified,
+            username: Some("fern".to_string()),
+        };
+
+        assert!(matches!(
+            users.login_external(&profile("1", "FERN@example.com", false)),
+            Err(UserError::EmailTaken)
+        ));
+        let user = users
+            .login_external(&profile("1", "FERN@example.com", true))
+            .unwrap();
+        assert_eq!(user.id, fern);
+        assert!(user.verified);
+
+        // Later logins find the account by subject, whatever the address.
+        let user = users
+            .login_external(&profile("1", "other@example.com", false))
+            .unwrap();
+        assert_eq!(user.id, fern);
+
+        let new = users
+            .login_external(&profile("2", "moss@example.com", false))
+            .unwrap();
+        assert_eq!(new.username, "fern2");
+        assert!(!new.verified);
+        assert!(matches!(
+            users.link_identity(&fern, profile("2", "", true).identity),
+            Err(UserError::IdentityLinked)
+        ));
+        assert_eq!(users.identities(&fern).len(), 1);
+    }
+}
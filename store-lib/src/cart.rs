@@ -3,7 +3,7 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use crate::store::Product;
+use crate::store::{InventoryBackend, InventoryError, Product, Variant};
 use bigdecimal::{num_bigint::BigInt, BigDecimal};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
@@ -18,6 +18,70 @@ impl CartBackend {
             carts: HashMap::new(),
         })))
     }
+
+    pub fn cart(&self, user_id: &Uuid) -> Cart {
+        let cart_store = self.lock().expect("cart backend threads");
+        cart_store.carts.get(user_id).cloned().unwrap_or_default()
+    }
+
+    /// Adds `number` of a listing to a user's cart, using the listing's
+    /// default variant unless `variant_id` picks another. The cart never
+    /// holds more of a variant than is free in stock.
+    pub fn add(
+        &self,
+        user_id: Uuid,
+        inventory: &InventoryBackend,
+        listing_id: &Uuid,
+        variant_id: Option<Uuid>,
+        number: usize,
+    ) -> Result<Cart, InventoryError> {
+        let listing = inventory
+            .product(listing_id)
+            .ok_or(InventoryError::NotFound)?;
+        let variant = match variant_id {
+            Some(id) => listing.variant(&id).ok_or(InventoryError::NotFound)?,
+            None => listing.default_variant(),
+        }
+        .clone();
+
+        let mut cart_store = self.lock().expect("cart backend threads");
+        let cart = cart_store.carts.entry(user_id).or_default();
+
+        let in_cart = cart.items.get(&variant.variant_id).map_or(0, |i| i.number);
+        let free = inventory.stock(&variant.variant_id).map_or(0, |s| s.free);
+        if in_cart + number > free {
+            return Err(InventoryError::InsufficientStock);
+        }
+
+        cart.items
+            .entry(variant.variant_id)
+            .and_modify(|item| item.number += number)
+            .or_insert(CartItem {
+                listing,
+                variant,
+                number,
+            });
+
+        Ok(cart.clone())
+    }
+
+    pub fn remove(&self, user_id: Uuid, variant_id: &Uuid) -> Cart {
+        let mut cart_store = self.lock().expect("cart backend threads");
+        let cart = cart_store.carts.entry(user_id).or_default();
+        cart.items.remove(variant_id);
+        cart.clone()
+    }
+
+    pub fn clear(&self, user_id: &Uuid) {
+        let mut cart_store = self.lock().expect("cart backend threads");
+        cart_store.carts.remove(user_id);
+    }
+}
+
+impl Default for CartBackend {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct CartStore {
@@ -35,21 +99,31 @@ impl std::ops::Deref for CartBackend {
 #[derive(Deserialize, Serialize, Clone, TS)]
 pub struct CartItem {
     pub listing: Product,
+    pub variant: Variant,
     pub number: usize,
 }
 
-#[derive(Serialize, Clone, TS)]
-#[ts(export)]
-pub struct Cart {
-    pub items: HashMap<Uuid, CartItem>,
-}
-
-impl Default for Cart {
-    fn default() -> Self {
+impl CartItem {
+    /// A line for the listing's default variant.
+    pub fn new(listing: Product, number: usize) -> Self {
+        let variant = listing.default_variant().clone();
         Self {
-            items: HashMap::new(),
+            listing,
+            variant,
+            number,
         }
     }
+
+    pub fn total(&self) -> BigDecimal {
+        &self.variant.price * BigDecimal::from(self.number as u64)
+    }
+}
+
+/// Items are keyed by variant id.
+#[derive(Serialize, Clone, Default, TS)]
+#[ts(export)]
+pub struct Cart {
+    pub items: HashMap<Uuid, CartItem>,
 }
 
 impl Cart {
@@ -61,7 +135,51 @@ impl Cart {
         self.items
             .values()
             .fold(BigDecimal::new(BigInt::ZERO, 2), |acc, item| {
-                acc + item.listing.price.clone()
+                acc + item.total()
             })
     }
+
+    /// Number of units across all lines.
+    pub fn count(&self) -> usize {
+        self.items.values().map(|item| item.number).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::Inventory;
+
+    #[test]
+    fn cart_lines_are_per_variant_and_capped_by_stock() {
+        let inventory = InventoryBackend::new();
+        let carts = CartBackend::new();
+        let user = Uuid::new_v4();
+
+        let mut product = Product::random();
+        let listing_id = product.listing_id;
+        product.variants.truncate(1);
+        let large = Variant {
+            variant_id: Uuid::new_v4(),
+            price: BigDecimal::from(40),
+            ..product.default_variant().clone()
+        };
+        product.variants.push(large.clone());
+        inventory.add_product(product, Inventory::new(2, 0, 0));
+
+        carts.add(user, &inventory, &listing_id, None, 2).unwrap();
+        assert!(matches!(
+            carts.add(user, &inventory, &listing_id, None, 1),
+            Err(InventoryError::InsufficientStock)
+        ));
+
+        let cart = carts
+            .add(user, &inventory, &listing_id, Some(large.variant_id), 1)
+            .unwrap();
+        assert_eq!(cart.items.len(), 2);
+        assert_eq!(cart.count(), 3);
+
+        let cart = carts.remove(user, &listing_id);
+        assert_eq!(cart.subtotal(), BigDecimal::from(40));
+    }
 }
@@ -0,0 +1,337 @@
+use std::collections::HashSet;
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Utc;
+use serde::{Deserialize, Deserializer};
+use uuid::Uuid;
+
+use crate::{
+    account::{Role, Signup, UserBackend, UserError},
+    store::{
+        Inventory, InventoryBackend, Product, ProductSpecs, Variant, VariantOption,
+        DEFAULT_LOW_STOCK_THRESHOLD,
+    },
+};
+
+/// Users, categories and products to load into the stores, read from YAML
+/// or JSON. Loading is idempotent: users already there, matched by email,
+/// and products already there, matched by their first SKU, are skipped.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fixture {
+    pub users: Vec<UserFixture>,
+    /// Categories products may use. Empty allows any.
+    pub categories: Vec<String>,
+    pub products: Vec<ProductFixture>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UserFixture {
+    pub email: String,
+    pub username: String,
+    pub password: String,
+    #[serde(default = "customer")]
+    pub role: Role,
+    /// Skip email verification.
+    #[serde(default)]
+    pub verified: bool,
+}
+
+fn customer() -> Role {
+    Role::Customer
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ProductFixture {
+    /// Keeps product links stable between loads. Random if left out.
+    pub id: Option<Uuid>,
+    pub name: String,
+    pub category: String,
+    #[serde(default)]
+    pub description: String,
+    pub image: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub specs: ProductSpecs,
+    /// At least one. The first is the product's default.
+    pub variants: Vec<VariantFixture>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct VariantFixture {
+    pub sku: String,
+    #[serde(default)]
+    pub options: Vec<VariantOption>,
+    #[serde(deserialize_with = "decimal")]
+    pub price: BigDecimal,
+    #[serde(default)]
+    pub stock: StockFixture,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct StockFixture {
+    pub free: usize,
+    pub low_stock_threshold: usize,
+}
+
+impl Default for StockFixture {
+    fn default() -> Self {
+        Self {
+            free: 0,
+            low_stock_threshold: DEFAULT_LOW_STOCK_THRESHOLD,
+        }
+    }
+}
+
+/// What loading a fixture changed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    pub users_added: usize,
+    pub users_skipped: usize,
+    pub products_added: usize,
+    pub products_skipped: usize,
+}
+
+impl std::fmt::Display for SeedReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "added {} users and {} products, skipped {} users and {} products already there",
+            self.users_added, self.products_added, self.users_skipped, self.products_skipped
+        )
+    }
+}
+
+impl Fixture {
+    pub fn from_yaml(text: &str) -> Result<Self, FixtureError> {
+        serde_yaml::from_str(text).map_err(|e| FixtureError::Parse(e.to_string()))
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, FixtureError> {
+        serde_json::from_str(text).map_err(|e| FixtureError::Parse(e.to_string()))
+    }
+
+    /// Finds mistakes that don't depend on what is already in the stores,
+    /// so nothing is loaded from a broken fixture.
+    pub fn check(&self) -> Result<(), FixtureError> {
+        let invalid = |message: String| Err(FixtureError::Invalid(message));
+        let mut skus = HashSet::new();
+        for product in &self.products {
+            if !self.categories.is_empty() && !self.categories.contains(&product.category) {
+                return invalid(format!(
+                    "{} is in unknown category {}",
+                    product.name, product.category
+                ));
+            }
+            if product.variants.is_empty() {
+                return invalid(format!("{} has no variants", product.name));
+            }
+            for variant in &product.variants {
+                if !skus.insert(variant.sku.as_str()) {
+                    return invalid(format!("SKU {} is used twice", variant.sku));
+                }
+                if variant.price < BigDecimal::zero() {
+                    return invalid(format!("SKU {} has a negative price", variant.sku));
+                }
+                if variant.price != variant.price.with_scale(2) {
+                    return invalid(format!("SKU {} has a price in part cents", variant.sku));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply(
+        &self,
+        users: &UserBackend,
+        inventory: &InventoryBackend,
+    ) -> Result<SeedReport, FixtureError> {
+        self.check()?;
+        let mut report = SeedReport::default();
+
+        {
+            let mut users = users.lock().expect("user store threading issue");
+            for user in &self.users {
+                if users.find_by_email(user.email.trim()).is_some() {
+                    report.users_skipped += 1;
+                    continue;
+                }
+                let error = |e| FixtureError::User(user.email.clone(), e);
+                let added = users
+                    .add(Signup {
+                        email: user.email.clone(),
+                        password: user.password.clone(),
+                        username: user.username.clone(),
+                    })
+                    .map_err(error)?;
+                users.set_role(&added.email, user.role).map_err(error)?;
+                if user.verified {
+                    users.set_verified(&added.email).map_err(error)?;
+                }
+                report.users_added += 1;
+            }
+        }
+
+        let existing: HashSet<String> = inventory
+            .stock_levels()
+            .into_iter()
+            .map(|(_, variant, _)| variant.sku)
+            .collect();
+        for product in &self.products {
+            let listed = product
+                .id
+                .is_some_and(|id| inventory.product(&id).is_some());
+            if listed || existing.contains(&product.variants[0].sku) {
+                report.products_skipped += 1;
+                continue;
+            }
+            if product.variants.iter().any(|v| existing.contains(&v.sku)) {
+                return Err(FixtureError::Invalid(format!(
+                    "{} shares a SKU with another product",
+                    product.name
+                )));
+            }
+            let (product, stock) = product.build();
+            inventory.add_product_with_stock(product, stock);
+            report.products_added += 1;
+        }
+        Ok(report)
+    }
+}
+
+impl ProductFixture {
+    /// The product, listed from now, and the stock of each variant.
+    fn build(&self) -> (Product, Vec<Inventory>) {
+        let listing_id = self.id.unwrap_or_else(Uuid::new_v4);
+        let variants: Vec<Variant> = self
+            .variants
+            .iter()
+            .enumerate()
+            .map(|(i, variant)| Variant {
+                variant_id: if i == 0 { listing_id } else { Uuid::new_v4() },
+                sku: variant.sku.clone(),
+                options: variant.options.clone(),
+                // Whole cents, as prices are shown as written.
+                price: variant.price.with_scale(2),
+            })
+            .collect();
+        let stock = self
+            .variants
+            .iter()
+            .map(|variant| Inventory {
+                low_stock_threshold: variant.stock.low_stock_threshold,
+                ..Inventory::new(variant.stock.free, 0, 0)
+            })
+            .collect();
+
+        let product = Product {
+            listing_id,
+            name: self.name.clone(),
+            price: variants
+                .iter()
+                .map(|variant| variant.price.clone())
+                .min()
+                .unwrap_or_default(),
+            description: self.description.clone(),
+            image: self.image.clone(),
+            category: self.category.clone(),
+            tags: self.tags.clone(),
+            listed_at: Utc::now(),
+            specs: self.specs.clone(),
+            variants,
+        };
+        (product, stock)
+    }
+}
+
+/// Amounts written as strings or numbers. Numbers are read as written
+/// rather than through a float, so 6.99 stays exact.
+pub fn decimal<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigDecimal, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Text(String),
+        Integer(i64),
+        Float(f64),
+    }
+
+    let text = match Amount::deserialize(deserializer)? {
+        Amount::Text(text) => text,
+        Amount::Integer(integer) => integer.to_string(),
+        Amount::Float(float) => float.to_string(),
+    };
+    text.trim().parse().map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug)]
+pub enum FixtureError {
+    Parse(String),
+    Invalid(String),
+    /// A user couldn't be added, such as for a weak password.
+    User(String, UserError),
+}
+
+impl std::fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FixtureError::Parse(e) => write!(f, "couldn't read fixture: {e}"),
+            FixtureError::Invalid(e) => write!(f, "invalid fixture: {e}"),
+            FixtureError::User(email, e) => write!(f, "couldn't add {email}: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FixtureError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FIXTURE: &str = "
+users:
+  - email: admin@example.com
+    username: admin
+    password: password1
+    role: Admin
+    verified: true
+categories: [Rocks]
+products:
+  - name: Amethyst
+    category: Rocks
+    image: amethyst.jpg
+    variants:
+      - sku: AMETHYST-TUMBLED
+        price: 12.99
+        stock: { free: 4 }
+      - sku: AMETHYST-GEODE
+        price: 45
+";
+
+    #[test]
+    fn loading_twice_adds_nothing_more() {
+        let users = UserBackend::new();
+        let inventory = InventoryBackend::new();
+        let fixture = Fixture::from_yaml(FIXTURE).unwrap();
+
+        let report = fixture.apply(&users, &inventory).unwrap();
+        assert_eq!(report.users_added, 1);
+        assert_eq!(report.products_added, 1);
+        let again = fixture.apply(&users, &inventory).unwrap();
+        assert_eq!(again.users_skipped, 1);
+        assert_eq!(again.products_skipped, 1);
+
+        let stock = inventory.stock_levels();
+        assert_eq!(stock.len(), 2);
+        let (product, _, tumbled) = &stock[0];
+        assert_eq!(product.price.to_string(), "12.99");
+        assert_eq!(tumbled.free, 4);
+        let admin = users.lock().unwrap();
+        let admin = admin.find_by_email("admin@example.com").unwrap();
+        assert!(admin.is_admin() && admin.verified);
+    }
+}
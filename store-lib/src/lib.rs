@@ -1,3 +1,19 @@
 pub mod account;
 pub mod cart;
+pub mod fixture;
+pub mod listing;
+pub mod lockout;
+pub mod mail;
+pub mod mock_idp;
+pub mod oidc;
+pub mod order;
+pub mod outbox;
+pub mod password_reset;
+pub mod restock;
+pub mod review;
+pub mod search;
+pub mod session_store;
 pub mod store;
+pub mod two_factor;
+pub mod verification;
+pub mod wishlist;
@@ -0,0 +1,439 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use ts_rs::TS;
+
+use crate::store::{Inventory, InventoryBackend, Product};
+
+pub const DEFAULT_PAGE_SIZE: usize = 12;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Deserialize, Serialize, Clone, TS)]
+#[ts(export)]
+pub struct ListingQuery {
+    #[serde(default = "ListingQuery::first_page")]
+    pub page: usize,
+    #[serde(default = "ListingQuery::default_page_size")]
+    pub per_page: usize,
+    #[serde(default)]
+    pub q: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub min_price: Option<BigDecimal>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub max_price: Option<BigDecimal>,
+    #[serde(default)]
+    pub in_stock: bool,
+    #[serde(default)]
+    pub sort: Option<ListingSort>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum ListingSort {
+    Relevance,
+    Name,
+    PriceAsc,
+    PriceDesc,
+    Newest,
+    BestSelling,
+}
+
+impl ListingSort {
+    pub const ALL: [ListingSort; 6] = [
+        ListingSort::Relevance,
+        ListingSort::Name,
+        ListingSort::PriceAsc,
+        ListingSort::PriceDesc,
+        ListingSort::Newest,
+        ListingSort::BestSelling,
+    ];
+
+    /// The query string value, matching the serde representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListingSort::Relevance => "relevance",
+            ListingSort::Name => "name",
+            ListingSort::PriceAsc => "price_asc",
+            ListingSort::PriceDesc => "price_desc",
+            ListingSort::Newest => "newest",
+            ListingSort::BestSelling => "best_selling",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ListingSort::Relevance => "Relevance",
+            ListingSort::Name => "Name",
+            ListingSort::PriceAsc => "Price: Low to High",
+            ListingSort::PriceDesc => "Price: High to Low",
+            ListingSort::Newest => "Newest",
+            ListingSort::BestSelling => "Best Selling",
+        }
+    }
+}
+
+impl ListingQuery {
+    fn first_page() -> usize {
+        1
+    }
+
+    fn default_page_size() -> usize {
+        DEFAULT_PAGE_SIZE
+    }
+
+    pub fn page(page: usize) -> Self {
+        Self {
+            page,
+            ..Self::default()
+        }
+    }
+
+    pub fn search_terms(&self) -> Option<&str> {
+        self.q.as_deref().map(str::trim).filter(|q| !q.is_empty())
+    }
+
+    /// Searches default to relevance, browsing to name. Relevance without a
+    /// search falls back to name as well.
+    pub fn sort(&self) -> ListingSort {
+        match (self.sort, self.search_terms()) {
+            (Some(ListingSort::Relevance), None) | (None, None) => ListingSort::Name,
+            (Some(sort), _) => sort,
+            (None, Some(_)) => ListingSort::Relevance,
+        }
+    }
+
+    fn category(&self) -> Option<&str> {
+        self.category.as_deref().filter(|c| !c.is_empty())
+    }
+
+    fn tag(&self) -> Option<&str> {
+        self.tag.as_deref().filter(|t| !t.is_empty())
+    }
+
+    /// Whether a product passes every filter except `skip`, so facet counts
+    /// show what selecting another value of that facet would return.
+    fn matches(&self, product: &Product, stock: Option<&Inventory>, skip: Option<Facet>) -> bool {
+        let category = skip == Some(Facet::Category)
+            || self
+                .category()
+                .is_none_or(|c| product.category.eq_ignore_ascii_case(c));
+        let tag = skip == Some(Facet::Tag)
+            || self
+                .tag()
+                .is_none_or(|t| product.tags.iter().any(|pt| pt.eq_ignore_ascii_case(t)));
+        let price = self
+            .min_price
+            .as_ref()
+            .is_none_or(|min| product.price >= *min)
+            && self
+                .max_price
+                .as_ref()
+                .is_none_or(|max| product.price <= *max);
+        let in_stock =
+            skip == Some(Facet::Stock) || !self.in_stock || stock.is_some_and(|s| s.free > 0);
+
+        category && tag && price && in_stock
+    }
+}
+
+impl Default for ListingQuery {
+    fn default() -> Self {
+        Self {
+            page: Self::first_page(),
+            per_page: Self::default_page_size(),
+            q: None,
+            category: None,
+            tag: None,
+            min_price: None,
+            max_price: None,
+            in_stock: false,
+            sort: None,
+        }
+    }
+}
+
+/// HTML forms submit untouched number inputs as empty strings.
+fn empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)?
+        .as_deref()
+        .map(str::trim)
+    {
+        None | Some("") => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(de::Error::custom),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Facet {
+    Category,
+    Tag,
+    Stock,
+}
+
+#[derive(Serialize, Clone, TS)]
+#[ts(export)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Clone, Default, TS)]
+#[ts(export)]
+pub struct Facets {
+    pub categories: Vec<FacetCount>,
+    pub tags: Vec<FacetCount>,
+    pub in_stock: usize,
+}
+
+#[derive(Serialize, Clone, TS)]
+#[ts(export)]
+pub struct ListingPage {
+    pub listings: Vec<Product>,
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+    pub next_page: Option<usize>,
+    pub facets: Facets,
+}
+
+impl InventoryBackend {
+    /// A stable, 1-indexed page of the catalog. Ties in the requested sort
+    /// keep name order so pages never shuffle between requests.
+    pub fn listings(&self, query: &ListingQuery) -> ListingPage {
+        let products = self.products.lock().expect("inventory backend threads");
+        let inventory = self.inventory.lock().expect("inventory backend threads");
+        let inventory = &*inventory;
+
+        let mut candidates: Vec<&Product> = products.values().collect();
+        candidates.sort_by(|a, b| a.name.cmp(&b.name).then(a.listing_id.cmp(&b.listing_id)));
+
+        let units_sold =
+            |p: &Product| Inventory::total(p, inventory).map_or(0, |s| s.ordered + s.sent);
+        match query.sort() {
+            ListingSort::Relevance | ListingSort::Name => {}
+            ListingSort::PriceAsc => candidates.sort_by(|a, b| a.price.cmp(&b.price)),
+            ListingSort::PriceDesc => candidates.sort_by(|a, b| b.price.cmp(&a.price)),
+            ListingSort::Newest => candidates.sort_by_key(|p| std::cmp::Reverse(p.listed_at)),
+            ListingSort::BestSelling => {
+                candidates.sort_by_key(|p| std::cmp::Reverse(units_sold(p)))
+            }
+        }
+
+        if let Some(q) = query.search_terms() {
+            let scores = self
+                .search
+                .lock()
+                .expect("inventory backend threads")
+                .search(q);
+            candidates.retain(|p| scores.contains_key(&p.listing_id));
+            if query.sort() == ListingSort::Relevance {
+                candidates.sort_by(|a, b| scores[&b.listing_id].total_cmp(&scores[&a.listing_id]));
+            }
+        }
+
+        let matching = |skip| {
+            candidates
+                .iter()
+                .filter(move |p| query.matches(p, Inventory::total(p, inventory).as_ref(), skip))
+        };
+
+        let mut categories = BTreeMap::<&str, usize>::new();
+        for product in matching(Some(Facet::Category)) {
+            *categories.entry(&product.category).or_default() += 1;
+        }
+
+        let mut tags = BTreeMap::<&str, usize>::new();
+        for product in matching(Some(Facet::Tag)) {
+            for tag in &product.tags {
+                *tags.entry(tag).or_default() += 1;
+            }
+        }
+
+        let in_stock = matching(Some(Facet::Stock))
+            .filter(|p| Inventory::total(p, inventory).is_some_and(|s| s.free > 0))
+            .count();
+
+        let facets = Facets {
+            categories: facet_counts(categories),
+            tags: facet_counts(tags),
+            in_stock,
+        };
+
+        let listings: Vec<&&Product> = matching(None).collect();
+        let per_page = query.per_page.clamp(1, MAX_PAGE_SIZE);
+        let page = query.page.max(1);
+        let total = listings.len();
+        let listings = listings
+            .into_iter()
+            .skip((page - 1).saturating_mul(per_page))
+            .take(per_page)
+            .map(|p| (*p).clone())
+            .collect();
+
+        ListingPage {
+            listings,
+            page,
+            per_page,
+            total,
+            next_page: page
+                .checked_add(1)
+                .filter(|_| page.saturating_mul(per_page) < total),
+            facets,
+        }
+    }
+}
+
+fn facet_counts(counts: BTreeMap<&str, usize>) -> Vec<FacetCount> {
+    counts
+        .into_iter()
+        .map(|(value, count)| FacetCount {
+            value: value.to_string(),
+            count,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::Variant;
+    use std::collections::HashSet;
+    use uuid::Uuid;
+
+    #[test]
+    fn listings_page_through_catalog_once() {
+        let backend = InventoryBackend::new();
+        for product in (0..25).map(|_| Product::random()) {
+            backend.add_product(product, Inventory::new(1, 0, 0));
+        }
+
+        let mut seen = Vec::new();
+        let mut query = ListingQuery::page(1);
+        loop {
+            let page = backend.listings(&query);
+            seen.extend(page.listings.into_iter().map(|p| p.listing_id));
+            match page.next_page {
+                Some(next) => query.page = next,
+                None => break,
+            }
+        }
+
+        let unique: HashSet<Uuid> = seen.iter().copied().collect();
+        assert_eq!(seen.len(), 25);
+        assert_eq!(unique.len(), 25);
+        assert_eq!(query.page, 3);
+    }
+
+    #[test]
+    fn huge_page_numbers_are_empty() {
+        let backend = InventoryBackend::new();
+        backend.add_product(Product::random(), Inventory::new(1, 0, 0));
+
+        let page = backend.listings(&ListingQuery::page(usize::MAX));
+        assert!(page.listings.is_empty());
+        assert_eq!(page.total, 1);
+        assert_eq!(page.next_page, None);
+    }
+
+    #[test]
+    fn sorts_by_price_and_sales() {
+        let backend = InventoryBackend::new();
+        // Sales of every variant count, not just the default one.
+        for (price, sold) in [(30, vec![1]), (10, vec![0, 5]), (20, vec![0])] {
+            let mut product = Product {
+                price: BigDecimal::from(price),
+                ..Product::random()
+            };
+            let first = product.default_variant().clone();
+            product.variants = (0..sold.len())
+                .map(|i| Variant {
+                    variant_id: if i == 0 {
+                        first.variant_id
+                    } else {
+                        Uuid::new_v4()
+                    },
+                    ..first.clone()
+                })
+                .collect();
+            let stock = sold
+                .iter()
+                .map(|&sold| Inventory::new(1, sold, 0))
+                .collect();
+            backend.add_product_with_stock(product, stock);
+        }
+
+        let prices = |sort| {
+            let query = ListingQuery {
+                sort: Some(sort),
+                ..ListingQuery::default()
+            };
+            backend
+                .listings(&query)
+                .listings
+                .into_iter()
+                .map(|p| p.price)
+                .collect::<Vec<_>>()
+        };
+
+        let expected = |prices: [i64; 3]| prices.map(BigDecimal::from).to_vec();
+        assert_eq!(prices(ListingSort::PriceAsc), expected([10, 20, 30]));
+        assert_eq!(prices(ListingSort::PriceDesc), expected([30, 20, 10]));
+        assert_eq!(prices(ListingSort::BestSelling), expected([10, 30, 20]));
+    }
+
+    #[test]
+    fn facets_ignore_their_own_filter() {
+        let backend = InventoryBackend::new();
+        let product = |name: &str, category: &str, tags: &[&str], price: i64| Product {
+            name: name.to_string(),
+            category: category.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            price: BigDecimal::from(price),
+            ..Product::random()
+        };
+        backend.add_product(
+            product("Pothos", "Plants", &["Beginner"], 10),
+            Inventory::new(3, 0, 0),
+        );
+        backend.add_product(
+            product("Aroid", "Plants", &["Rare"], 40),
+            Inventory::new(0, 0, 0),
+        );
+        backend.add_product(
+            product("Ruby", "Rocks", &["Rare"], 25),
+            Inventory::new(1, 0, 0),
+        );
+
+        let query = ListingQuery {
+            category: Some("plants".to_string()),
+            ..ListingQuery::default()
+        };
+        let page = backend.listings(&query);
+        assert_eq!(page.total, 2);
+        assert_eq!(page.facets.categories.len(), 2);
+        assert_eq!(page.facets.in_stock, 1);
+
+        let query = ListingQuery {
+            tag: Some("Rare".to_string()),
+            in_stock: true,
+            max_price: Some(BigDecimal::from(30)),
+            ..ListingQuery::default()
+        };
+        let page = backend.listings(&query);
+        assert_eq!(page.total, 1);
+        assert_eq!(page.listings[0].name, "Ruby");
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Accounts or addresses tracked before the oldest are dropped.
+const MAX_ENTRIES: usize = 10_000;
+/// What a full map is cut back to, so eviction runs once per batch of
+/// inserts rather than on each one.
+const KEEP_ENTRIES: usize = MAX_ENTRIES * 3 / 4;
+
+/// When repeated login failures lock an account or address out.
+#[derive(Clone, Copy, Debug)]
+pub struct LockoutPolicy {
+    /// Failures for one account before it is locked.
+    pub max_account_failures: u32,
+    /// Failures from one address, across all accounts, before it is locked.
+    pub max_ip_failures: u32,
+    /// How long a lock lasts. Failures older than this are forgotten.
+    pub lockout: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            max_account_failures: 5,
+            max_ip_failures: 20,
+            lockout: Duration::minutes(15),
+        }
+    }
+}
+
+/// Counts failed logins per account and per client address, refusing
+/// further attempts for a while once either passes its limit. Accounts are
+/// keyed by the email tried, whether or not it is registered, so a lock
+/// doesn't reveal which addresses have accounts.
+#[derive(Clone)]
+pub struct LoginAttempts {
+    policy: LockoutPolicy,
+    failures: Arc<Mutex<AttemptStore>>,
+}
+
+#[derive(Default)]
+struct AttemptStore {
+    accounts: HashMap<String, Failures>,
+    ips: HashMap<IpAddr, Failures>,
+    next_sweep: Option<DateTime<Utc>>,
+}
+
+impl AttemptStore {
+    /// Drops expired entries once per lockout period, or sooner if either
+    /// map fills up.
+    fn prune(&mut self, policy: &LockoutPolicy, now: DateTime<Utc>) {
+        let due = self.next_sweep.is_none_or(|at| now >= at);
+        if due || self.accounts.len() >= MAX_ENTRIES || self.ips.len() >= MAX_ENTRIES {
+            prune(&mut self.accounts, policy, now);
+            prune(&mut self.ips, policy, now);
+            self.next_sweep = Some(now + policy.lockout);
+        }
+    }
+}
+
+/// Removes entries whose failures and lock have both run out. A map that
+/// was full is then cut back to `KEEP_ENTRIES`, dropping the least recently
+/// failed entries first and locked ones last.
+fn prune<K: Eq + Hash + Clone>(
+    map: &mut HashMap<K, Failures>,
+    policy: &LockoutPolicy,
+    now: DateTime<Utc>,
+) {
+    let full = map.len() >= MAX_ENTRIES;
+    map.retain(|_, f| now - f.last < policy.lockout || f.locked(now).is_some());
+    if !full || map.len() <= KEEP_ENTRIES {
+        return;
+    }
+
+    let excess = map.len() - KEEP_ENTRIES;
+    let mut oldest: Vec<_> = map
+        .iter()
+        .map(|(key, f)| (f.locked(now).is_some(), f.last, key.clone()))
+        .collect();
+    oldest.sort_unstable_by_key(|(locked, last, _)| (*locked, *last));
+    for (_, _, key) in oldest.into_iter().take(excess) {
+        map.remove(&key);
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Failures {
+    count: u32,
+    last: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl Failures {
+    fn record(
+        entry: Option<&mut Failures>,
+        max: u32,
+        policy: &LockoutPolicy,
+        now: DateTime<Utc>,
+    ) -> Failures {
+        let mut failures = match entry {
+            Some(f) if now - f.last < policy.lockout => *f,
+            _ => Failures {
+                count: 0,
+                last: now,
+                locked_until: None,
+            },
+        };
+        failures.count += 1;
+        failures.last = now;
+        if failures.count >= max {
+            failures.locked_until = Some(now + policy.lockout);
+        }
+        failures
+    }
+
+    fn locked(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.locked_until.filter(|until| *until > now)
+    }
+}
+
+fn account_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+impl LoginAttempts {
+    pub fn new(policy: LockoutPolicy) -> Self {
+        Self {
+            policy,
+            failures: Arc::new(Mutex::new(AttemptStore::default())),
+        }
+    }
+
+    /// Fails with the time the lock ends if `email` or `ip` is locked out.
+    pub fn check(&self, email: &str, ip: IpAddr, now: DateTime<Utc>) -> Result<(), Locked> {
+        let store = self.failures.lock().expect("login attempt threads");
+        let account = store
+            .accounts
+            .get(&account_key(email))
+            .and_then(|f| f.locked(now));
+        let ip = store.ips.get(&ip).and_then(|f| f.locked(now));
+
+        match account.max(ip) {
+            Some(until) => Err(Locked { until }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, email: &str, ip: IpAddr, now: DateTime<Utc>) {
+        let mut store = self.failures.lock().expect("login attempt threads");
+        store.prune(&self.policy, now);
+        let key = account_key(email);
+        let account = Failures::record(
+            store.accounts.get_mut(&key),
+            self.policy.max_account_failures,
+            &self.policy,
+            now,
+        );
+        let by_ip = Failures::record(
+            store.ips.get_mut(&ip),
+            self.policy.max_ip_failures,
+            &self.policy,
+            now,
+        );
+        store.accounts.insert(key, account);
+        store.ips.insert(ip, by_ip);
+    }
+
+    /// Clears the account's failures. The address keeps its count, so one
+    /// good login doesn't reset guessing at other accounts.
+    pub fn record_success(&self, email: &str) {
+        let mut store = self.failures.lock().expect("login attempt threads");
+        store.accounts.remove(&account_key(email));
+    }
+}
+
+impl Default for LoginAttempts {
+    fn default() -> Self {
+        Self::new(LockoutPolicy::default())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Locked {
+    pub until: DateTime<Utc>,
+}
+
+impl Locked {
+    /// Whole minutes left, rounded up, for telling the user.
+    pub fn minutes_left(&self, now: DateTime<Utc>) -> i64 {
+        let seconds = (self.until - now).num_seconds().max(0);
+        (seconds + 59) / 60
+    }
+}
+
+impl std::fmt::Display for Locked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "too many failed logins, locked until {}", self.until)
+    }
+}
+
+impl std::error::Error for Locked {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repeated_failures_lock_the_account_then_expire() {
+        let attempts = LoginAttempts::new(LockoutPolicy {
+            max_account_failures: 3,
+            max_ip_failures: 5,
+            lockout: Duration::minutes(15),
+        });
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let other_ip: IpAddr = "198.51.100.1".parse().unwrap();
+        let now = Utc::now();
+
+        for _ in 0..2 {
+            attempts.record_failure("fern@example.com", ip, now);
+        }
+        assert!(attempts.check("fern@example.com", ip, now).is_ok());
+        attempts.record_failure("FERN@example.com", ip, now);
+
+        let locked = attempts
+            .check("fern@example.com", other_ip, now)
+            .unwrap_err();
+        assert_eq!(locked.minutes_left(now), 15);
+        assert!(attempts.check("moss@example.com", other_ip, now).is_ok());
+        assert!(attempts
+            .check("fern@example.com", ip, now + Duration::minutes(16))
+            .is_ok());
+
+        // The address is locked for every account after enough failures.
+        attempts.record_failure("moss@example.com", ip, now);
+        attempts.record_failure("ivy@example.com", ip, now);
+        assert!(attempts.check("new@example.com", ip, now).is_err());
+        assert!(attempts.check("new@example.com", other_ip, now).is_ok());
+    }
+
+    #[test]
+    fn expired_entries_are_pruned_and_the_maps_capped() {
+        let attempts = LoginAttempts::new(LockoutPolicy {
+            max_account_failures: 3,
+            max_ip_failures: 3,
+            lockout: Duration::minutes(15),
+        });
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let now = Utc::now();
+        for _ in 0..3 {
+            attempts.record_failure("fern@example.com", ip, now);
+        }
+
+        // Flooding with new accounts and addresses keeps the maps bounded,
+        // and the locked account is the last thing evicted.
+        for n in 0..MAX_ENTRIES as u32 {
+            let other = IpAddr::from(n.to_be_bytes());
+            attempts.record_failure(&format!("user{n}@example.com"), other, now);
+        }
+        {
+            let store = attempts.failures.lock().unwrap();
+            assert!(store.accounts.len() <= MAX_ENTRIES);
+            assert!(store.ips.len() <= MAX_ENTRIES);
+        }
+        assert!(attempts.check("fern@example.com", ip, now).is_err());
+
+        // Once the lockout has passed, the next failure sweeps the rest.
+        let later = now + Duration::minutes(16);
+        attempts.record_failure("moss@example.com", ip, later);
+        let store = attempts.failures.lock().unwrap();
+        assert_eq!(store.accounts.len(), 1);
+        assert_eq!(store.ips.len(), 1);
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use axum_login::tracing::info;
+use chrono::Utc;
+use lettre::{
+    message::{header::ContentType, Mailbox, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    Address, Message, SmtpTransport, Transport,
+};
+use uuid::Uuid;
+
+/// A message ready to send. `html`, when present, is sent as an alternative
+/// to the plain-text body.
+#[derive(Clone, Debug)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>,
+}
+
+impl Email {
+    pub fn new(to: &str, subject: &str, text: String) -> Self {
+        Self {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            text,
+            html: None,
+        }
+    }
+
+    fn message(&self, from: &Mailbox) -> Result<Message, MailError> {
+        let builder = Message::builder()
+            .from(from.clone())
+            .to(self.to.parse::<Mailbox>()?)
+            .subject(&self.subject);
+
+        let message = match &self.html {
+            None => builder
+                .header(ContentType::TEXT_PLAIN)
+                .body(self.text.clone()),
+            Some(html) => builder.multipart(
+                MultiPart::alternative()
+                    .singlepart(SinglePart::plain(self.text.clone()))
+                    .singlepart(SinglePart::html(html.clone())),
+            ),
+        };
+
+        message.map_err(|e| MailError::Build(e.to_string()))
+    }
+}
+
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+/// Checks that `address` is a single, well-formed mailbox address.
+pub fn parse_address(address: &str) -> Result<Address, MailError> {
+    Ok(address.trim().parse::<Address>()?)
+}
+
+/// Delivers through an SMTP server.
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// An unencrypted connection, for a relay on the same host or a local
+    /// sink during development.
+    pub fn new(host: &str, port: u16, from: &str) -> Result<Self, MailError> {
+        Ok(Self {
+            transport: SmtpTransport::builder_dangerous(host).port(port).build(),
+            from: from.parse()?,
+        })
+    }
+
+    /// Upgrades the connection with STARTTLS and logs in.
+    pub fn starttls(
+        host: &str,
+        port: u16,
+        username: String,
+        password: String,
+        from: &str,
+    ) -> Result<Self, MailError> {
+        let transport = SmtpTransport::starttls_relay(host)
+            .map_err(|e| MailError::Transport(e.to_string()))?
+            .port(port)
+            .credentials(Credentials::new(username, password))
+            .build();
+
+        Ok(Self {
+            transport,
+            from: from.parse()?,
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = email.message(&self.from)?;
+        self.transport
+            .send(&message)
+            .map_err(|e| MailError::Transport(e.to_string()))?;
+        info!("Sent \"{}\" to {}", email.subject, email.to);
+        Ok(())
+    }
+}
+
+/// Writes each message to an `.eml` file instead of sending it, so mail can
+/// be read during development without a server.
+pub struct FileMailer {
+    dir: PathBuf,
+    from: Mailbox,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl AsRef<Path>, from: &str) -> Result<Self, MailError> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            from: from.parse()?,
+        })
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = email.message(&self.from)?;
+        let path = self.dir.join(format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S"),
+            Uuid::new_v4()
+        ));
+        fs::write(&path, message.formatted())?;
+        info!(
+            "Wrote \"{}\" for {} to {}",
+            email.subject,
+            email.to,
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    InvalidAddress(String),
+    Build(String),
+    Transport(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailError::InvalidAddress(e) => write!(f, "invalid email address: {}", e),
+            MailError::Build(e) => write!(f, "could not build email: {}", e),
+            MailError::Transport(e) => write!(f, "could not send email: {}", e),
+            MailError::Io(e) => write!(f, "could not write email: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+impl From<lettre::address::AddressError> for MailError {
+    fn from(value: lettre::address::AddressError) -> Self {
+        MailError::InvalidAddress(value.to_string())
+    }
+}
+
+impl From<std::io::Error> for MailError {
+    fn from(value: std::io::Error) -> Self {
+        MailError::Io(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Accepts one SMTP session and returns everything sent after DATA.
+    fn smtp_sink() -> (u16, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let sink = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writer.write_all(b"220 sink ready\r\n").unwrap();
+
+            let mut data = String::new();
+            let mut in_data = false;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        writer.write_all(b"250 queued\r\n").unwrap();
+                    } else {
+                        data.push_str(&line);
+                    }
+                } else if line.starts_with("EHLO") {
+                    writer.write_all(b"250 sink\r\n").unwrap();
+                } else if line.starts_with("DATA") {
+                    in_data = true;
+                    writer.write_all(b"354 go ahead\r\n").unwrap();
+                } else if line.starts_with("QUIT") {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    writer.write_all(b"250 ok\r\n").unwrap();
+                }
+                line.clear();
+            }
+            data
+        });
+
+        (port, sink)
+    }
+
+    #[test]
+    fn smtp_mailer_delivers_to_server() {
+        let (port, sink) = smtp_sink();
+        let mailer = SmtpMailer::new("127.0.0.1", port, "Plantomics <shop@example.com>").unwrap();
+
+        let email = Email::new(
+            "fern@example.com",
+            "Back in stock",
+            "Your plant is back.".to_string(),
+        );
+        mailer.send(&email).unwrap();
+        drop(mailer);
+
+        let data = sink.join().unwrap();
+        assert!(data.contains("Subject: Back in stock"));
+        assert!(data.contains("Your plant is back."));
+        assert!(matches!(
+            parse_address("not an address"),
+            Err(MailError::InvalidAddress(_))
+        ));
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum_login::tracing::info;
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chrono::{DateTime, Duration, Utc};
+use openidconnect::url::{form_urlencoded, Url};
+use openidconnect::{
+    core::{
+        CoreHmacKey, CoreIdToken, CoreIdTokenClaims, CoreIdTokenFields, CoreJsonWebKeySet,
+        CoreJwsSigningAlgorithm, CoreProviderMetadata, CoreResponseType, CoreSubjectIdentifierType,
+        CoreTokenResponse, CoreTokenType,
+    },
+    AccessToken, Audience, AuthUrl, AuthorizationCode, EmptyAdditionalClaims,
+    EmptyAdditionalProviderMetadata, EmptyExtraTokenFields, EndUserEmail, EndUserUsername,
+    IssuerUrl, JsonWebKeySetUrl, Nonce, ResponseTypes, StandardClaims, SubjectIdentifier, TokenUrl,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// How long a code waits to be exchanged.
+const CODE_MINUTES: i64 = 1;
+const ID_TOKEN_MINUTES: i64 = 5;
+
+/// A stand-in identity provider for development and tests. It logs anyone
+/// in as whatever address they type, and signs ID tokens with the client
+/// secret.
+#[derive(Clone)]
+pub struct MockIdp {
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+/// A code handed out and what it is good for.
+struct Grant {
+    email: String,
+    email_verified: bool,
+    redirect_uri: String,
+    nonce: Option<String>,
+    code_challenge: String,
+    expires: DateTime<Utc>,
+}
+
+/// The query a client sends users to the authorization endpoint with.
+#[derive(Deserialize, Clone, Debug)]
+pub struct AuthorizeRequest {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub state: String,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+/// The form a client posts to the token endpoint. The client's credentials
+/// come either in it or in a basic auth header.
+#[derive(Deserialize, Clone, Debug)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: String,
+    pub redirect_uri: String,
+    pub code_verifier: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+impl MockIdp {
+    pub fn new(issuer: &str, client_id: &str, client_secret: &str) -> Self {
+        Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            grants: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The discovery document, served at `/.well-known/openid-configuration`.
+    pub fn metadata(&self) -> Result<CoreProviderMetadata, MockIdpError> {
+        let url = |path: &str| format!("{}/{path}", self.issuer);
+        let invalid = |_| MockIdpError::InvalidRequest("issuer is not a URL");
+        let metadata = CoreProviderMetadata::new(
+            IssuerUrl::new(self.issuer.clone()).map_err(invalid)?,
+            AuthUrl::new(url("authorize")).map_err(invalid)?,
+            JsonWebKeySetUrl::new(url("jwks")).map_err(invalid)?,
+            vec![ResponseTypes::new(vec![CoreResponseType::Code])],
+            vec![CoreSubjectIdentifierType::Public],
+            vec![CoreJwsSigningAlgorithm::HmacSha256],
+            EmptyAdditionalProviderMetadata {},
+        )
+        .set_token_endpoint(Some(TokenUrl::new(url("token")).map_err(invalid)?));
+        Ok(metadata)
+    }
+
+    /// No public keys, since tokens are signed with the client secret.
+    pub fn jwks(&self) -> CoreJsonWebKeySet {
+        CoreJsonWebKeySet::new(Vec::new())
+    }
+
+    /// Checks a request before asking who to log in as.
+    pub fn check(&self, request: &AuthorizeRequest) -> Result<(), MockIdpError> {
+        if request.client_id != self.client_id {
+            return Err(MockIdpError::InvalidClient);
+        }
+        if request.response_type != "code" {
+            return Err(MockIdpError::InvalidRequest(
+                "only the code flow is supported",
+            ));
+        }
+        if request.code_challenge.is_none()
+            || request.code_challenge_method.as_deref() != Some("S256")
+        {
+            return Err(MockIdpError::InvalidRequest(
+                "an S256 PKCE challenge is required",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Logs the user in as `email`, returning where to send them back to
+    /// with a code.
+    pub fn authorize(
+        &self,
+        request: &AuthorizeRequest,
+        email: &str,
+        email_verified: bool,
+        now: DateTime<Utc>,
+    ) -> Result<String, MockIdpError> {
+        self.check(request)?;
+        let code = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>());
+        let grant = Grant {
+            email: email.trim().to_string(),
+            email_verified,
+            redirect_uri: request.redirect_uri.clone(),
+            nonce: request.nonce.clone(),
+            code_challenge: request.code_challenge.clone().unwrap_or_default(),
+            expires: now + Duration::minutes(CODE_MINUTES),
+        };
+        let mut grants = self.grants.lock().expect("mock IdP threading issue");
+        grants.retain(|_, grant| grant.expires > now);
+        grants.insert(code.clone(), grant);
+
+        let mut redirect = Url::parse(&request.redirect_uri)
+            .map_err(|_| MockIdpError::InvalidRequest("redirect_uri is not a URL"))?;
+        redirect
+            .query_pairs_mut()
+            .append_pair("code", &code)
+            .append_pair("state", &request.state);
+        info!("Mock IdP logged in {email}");
+        Ok(redirect.to_string())
+    }
+
+    /// Trades a code for tokens, checking the client, the redirect and the
+    /// PKCE verifier. Each code works once.
+    pub fn token(
+        &self,
+        request: TokenRequest,
+        authorization: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<CoreTokenResponse, MockIdpError> {
+        let (client_id, client_secret) = authorization
+            .and_then(basic_auth)
+            .or_else(|| Some((request.client_id?, request.client_secret?)))
+            .ok_or(MockIdpError::InvalidClient)?;
+        if client_id != self.client_id || client_secret != self.client_secret {
+            return Err(MockIdpError::InvalidClient);
+        }
+        if request.grant_type != "authorization_code" {
+            return Err(MockIdpError::UnsupportedGrantType);
+        }
+
+        let grant = self
+            .grants
+            .lock()
+            .expect("mock IdP threading issue")
+            .remove(&request.code)
+            .filter(|grant| grant.expires > now)
+            .ok_or(MockIdpError::InvalidGrant)?;
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(
+            request.code_verifier.unwrap_or_default().as_bytes(),
+        ));
+        if grant.redirect_uri != request.redirect_uri || grant.code_challenge != challenge {
+            return Err(MockIdpError::InvalidGrant);
+        }
+
+        let username = grant
+            .email
+            .split('@')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let claims = CoreIdTokenClaims::new(
+            IssuerUrl::new(self.issuer.clone())
+                .map_err(|_| MockIdpError::InvalidRequest("issuer is not a URL"))?,
+            vec![Audience::new(self.client_id.clone())],
+            now + Duration::minutes(ID_TOKEN_MINUTES),
+            now,
+            StandardClaims::new(SubjectIdentifier::new(subject(&grant.email)))
+                .set_email(Some(EndUserEmail::new(grant.email)))
+                .set_email_verified(Some(grant.email_verified))
+                .set_preferred_username(Some(EndUserUsername::new(username))),
+            EmptyAdditionalClaims {},
+        )
+        .set_nonce(grant.nonce.map(Nonce::new));
+
+        let access_token = AccessToken::new(URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>()));
+        let id_token = CoreIdToken::new(
+            claims,
+            &CoreHmacKey::new(self.client_secret.as_bytes()),
+            CoreJwsSigningAlgorithm::HmacSha256,
+            Some(&access_token),
+            Some(&AuthorizationCode::new(request.code)),
+        )
+        .map_err(|_| MockIdpError::InvalidRequest("ID token can't be signed"))?;
+
+        Ok(CoreTokenResponse::new(
+            access_token,
+            CoreTokenType::Bearer,
+            CoreIdTokenFields::new(Some(id_token), EmptyExtraTokenFields {}),
+        ))
+    }
+}
+
+/// The client ID and secret from a basic auth header, which clients
+/// form-encode before joining.
+fn basic_auth(header: &str) -> Option<(String, String)> {
+    let encoded = header.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded).ok()?).ok()?;
+    let (id, secret) = decoded.split_once(':')?;
+    let decode = |part: &str| -> String {
+        form_urlencoded::parse(part.as_bytes())
+            .map(|(k, _)| k)
+            .collect()
+    };
+    Some((decode(id), decode(secret)))
+}
+
+/// The same address always gets the same subject.
+fn subject(email: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(email.to_lowercase().as_bytes()))
+}
+
+#[derive(Debug)]
+pub enum MockIdpError {
+    InvalidRequest(&'static str),
+    InvalidClient,
+    InvalidGrant,
+    UnsupportedGrantType,
+}
+
+impl MockIdpError {
+    /// The OAuth error code, for the `error` field of a response.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MockIdpError::InvalidRequest(_) => "invalid_request",
+            MockIdpError::InvalidClient => "invalid_client",
+            MockIdpError::InvalidGrant => "invalid_grant",
+            MockIdpError::UnsupportedGrantType => "unsupported_grant_type",
+        }
+    }
+}
+
+impl std::fmt::Display for MockIdpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MockIdpError::InvalidRequest(reason) => write!(f, "{reason}"),
+            MockIdpError::InvalidClient => write!(f, "unknown client or wrong secret"),
+            MockIdpError::InvalidGrant => write!(f, "code is invalid, expired or used"),
+            MockIdpError::UnsupportedGrantType => {
+                write!(f, "only authorization codes are supported")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MockIdpError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use openidconnect::{core::CoreIdTokenVerifier, ClientId, ClientSecret, TokenResponse};
+
+    #[test]
+    fn codes_need_the_pkce_verifier_and_work_once() {
+        let idp = MockIdp::new("http://localhost:8080/mock-idp", "shop", "secret");
+        let verifier = "a-verifier-long-enough-to-be-a-real-pkce-code-verifier";
+        let request = AuthorizeRequest {
+            response_type: "code".to_string(),
+            client_id: "shop".to_string(),
+            redirect_uri: "http://localhost:8080/login/oidc/callback".to_string(),
+            state: "state".to_string(),
+            nonce: Some("nonce".to_string()),
+            code_challenge: Some(URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))),
+            code_challenge_method: Some("S256".to_string()),
+        };
+        let now = Utc::now();
+        let redirect = Url::parse(
+            &idp.authorize(&request, "fern@example.com", true, now)
+                .unwrap(),
+        )
+        .unwrap();
+        let query: HashMap<_, _> = redirect.query_pairs().into_owned().collect();
+        assert_eq!(query["state"], "state");
+
+        let token_request = |code_verifier: &str| TokenRequest {
+            grant_type: "authorization_code".to_string(),
+            code: query["code"].clone(),
+            redirect_uri: request.redirect_uri.clone(),
+            code_verifier: Some(code_verifier.to_string()),
+            client_id: Some("shop".to_string()),
+            client_secret: Some("secret".to_string()),
+        };
+        assert!(matches!(
+            idp.token(token_request("wrong"), Some("Basic c2hvcDpzZWNyZXQ="), now),
+            Err(MockIdpError::InvalidGrant)
+        ));
+
+        // A failed exchange uses up the code too.
+        let redirect = idp
+            .authorize(&request, "fern@example.com", true, now)
+            .unwrap();
+        let query: HashMap<_, _> = Url::parse(&redirect)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        let token_request = TokenRequest {
+            code: query["code"].clone(),
+            ..token_request(verifier)
+        };
+        let response = idp.token(token_request.clone(), None, now).unwrap();
+        let id_verifier = CoreIdTokenVerifier::new_confidential_client(
+            ClientId::new("shop".to_string()),
+            ClientSecret::new("secret".to_string()),
+            IssuerUrl::new("http://localhost:8080/mock-idp".to_string()).unwrap(),
+            idp.jwks(),
+        )
+        .set_allowed_algs([CoreJwsSigningAlgorithm::HmacSha256]);
+        let claims = response
+            .id_token()
+            .unwrap()
+            .claims(&id_verifier, &Nonce::new("nonce".to_string()))
+            .unwrap();
+        assert_eq!(claims.email().unwrap().as_str(), "fern@example.com");
+        assert_eq!(claims.subject().as_str(), subject("FERN@example.com"));
+
+        assert!(matches!(
+            idp.token(token_request, None, now),
+            Err(MockIdpError::InvalidGrant)
+        ));
+    }
+}
@@ -0,0 +1,219 @@
+use openidconnect::{
+    core::{CoreAuthenticationFlow, CoreClient, CoreJsonWebKey, CoreProviderMetadata},
+    reqwest, AccessTokenHash, AuthorizationCode, ClientId, ClientSecret, CsrfToken,
+    EndpointMaybeSet, EndpointNotSet, EndpointSet, IssuerUrl, JsonWebKey, JwsSigningAlgorithm,
+    Nonce, OAuth2TokenResponse, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
+    TokenResponse,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+/// A client set up from the provider's discovery document.
+type DiscoveredClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+/// An identity provider and how the shop is registered with it.
+#[derive(Clone, Debug)]
+pub struct OidcConfig {
+    /// Shown on the login button.
+    pub name: String,
+    pub issuer_url: String,
+    pub client_id: String,
+    /// Left out for public clients, which rely on PKCE alone.
+    pub client_secret: Option<String>,
+    /// The callback registered with the provider.
+    pub redirect_url: String,
+}
+
+/// An account at an identity provider. The subject never changes for a
+/// user, unlike their email.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExternalIdentity {
+    pub issuer: String,
+    pub subject: String,
+}
+
+/// Who the provider says logged in.
+#[derive(Clone, Debug)]
+pub struct ExternalProfile {
+    pub identity: ExternalIdentity,
+    pub email: Option<String>,
+    /// Whether the provider checked the user owns `email`.
+    pub email_verified: bool,
+    /// A username to suggest for a new account.
+    pub username: Option<String>,
+}
+
+/// A login sent to the provider, kept in the session until it comes back
+/// to the callback.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OidcLogin {
+    state: String,
+    nonce: String,
+    pkce_verifier: String,
+}
+
+/// Logs users in with the authorization code flow and PKCE. The provider is
+/// looked up on first use, so the shop starts even while it is down.
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    client: OnceCell<DiscoveredClient>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Result<Self, OidcError> {
+        let http = reqwest::Client::builder()
+            // Following redirects would let the provider point requests anywhere.
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| OidcError::Discovery(e.to_string()))?;
+        Ok(Self {
+            config,
+            http,
+            client: OnceCell::new(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    async fn client(&self) -> Result<&DiscoveredClient, OidcError> {
+        self.client
+            .get_or_try_init(|| async {
+                let discovery = |e: String| OidcError::Discovery(e);
+                let issuer = IssuerUrl::new(self.config.issuer_url.clone())
+                    .map_err(|e| discovery(e.to_string()))?;
+                let redirect = RedirectUrl::new(self.config.redirect_url.clone())
+                    .map_err(|e| discovery(e.to_string()))?;
+                let metadata = CoreProviderMetadata::discover_async(issuer, &self.http)
+                    .await
+                    .map_err(|e| discovery(e.to_string()))?;
+                let client = CoreClient::from_provider_metadata(
+                    metadata,
+                    ClientId::new(self.config.client_id.clone()),
+                    self.config.client_secret.clone().map(ClientSecret::new),
+                );
+                Ok(client.set_redirect_uri(redirect))
+            })
+            .await
+    }
+
+    /// Where to send the user to log in, and what to keep for the callback.
+    pub async fn start(&self) -> Result<(String, OidcLogin), OidcError> {
+        let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+        let (url, state, nonce) = self
+            .client()
+            .await?
+            .authorize_url(
+                CoreAuthenticationFlow::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .add_scope(Scope::new("email".to_string()))
+            .add_scope(Scope::new("profile".to_string()))
+            .set_pkce_challenge(challenge)
+            .url();
+
+        let login = OidcLogin {
+            state: state.secret().clone(),
+            nonce: nonce.secret().clone(),
+            pkce_verifier: verifier.secret().clone(),
+        };
+        Ok((url.to_string(), login))
+    }
+
+    /// Trades the code the provider sent back for a checked ID token.
+    pub async fn finish(
+        &self,
+        login: OidcLogin,
+        state: &str,
+        code: &str,
+    ) -> Result<ExternalProfile, OidcError> {
+        if state != login.state {
+            return Err(OidcError::StateMismatch);
+        }
+
+        let client = self.client().await?;
+        let token = client
+            .exchange_code(AuthorizationCode::new(code.to_string()))
+            .map_err(|e| OidcError::Exchange(e.to_string()))?
+            .set_pkce_verifier(PkceCodeVerifier::new(login.pkce_verifier))
+            .request_async(&self.http)
+            .await
+            .map_err(|e| OidcError::Exchange(e.to_string()))?;
+
+        let invalid = |e: String| OidcError::InvalidIdToken(e);
+        let id_token = token
+            .id_token()
+            .ok_or_else(|| invalid("no ID token in the response".to_string()))?;
+        let verifier = client.id_token_verifier();
+        let claims = id_token
+            .claims(&verifier, &Nonce::new(login.nonce))
+            .map_err(|e| invalid(e.to_string()))?;
+
+        // Makes sure the access token wasn't swapped for another user's.
+        if let Some(expected) = claims.access_token_hash() {
+            let algorithm = id_token.signing_alg().map_err(|e| invalid(e.to_string()))?;
+            // Tokens signed with the client secret have no key in the key set.
+            let key = match (algorithm.uses_shared_secret(), &self.config.client_secret) {
+                (true, Some(secret)) => CoreJsonWebKey::new_symmetric(secret.clone().into_bytes()),
+                _ => id_token
+                    .signing_key(&verifier)
+                    .map_err(|e| invalid(e.to_string()))?
+                    .clone(),
+            };
+            let actual = AccessTokenHash::from_token(token.access_token(), algorithm, &key)
+                .map_err(|e| invalid(e.to_string()))?;
+            if actual != *expected {
+                return Err(invalid("access token hash mismatch".to_string()));
+            }
+        }
+
+        Ok(ExternalProfile {
+            identity: ExternalIdentity {
+                issuer: claims.issuer().to_string(),
+                subject: claims.subject().to_string(),
+            },
+            email: claims.email().map(|email| email.to_string()),
+            email_verified: claims.email_verified().unwrap_or(false),
+            username: claims
+                .preferred_username()
+                .map(|username| username.to_string())
+                .or_else(|| {
+                    let name = claims.name()?.get(None)?;
+                    Some(name.to_string())
+                }),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum OidcError {
+    /// The provider couldn't be reached or described itself wrongly.
+    Discovery(String),
+    /// The callback wasn't for the login this session started.
+    StateMismatch,
+    Exchange(String),
+    InvalidIdToken(String),
+}
+
+impl std::fmt::Display for OidcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OidcError::Discovery(e) => write!(f, "identity provider discovery failed: {e}"),
+            OidcError::StateMismatch => write!(f, "login state did not match"),
+            OidcError::Exchange(e) => write!(f, "code exchange failed: {e}"),
+            OidcError::InvalidIdToken(e) => write!(f, "invalid ID token: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for OidcError {}
@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use bigdecimal::{num_bigint::BigInt, BigDecimal, RoundingMode, Zero};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    account::User,
+    cart::Cart,
+    store::{InventoryBackend, InventoryError},
+};
+
+#[derive(Clone)]
+pub struct OrderBackend(Arc<Mutex<OrderStore>>);
+
+impl OrderBackend {
+    pub fn new() -> Self {
+        Self::with_pricing(Pricing::default())
+    }
+
+    pub fn with_pricing(pricing: Pricing) -> Self {
+        OrderBackend(Arc::new(Mutex::new(OrderStore {
+            orders: HashMap::new(),
+            pricing,
+        })))
+    }
+
+    /// Turns a cart into an order, taking its items out of free stock.
+    /// Only users with a verified email can order. Clearing the cart is
+    /// left to the caller.
+    pub fn place(
+        &self,
+        user: &User,
+        cart: &Cart,
+        inventory: &InventoryBackend,
+    ) -> Result<Order, OrderError> {
+        let user_id = user.id;
+        if !user.verified {
+            return Err(OrderError::Unverified);
+        }
+        if cart.items.is_empty() {
+            return Err(OrderError::EmptyCart);
+        }
+
+        let lines: Vec<(Uuid, usize)> = cart
+            .items
+            .values()
+            .map(|item| (item.variant.variant_id, item.number))
+            .collect();
+        inventory.sell(user_id, &lines)?;
+
+        let mut lines: Vec<OrderLine> = cart
+            .items
+            .values()
+            .map(|item| OrderLine {
+                listing_id: item.listing.listing_id,
+                variant_id: item.variant.variant_id,
+                name: item.listing.name.clone(),
+                variant: item.variant.label(),
+                price: item.variant.price.clone(),
+                number: item.number,
+            })
+            .collect();
+        lines.sort_by(|a, b| a.name.cmp(&b.name).then(a.variant.cmp(&b.variant)));
+
+        let pricing = self.pricing();
+        let subtotal = cart.subtotal();
+        let order = Order {
+            order_id: Uuid::new_v4(),
+            user_id,
+            lines,
+            total: pricing.total(&subtotal),
+            tax: pricing.tax(&subtotal),
+            shipping: pricing.shipping,
+            subtotal,
+            placed_at: Utc::now(),
+            status: OrderStatus::Placed,
+        };
+
+        let mut order_store = self.lock().expect("order backend threads");
+        order_store.orders.insert(order.order_id, order.clone());
+        Ok(order)
+    }
+
+    pub fn pricing(&self) -> Pricing {
+        let order_store = self.lock().expect("order backend threads");
+        order_store.pricing.clone()
+    }
+
+    pub fn order(&self, order_id: &Uuid) -> Option<Order> {
+        let order_store = self.lock().expect("order backend threads");
+        order_store.orders.get(order_id).cloned()
+    }
+
+    /// A customer's orders, newest first.
+    pub fn orders(&self, user_id: &Uuid) -> Vec<Order> {
+        let order_store = self.lock().expect("order backend threads");
+        let mut orders: Vec<Order> = order_store
+            .orders
+            .values()
+            .filter(|order| order.user_id == *user_id)
+            .cloned()
+            .collect();
+        orders.sort_by_key(|order| std::cmp::Reverse(order.placed_at));
+        orders
+    }
+
+    /// Every order, newest first.
+    pub fn all(&self) -> Vec<Order> {
+        let order_store = self.lock().expect("order backend threads");
+        let mut orders: Vec<Order> = order_store.orders.values().cloned().collect();
+        orders.sort_by_key(|order| std::cmp::Reverse(order.placed_at));
+        orders
+    }
+
+    /// Marks a placed order as shipped, moving its stock to sent.
+    pub fn ship(&self, order_id: &Uuid, inventory: &InventoryBackend) -> Result<Order, OrderError> {
+        let mut order_store = self.lock().expect("order backend threads");
+        let order = order_store
+            .orders
+            .get_mut(order_id)
+            .ok_or(OrderError::NotFound)?;
+        if order.status != OrderStatus::Placed {
+            return Err(OrderError::InvalidStatus(order.status));
+        }
+
+        let lines: Vec<(Uuid, usize)> = order
+            .lines
+            .iter()
+            .map(|line| (line.variant_id, line.number))
+            .collect();
+        inventory.ship(&lines);
+        order.status = OrderStatus::Shipped;
+        Ok(order.clone())
+    }
+
+    /// Marks an order as refunded. Stock is left as it is; returned items
+    /// are restocked by hand once they arrive.
+    pub fn refund(&self, order_id: &Uuid) -> Result<Order, OrderError> {
+        let mut order_store = self.lock().expect("order backend threads");
+        let order = order_store
+            .orders
+            .get_mut(order_id)
+            .ok_or(OrderError::NotFound)?;
+        if order.status == OrderStatus::Refunded {
+            return Err(OrderError::InvalidStatus(order.status));
+        }
+
+        order.status = OrderStatus::Refunded;
+        Ok(order.clone())
+    }
+
+    /// Detaches a deleted customer's orders from their account. The orders
+    /// stay for the books, owned by no one.
+    pub fn anonymise(&self, user_id: &Uuid) {
+        let mut order_store = self.lock().expect("order backend threads");
+        for order in order_store.orders.values_mut() {
+            if order.user_id == *user_id {
+                order.user_id = Uuid::nil();
+            }
+        }
+    }
+
+    /// Whether the user bought the listing and kept it. Refunded orders
+    /// don't count.
+    pub fn has_purchased(&self, user_id: &Uuid, listing_id: &Uuid) -> bool {
+        let order_store = self.lock().expect("order backend threads");
+        order_store.orders.values().any(|order| {
+            order.user_id == *user_id
+                && order.status != OrderStatus::Refunded
+                && order.lines.iter().any(|l| l.listing_id == *listing_id)
+        })
+    }
+}
+
+impl Default for OrderBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct OrderStore {
+    pub orders: HashMap<Uuid, Order>,
+    pricing: Pricing,
+}
+
+impl std::ops::Deref for OrderBackend {
+    type Target = Arc<Mutex<OrderStore>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// What an order costs on top of its items.
+#[derive(Clone, Debug)]
+pub struct Pricing {
+    /// Flat rate for standard shipping.
+    pub shipping: BigDecimal,
+    /// Sales tax on the subtotal, as a fraction.
+    pub tax_rate: BigDecimal,
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self {
+            shipping: BigDecimal::new(BigInt::from(699), 2),
+            tax_rate: BigDecimal::zero(),
+        }
+    }
+}
+
+impl Pricing {
+    /// Tax on `subtotal`, rounded to the cent.
+    pub fn tax(&self, subtotal: &BigDecimal) -> BigDecimal {
+        (subtotal * &self.tax_rate).with_scale_round(2, RoundingMode::HalfUp)
+    }
+
+    pub fn total(&self, subtotal: &BigDecimal) -> BigDecimal {
+        subtotal + &self.shipping + self.tax(subtotal)
+    }
+}
+
+/// A line as it was bought, so later price or name changes to the product
+/// don't rewrite order history.
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export)]
+pub struct OrderLine {
+    pub listing_id: Uuid,
+    pub variant_id: Uuid,
+    pub name: String,
+    pub variant: String,
+    pub price: BigDecimal,
+    pub number: usize,
+}
+
+impl OrderLine {
+    pub fn total(&self) -> BigDecimal {
+        &self.price * BigDecimal::from(self.number as u64)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export)]
+pub struct Order {
+    pub order_id: Uuid,
+    pub user_id: Uuid,
+    pub lines: Vec<OrderLine>,
+    pub subtotal: BigDecimal,
+    pub shipping: BigDecimal,
+    pub tax: BigDecimal,
+    pub total: BigDecimal,
+    pub placed_at: DateTime<Utc>,
+    pub status: OrderStatus,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum OrderStatus {
+    Placed,
+    Shipped,
+    Refunded,
+}
+
+impl std::fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            OrderStatus::Placed => "Placed",
+            OrderStatus::Shipped => "Shipped",
+            OrderStatus::Refunded => "Refunded",
+        };
+
+        write!(f, "{}", status)
+    }
+}
+
+#[derive(Debug, TS)]
+#[ts(export)]
+pub enum OrderError {
+    EmptyCart,
+    Unverified,
+    NotFound,
+    InvalidStatus(OrderStatus),
+    Inventory(InventoryError),
+}
+
+impl std::fmt::Display for OrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderError::EmptyCart => write!(f, "cart is empty"),
+            OrderError::Unverified => write!(f, "email address is not verified"),
+            OrderError::NotFound => write!(f, "order not found"),
+            OrderError::InvalidStatus(status) => {
+                write!(f, "order is already {}", status.to_string().to_lowercase())
+            }
+            OrderError::Inventory(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+impl From<InventoryError> for OrderError {
+    fn from(value: InventoryError) -> Self {
+        OrderError::Inventory(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        account::{signup, UserStore},
+        cart::CartBackend,
+        store::{Inventory, Product},
+    };
+
+    #[test]
+    fn placing_an_order_moves_stock_to_ordered() {
+        let inventory = InventoryBackend::new();
+        let carts = CartBackend::new();
+        let orders = OrderBackend::new();
+        let mut users = UserStore::default();
+        users.add(signup("fern@example.com")).unwrap();
+        let unverified = users.find_by_email("fern@example.com").unwrap().clone();
+        users.set_verified("fern@example.com").unwrap();
+        let user = users.find_by_email("fern@example.com").unwrap().clone();
+
+        let product = Product::random();
+        let listing_id = product.listing_id;
+        inventory.add_product(product, Inventory::new(3, 0, 0));
+
+        assert!(matches!(
+            orders.place(&user, &carts.cart(&user.id), &inventory),
+            Err(OrderError::EmptyCart)
+        ));
+
+        let cart = carts
+            .add(user.id, &inventory, &listing_id, None, 2)
+            .unwrap();
+        assert!(matches!(
+            orders.place(&unverified, &cart, &inventory),
+            Err(OrderError::Unverified)
+        ));
+        let order = orders.place(&user, &cart, &inventory).unwrap();
+        assert_eq!(order.total, order.subtotal + Pricing::default().shipping);
+        let taxed = Pricing {
+            tax_rate: "0.0825".parse().unwrap(),
+            ..Pricing::default()
+        };
+        assert_eq!(taxed.tax(&"19.99".parse().unwrap()).to_string(), "1.65");
+
+        let stock = inventory.stock(&listing_id).unwrap();
+        assert_eq!((stock.free, stock.ordered), (1, 2));
+        assert_eq!(inventory.reconstruct_free(&listing_id), 1);
+        assert!(orders.has_purchased(&user.id, &listing_id));
+
+        orders.ship(&order.order_id, &inventory).unwrap();
+        let stock = inventory.stock(&listing_id).unwrap();
+        assert_eq!((stock.ordered, stock.sent), (0, 2));
+        assert!(matches!(
+            orders.ship(&order.order_id, &inventory),
+            Err(OrderError::InvalidStatus(OrderStatus::Shipped))
+        ));
+        orders.refund(&order.order_id).unwrap();
+        assert!(!orders.has_purchased(&user.id, &listing_id));
+
+        // The same cart again would oversell.
+        assert!(matches!(
+            orders.place(&user, &cart, &inventory),
+            Err(OrderError::Inventory(InventoryError::InsufficientStock))
+        ));
+    }
+}
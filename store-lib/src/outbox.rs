@@ -0,0 +1,164 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use axum_login::tracing::{error, warn};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::mail::{Email, MailError, Mailer};
+
+/// Attempts before a message is given up on.
+pub const MAX_ATTEMPTS: u32 = 5;
+/// Wait before the first retry, doubling after each failure.
+pub const RETRY_BACKOFF_SECONDS: i64 = 30;
+
+/// Queues mail so requests never wait on, or fail because of, the mail
+/// server. `deliver_due` is called periodically to hand messages to the
+/// underlying mailer, retrying failures with exponential backoff.
+#[derive(Clone)]
+pub struct Outbox {
+    queue: Arc<Mutex<VecDeque<Pending>>>,
+    failed: Arc<Mutex<Vec<Pending>>>,
+    mailer: Arc<dyn Mailer>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Pending {
+    pub email: Email,
+    pub attempts: u32,
+    pub next_attempt: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+impl Outbox {
+    pub fn new(mailer: Arc<dyn Mailer>) -> Self {
+        Self {
+            queue: Arc::new(Mutex::new(VecDeque::new())),
+            failed: Arc::new(Mutex::new(Vec::new())),
+            mailer,
+        }
+    }
+
+    pub fn enqueue(&self, email: Email) {
+        let mut queue = self.queue.lock().expect("outbox threads");
+        queue.push_back(Pending {
+            email,
+            attempts: 0,
+            next_attempt: Utc::now(),
+            last_error: None,
+        });
+    }
+
+    /// Sends every message due by `now`, returning how many were delivered.
+    pub fn deliver_due(&self, now: DateTime<Utc>) -> usize {
+        let due: Vec<Pending> = {
+            let mut queue = self.queue.lock().expect("outbox threads");
+            let (due, waiting): (Vec<Pending>, Vec<Pending>) =
+                queue.drain(..).partition(|p| p.next_attempt <= now);
+            *queue = waiting.into();
+            due
+        };
+
+        let mut delivered = 0;
+        for mut pending in due {
+            pending.attempts += 1;
+            match self.mailer.send(&pending.email) {
+                Ok(()) => delivered += 1,
+                Err(e) if pending.attempts >= MAX_ATTEMPTS => {
+                    error!(
+                        "Giving up on \"{}\" to {} after {} attempts: {e}",
+                        pending.email.subject, pending.email.to, pending.attempts
+                    );
+                    pending.last_error = Some(e.to_string());
+                    self.failed.lock().expect("outbox threads").push(pending);
+                }
+                Err(e) => {
+                    warn!(
+                        "Sending \"{}\" to {} failed, will retry: {e}",
+                        pending.email.subject, pending.email.to
+                    );
+                    let backoff = RETRY_BACKOFF_SECONDS << (pending.attempts - 1);
+                    pending.next_attempt = now + Duration::seconds(backoff);
+                    pending.last_error = Some(e.to_string());
+                    self.queue
+                        .lock()
+                        .expect("outbox threads")
+                        .push_back(pending);
+                }
+            }
+        }
+
+        delivered
+    }
+
+    /// Messages still waiting to be sent, including ones awaiting a retry.
+    pub fn pending(&self) -> Vec<Pending> {
+        let queue = self.queue.lock().expect("outbox threads");
+        queue.iter().cloned().collect()
+    }
+
+    /// Messages that ran out of attempts.
+    pub fn failed(&self) -> Vec<Pending> {
+        self.failed.lock().expect("outbox threads").clone()
+    }
+}
+
+impl Mailer for Outbox {
+    /// Queues the message. Only the background delivery talks to the server.
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        self.enqueue(email.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Fails the first `failures` sends, then records what it sends.
+    struct Flaky {
+        failures: Mutex<u32>,
+        sent: Mutex<Vec<Email>>,
+    }
+
+    impl Mailer for Flaky {
+        fn send(&self, email: &Email) -> Result<(), MailError> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(MailError::Transport("connection refused".to_string()));
+            }
+            self.sent.lock().unwrap().push(email.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_sends_are_retried_with_backoff() {
+        let mailer = Arc::new(Flaky {
+            failures: Mutex::new(2),
+            sent: Mutex::new(Vec::new()),
+        });
+        let outbox = Outbox::new(mailer.clone());
+        outbox.enqueue(Email::new(
+            "fern@example.com",
+            "Welcome",
+            "Hello".to_string(),
+        ));
+
+        let start = Utc::now();
+        assert_eq!(outbox.deliver_due(start), 0);
+        // Not due again until the backoff has passed.
+        assert_eq!(outbox.deliver_due(start + Duration::seconds(1)), 0);
+        assert_eq!(outbox.pending()[0].attempts, 1);
+
+        assert_eq!(outbox.deliver_due(start + Duration::seconds(30)), 0);
+        assert_eq!(outbox.deliver_due(start + Duration::seconds(89)), 0);
+        assert_eq!(outbox.deliver_due(start + Duration::seconds(90)), 1);
+
+        assert!(outbox.pending().is_empty());
+        assert!(outbox.failed().is_empty());
+        assert_eq!(mailer.sent.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::account::UserError;
+
+/// How long a reset link stays valid.
+pub const RESET_TOKEN_MINUTES: i64 = 60;
+
+/// Outstanding password reset tokens. Only a hash of each token is kept, so
+/// a leaked store can't be used to reset passwords, and each token works
+/// once.
+#[derive(Clone, Default)]
+pub struct PasswordResets {
+    pending: HashMap<String, PendingReset>,
+}
+
+#[derive(Clone)]
+struct PendingReset {
+    user_id: Uuid,
+    expires: DateTime<Utc>,
+}
+
+impl PasswordResets {
+    /// A new token for `user_id`, replacing any they were sent before.
+    pub fn issue(&mut self, user_id: Uuid, now: DateTime<Utc>) -> String {
+        self.pending
+            .retain(|_, reset| reset.user_id != user_id && reset.expires > now);
+
+        let token = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+        self.pending.insert(
+            hash(&token),
+            PendingReset {
+                user_id,
+                expires: now + Duration::minutes(RESET_TOKEN_MINUTES),
+            },
+        );
+        token
+    }
+
+    /// Uses up `token`, returning the user it was issued to.
+    pub fn redeem(&mut self, token: &str, now: DateTime<Utc>) -> Result<Uuid, ResetError> {
+        let reset = self
+            .pending
+            .remove(&hash(token.trim()))
+            .ok_or(ResetError::Invalid)?;
+        if reset.expires < now {
+            return Err(ResetError::Expired);
+        }
+        Ok(reset.user_id)
+    }
+}
+
+fn hash(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct ForgotPassword {
+    pub email: String,
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct ResetPassword {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug)]
+pub enum ResetError {
+    Invalid,
+    Expired,
+    User(UserError),
+}
+
+impl std::fmt::Display for ResetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResetError::Invalid => write!(f, "reset link is invalid or has already been used"),
+            ResetError::Expired => write!(f, "reset link has expired"),
+            ResetError::User(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ResetError {}
+
+impl From<UserError> for ResetError {
+    fn from(value: UserError) -> Self {
+        ResetError::User(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::account::{signup, Credentials, UserBackend};
+    use axum_login::AuthnBackend;
+
+    fn login(users: &UserBackend, password: &str) -> bool {
+        let credentials = Credentials {
+            email: "fern@example.com".to_string(),
+            password: password.to_string(),
+            remember: false,
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        matches!(
+            runtime.block_on(users.authenticate(credentials)),
+            Ok(Some(_))
+        )
+    }
+
+    #[test]
+    fn reset_tokens_work_once_and_change_the_password() {
+        let users = UserBackend::new();
+        users
+            .lock()
+            .unwrap()
+            .add(signup("fern@example.com"))
+            .unwrap();
+
+        let now = Utc::now();
+        let mut user_store = users.lock().unwrap();
+        assert!(user_store
+            .request_password_reset("nobody@example.com", now)
+            .is_none());
+        let (user, expired) = user_store
+            .request_password_reset("fern@example.com", now)
+            .unwrap();
+        assert!(matches!(
+            user_store.reset_password(&expired, "new password", now + Duration::hours(2)),
+            Err(ResetError::Expired)
+        ));
+
+        let (_, token) = user_store
+            .request_password_reset("fern@example.com", now)
+            .unwrap();
+        let reset = user_store
+            .reset_password(&token, "new password", now)
+            .unwrap();
+        assert_ne!(
+            axum_login::AuthUser::session_auth_hash(&user),
+            axum_login::AuthUser::session_auth_hash(&reset)
+        );
+        assert!(matches!(
+            user_store.reset_password(&token, "another password", now),
+            Err(ResetError::Invalid)
+        ));
+        drop(user_store);
+
+        assert!(!login(&users, "password1"));
+        assert!(login(&users, "new password"));
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum_login::tracing::{error, info};
+use serde::Deserialize;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    mail::{parse_address, Email, MailError, Mailer},
+    store::{Product, RestockListener},
+};
+
+/// Email addresses waiting for out-of-stock products, each told once when
+/// the product can be bought again.
+#[derive(Clone)]
+pub struct RestockAlerts {
+    subscribers: Arc<Mutex<HashMap<Uuid, Vec<String>>>>,
+    mailer: Arc<dyn Mailer>,
+    site_url: String,
+}
+
+impl RestockAlerts {
+    /// `site_url` is prefixed to product links in the emails.
+    pub fn new(mailer: Arc<dyn Mailer>, site_url: &str) -> Self {
+        Self {
+            subscribers: Arc::new(Mutex::new(HashMap::new())),
+            mailer,
+            site_url: site_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn subscribe(&self, listing_id: Uuid, email: &str) -> Result<(), MailError> {
+        let email = parse_address(email)?.to_string();
+        let mut subscribers = self.subscribers.lock().expect("restock alert threads");
+        let waiting = subscribers.entry(listing_id).or_default();
+        if !waiting.contains(&email) {
+            waiting.push(email);
+        }
+        Ok(())
+    }
+
+    pub fn is_subscribed(&self, listing_id: &Uuid, email: &str) -> bool {
+        let subscribers = self.subscribers.lock().expect("restock alert threads");
+        subscribers
+            .get(listing_id)
+            .is_some_and(|waiting| waiting.iter().any(|e| e == email))
+    }
+
+    /// Takes the address off every product it is waiting on.
+    pub fn unsubscribe_all(&self, email: &str) {
+        let mut subscribers = self.subscribers.lock().expect("restock alert threads");
+        for waiting in subscribers.values_mut() {
+            waiting.retain(|e| !e.eq_ignore_ascii_case(email.trim()));
+        }
+        subscribers.retain(|_, waiting| !waiting.is_empty());
+    }
+
+    fn email(&self, product: &Product, to: &str) -> Email {
+        Email::new(
+            to,
+            &format!("{} is back in stock", product.name),
+            format!(
+                "Good news! {} is available again at Plantomics.\n\n\
+                 Get yours before it sells out: {}/products/{}\n\n\
+                 You asked to hear about this item, so this is the only email we'll send about it.\n",
+                product.name, self.site_url, product.listing_id
+            ),
+        )
+    }
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct RestockSubscription {
+    pub email: String,
+}
+
+impl RestockListener for RestockAlerts {
+    /// Mails everyone waiting on `product`. Addresses that fail stay
+    /// subscribed for the next restock.
+    fn restocked(&self, product: &Product) {
+        let waiting = {
+            let mut subscribers = self.subscribers.lock().expect("restock alert threads");
+            subscribers.remove(&product.listing_id).unwrap_or_default()
+        };
+        if waiting.is_empty() {
+            return;
+        }
+        info!(
+            "Sending {} back in stock alerts for {}",
+            waiting.len(),
+            product.listing_id
+        );
+
+        let failed: Vec<String> = waiting
+            .into_iter()
+            .filter(|to| match self.mailer.send(&self.email(product, to)) {
+                Ok(()) => false,
+                Err(e) => {
+                    error!("Back in stock email to {to} failed: {e}");
+                    true
+                }
+            })
+            .collect();
+
+        if !failed.is_empty() {
+            let mut subscribers = self.subscribers.lock().expect("restock alert threads");
+            subscribers
+                .entry(product.listing_id)
+                .or_default()
+                .extend(failed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::{AdjustStock, AdjustmentReason, Inventory, InventoryBackend};
+
+    #[derive(Default)]
+    struct Outbox(Mutex<Vec<Email>>);
+
+    impl Mailer for Outbox {
+        fn send(&self, email: &Email) -> Result<(), MailError> {
+            self.0.lock().unwrap().push(email.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn subscribers_are_mailed_once_when_stock_returns() {
+        let outbox = Arc::new(Outbox::default());
+        let alerts = RestockAlerts::new(outbox.clone(), "http://localhost:8080/");
+        let inventory = InventoryBackend::new();
+        inventory.on_restock(Arc::new(alerts.clone()));
+
+        let mut product = Product::random();
+        product.variants.truncate(1);
+        let listing_id = product.listing_id;
+        inventory.add_product(product, Inventory::new(0, 0, 0));
+
+        alerts.subscribe(listing_id, "fern@example.com").unwrap();
+        alerts.subscribe(listing_id, "fern@example.com").unwrap();
+        assert!(alerts.subscribe(listing_id, "fern").is_err());
+
+        let adjust = |delta, reason| {
+            let admin = Uuid::new_v4();
+            inventory
+                .adjust(listing_id, admin, AdjustStock { delta, reason })
+                .unwrap();
+        };
+        adjust(1, AdjustmentReason::Restock);
+        adjust(-1, AdjustmentReason::Damage);
+        adjust(1, AdjustmentReason::Restock);
+
+        let sent = outbox.0.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0]
+            .text
+            .contains(&format!("http://localhost:8080/products/{listing_id}")));
+        assert!(!alerts.is_subscribed(&listing_id, "fern@example.com"));
+
+        alerts.subscribe(listing_id, "Moss@example.com").unwrap();
+        alerts.unsubscribe_all("moss@example.com");
+        assert!(!alerts.is_subscribed(&listing_id, "Moss@example.com"));
+    }
+}
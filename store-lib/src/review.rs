@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{account::User, order::OrderBackend};
+
+pub const DEFAULT_REVIEW_PAGE_SIZE: usize = 10;
+pub const MAX_TITLE_LENGTH: usize = 120;
+pub const MAX_BODY_LENGTH: usize = 5000;
+/// Shown as the author of reviews from deleted accounts.
+pub const FORMER_CUSTOMER: &str = "Former customer";
+
+#[derive(Clone)]
+pub struct ReviewBackend(Arc<Mutex<ReviewStore>>);
+
+impl ReviewBackend {
+    pub fn new() -> Self {
+        ReviewBackend(Arc::new(Mutex::new(ReviewStore {
+            reviews: HashMap::new(),
+        })))
+    }
+
+    /// Records a review from a customer who has ordered the listing. New
+    /// reviews wait for an admin to approve them before they are shown.
+    pub fn submit(
+        &self,
+        user: &User,
+        listing_id: Uuid,
+        orders: &OrderBackend,
+        review: NewReview,
+    ) -> Result<Review, ReviewError> {
+        let title = review.title.trim();
+        if !(1..=5).contains(&review.rating) {
+            return Err(ReviewError::InvalidRating);
+        }
+        if title.is_empty() || title.chars().count() > MAX_TITLE_LENGTH {
+            return Err(ReviewError::InvalidTitle);
+        }
+        if review.body.trim().chars().count() > MAX_BODY_LENGTH {
+            return Err(ReviewError::BodyTooLong);
+        }
+        if !orders.has_purchased(&user.id, &listing_id) {
+            return Err(ReviewError::NotPurchased);
+        }
+
+        let mut review_store = self.lock().expect("review backend threads");
+        if review_store
+            .reviews
+            .values()
+            .any(|r| r.listing_id == listing_id && r.user_id == user.id)
+        {
+            return Err(ReviewError::AlreadyReviewed);
+        }
+
+        let review = Review {
+            review_id: Uuid::new_v4(),
+            listing_id,
+            user_id: user.id,
+            author: user.username.clone(),
+            rating: review.rating,
+            title: title.to_string(),
+            body: review.body.trim().to_string(),
+            created_at: Utc::now(),
+            status: ReviewStatus::Pending,
+        };
+        review_store
+            .reviews
+            .insert(review.review_id, review.clone());
+        Ok(review)
+    }
+
+    pub fn moderate(&self, review_id: &Uuid, status: ReviewStatus) -> Result<Review, ReviewError> {
+        let mut review_store = self.lock().expect("review backend threads");
+        let review = review_store
+            .reviews
+            .get_mut(review_id)
+            .ok_or(ReviewError::NotFound)?;
+        review.status = status;
+        Ok(review.clone())
+    }
+
+    /// A page of a listing's approved reviews, newest first.
+    pub fn reviews(&self, listing_id: &Uuid, query: &ReviewQuery) -> ReviewPage {
+        let review_store = self.lock().expect("review backend threads");
+        let mut reviews: Vec<&Review> = review_store
+            .reviews
+            .values()
+            .filter(|r| r.listing_id == *listing_id && r.status == ReviewStatus::Approved)
+            .collect();
+        reviews.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+
+        let summary = RatingSummary::of(reviews.iter().copied());
+        let per_page = query.per_page.clamp(1, crate::listing::MAX_PAGE_SIZE);
+        let page = query.page.max(1);
+        let total = reviews.len();
+
+        ReviewPage {
+            reviews: reviews
+                .into_iter()
+                .skip((page - 1).saturating_mul(per_page))
+                .take(per_page)
+                .cloned()
+                .collect(),
+            page,
+            per_page,
+            total,
+            next_page: page
+                .checked_add(1)
+                .filter(|_| page.saturating_mul(per_page) < total),
+            summary,
+        }
+    }
+
+    /// Average of a listing's approved reviews.
+    pub fn summary(&self, listing_id: &Uuid) -> RatingSummary {
+        let review_store = self.lock().expect("review backend threads");
+        RatingSummary::of(
+            review_store
+                .reviews
+                .values()
+                .filter(|r| r.listing_id == *listing_id && r.status == ReviewStatus::Approved),
+        )
+    }
+
+    /// Whether `user_id` has already reviewed the listing, in any status.
+    pub fn has_reviewed(&self, user_id: &Uuid, listing_id: &Uuid) -> bool {
+        let review_store = self.lock().expect("review backend threads");
+        review_store
+            .reviews
+            .values()
+            .any(|r| r.listing_id == *listing_id && r.user_id == *user_id)
+    }
+
+    /// Shows a renamed user's new name on their reviews.
+    pub fn rename_author(&self, user_id: &Uuid, username: &str) {
+        let mut review_store = self.lock().expect("review backend threads");
+        for review in review_store.reviews.values_mut() {
+            if review.user_id == *user_id {
+                review.author = username.to_string();
+            }
+        }
+    }
+
+    /// Detaches a deleted customer's reviews from their account. The reviews
+    /// stay, credited to no one by name.
+    pub fn anonymise(&self, user_id: &Uuid) {
+        let mut review_store = self.lock().expect("review backend threads");
+        for review in review_store.reviews.values_mut() {
+            if review.user_id == *user_id {
+                review.user_id = Uuid::nil();
+                review.author = FORMER_CUSTOMER.to_string();
+            }
+        }
+    }
+
+    /// Every review for moderation, pending ones first, then newest first.
+    pub fn all(&self) -> Vec<Review> {
+        let review_store = self.lock().expect("review backend threads");
+        let mut reviews: Vec<Review> = review_store.reviews.values().cloned().collect();
+        reviews.sort_by_key(|r| {
+            (
+                r.status != ReviewStatus::Pending,
+                std::cmp::Reverse(r.created_at),
+            )
+        });
+        reviews
+    }
+}
+
+impl Default for ReviewBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct ReviewStore {
+    pub reviews: HashMap<Uuid, Review>,
+}
+
+impl std::ops::Deref for ReviewBackend {
+    type Target = Arc<Mutex<ReviewStore>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export)]
+pub struct Review {
+    pub review_id: Uuid,
+    pub listing_id: Uuid,
+    #[serde(skip)]
+    pub user_id: Uuid,
+    pub author: String,
+    /// One to five stars.
+    pub rating: u8,
+    pub title: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub status: ReviewStatus,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum ReviewStatus {
+    Pending,
+    Approved,
+    Hidden,
+}
+
+impl std::fmt::Display for ReviewStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            ReviewStatus::Pending => "Pending",
+            ReviewStatus::Approved => "Approved",
+            ReviewStatus::Hidden => "Hidden",
+        };
+
+        write!(f, "{}", status)
+    }
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct NewReview {
+    pub rating: u8,
+    pub title: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct ModerateReview {
+    pub status: ReviewStatus,
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct ReviewQuery {
+    #[serde(default = "ReviewQuery::first_page")]
+    pub page: usize,
+    #[serde(default = "ReviewQuery::default_page_size")]
+    pub per_page: usize,
+}
+
+impl ReviewQuery {
+    fn first_page() -> usize {
+        1
+    }
+
+    fn default_page_size() -> usize {
+        DEFAULT_REVIEW_PAGE_SIZE
+    }
+}
+
+impl Default for ReviewQuery {
+    fn default() -> Self {
+        Self {
+            page: Self::first_page(),
+            per_page: Self::default_page_size(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default, TS)]
+#[ts(export)]
+pub struct RatingSummary {
+    /// Zero when there are no reviews.
+    pub average: f32,
+    pub count: usize,
+}
+
+impl RatingSummary {
+    fn of<'a>(reviews: impl Iterator<Item = &'a Review>) -> Self {
+        let (sum, count) = reviews.fold((0, 0), |(sum, count), r| {
+            (sum + r.rating as usize, count + 1)
+        });
+        Self {
+            average: if count == 0 {
+                0.0
+            } else {
+                sum as f32 / count as f32
+            },
+            count,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, TS)]
+#[ts(export)]
+pub struct ReviewPage {
+    pub reviews: Vec<Review>,
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+    pub next_page: Option<usize>,
+    pub summary: RatingSummary,
+}
+
+#[derive(Debug, TS)]
+#[ts(export)]
+pub enum ReviewError {
+    NotFound,
+    NotPurchased,
+    AlreadyReviewed,
+    InvalidRating,
+    InvalidTitle,
+    BodyTooLong,
+}
+
+impl std::fmt::Display for ReviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = match self {
+            ReviewError::NotFound => "review not found",
+            ReviewError::NotPurchased => "only customers who bought this item can review it",
+            ReviewError::AlreadyReviewed => "you have already reviewed this item",
+            ReviewError::InvalidRating => "rating must be between 1 and 5 stars",
+            ReviewError::InvalidTitle => "title must be between 1 and 120 characters",
+            ReviewError::BodyTooLong => "review must be at most 5000 characters",
+        };
+
+        write!(f, "{}", error)
+    }
+}
+
+impl std::error::Error for ReviewError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        account::{verified_user, UserStore},
+        cart::CartBackend,
+        store::{Inventory, InventoryBackend, Product},
+    };
+
+    #[test]
+    fn only_purchasers_review_and_only_approved_reviews_count() {
+        let inventory = InventoryBackend::new();
+        let carts = CartBackend::new();
+        let orders = OrderBackend::new();
+        let reviews = ReviewBackend::new();
+
+        let user = verified_user(&mut UserStore::default(), "fern@example.com");
+
+        let product = Product::random();
+        let listing_id = product.listing_id;
+        inventory.add_product(product, Inventory::new(3, 0, 0));
+
+        let review = |rating| NewReview {
+            rating,
+            title: "Lovely".to_string(),
+            body: String::new(),
+        };
+        assert!(matches!(
+            reviews.submit(&user, listing_id, &orders, review(5)),
+            Err(ReviewError::NotPurchased)
+        ));
+
+        let cart = carts
+            .add(user.id, &inventory, &listing_id, None, 1)
+            .unwrap();
+        orders.place(&user, &cart, &inventory).unwrap();
+        assert!(matches!(
+            reviews.submit(&user, listing_id, &orders, review(6)),
+            Err(ReviewError::InvalidRating)
+        ));
+
+        let submitted = reviews
+            .submit(&user, listing_id, &orders, review(4))
+            .unwrap();
+        assert!(matches!(
+            reviews.submit(&user, listing_id, &orders, review(4)),
+            Err(ReviewError::AlreadyReviewed)
+        ));
+        assert_eq!(reviews.summary(&listing_id).count, 0);
+
+        reviews
+            .moderate(&submitted.review_id, ReviewStatus::Approved)
+            .unwrap();
+        let page = reviews.reviews(&listing_id, &ReviewQuery::default());
+        assert_eq!(page.total, 1);
+        assert_eq!(page.summary.average, 4.0);
+
+        reviews.rename_author(&user.id, "fern2");
+        assert_eq!(reviews.all()[0].author, "fern2");
+        reviews.anonymise(&user.id);
+        assert_eq!(reviews.all()[0].author, FORMER_CUSTOMER);
+        assert!(!reviews.has_reviewed(&user.id, &listing_id));
+    }
+
+    #[test]
+    fn overlong_reviews_are_refused() {
+        let orders = OrderBackend::new();
+        let reviews = ReviewBackend::new();
+        let user = verified_user(&mut UserStore::default(), "fern@example.com");
+
+        let review = NewReview {
+            rating: 5,
+            title: "Lovely".to_string(),
+            body: "a".repeat(MAX_BODY_LENGTH + 1),
+        };
+        assert!(matches!(
+            reviews.submit(&user, Uuid::new_v4(), &orders, review),
+            Err(ReviewError::BodyTooLong)
+        ));
+    }
+}
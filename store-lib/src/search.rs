@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::store::Product;
+
+const NAME_WEIGHT: f32 = 3.0;
+const DESCRIPTION_WEIGHT: f32 = 1.0;
+
+/// Inverted index over product names and descriptions.
+#[derive(Default)]
+pub struct SearchIndex {
+    terms: HashMap<String, HashMap<Uuid, f32>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, product: &Product) {
+        self.remove(&product.listing_id);
+
+        let fields = [
+            (&product.name, NAME_WEIGHT),
+            (&product.description, DESCRIPTION_WEIGHT),
+        ];
+
+        for (text, weight) in fields {
+            for term in tokenize(text) {
+                *self
+                    .terms
+                    .entry(term)
+                    .or_default()
+                    .entry(product.listing_id)
+                    .or_default() += weight;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, listing_id: &Uuid) {
+        self.terms.retain(|_, postings| {
+            postings.remove(listing_id);
+            !postings.is_empty()
+        });
+    }
+
+    /// Scores every listing containing all terms of `query`. The last term
+    /// also matches as a prefix so results follow the user while they type.
+    pub fn search(&self, query: &str) -> HashMap<Uuid, f32> {
+        let terms = tokenize(query);
+        let Some((last, rest)) = terms.split_last() else {
+            return HashMap::new();
+        };
+
+        let mut matches: Option<HashMap<Uuid, f32>> = None;
+        for term in rest {
+            let scores = self.terms.get(term).cloned().unwrap_or_default();
+            matches = Some(intersect(matches, scores));
+        }
+
+        let mut prefixed: HashMap<Uuid, f32> = HashMap::new();
+        for (term, postings) in self.terms.iter().filter(|(t, _)| t.starts_with(last)) {
+            // Whole-word hits outrank partial ones.
+            let boost = if term == last { 1.0 } else { 0.5 };
+            for (id, score) in postings {
+                *prefixed.entry(*id).or_default() += score * boost;
+            }
+        }
+
+        intersect(matches, prefixed)
+    }
+}
+
+fn intersect(acc: Option<HashMap<Uuid, f32>>, scores: HashMap<Uuid, f32>) -> HashMap<Uuid, f32> {
+    match acc {
+        None => scores,
+        Some(acc) => acc
+            .into_iter()
+            .filter_map(|(id, score)| Some((id, score + scores.get(&id)?)))
+            .collect(),
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let word = word.to_lowercase();
+            // Fold simple plurals so "succulents" finds "Succulent".
+            match word.strip_suffix('s') {
+                Some(stem) if stem.len() > 2 && !stem.ends_with('s') => stem.to_string(),
+                _ => word,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn product(name: &str, description: &str) -> Product {
+        Product {
+            name: name.to_string(),
+            description: description.to_string(),
+            ..Product::random()
+        }
+    }
+
+    #[test]
+    fn ranks_name_matches_above_description_matches() {
+        let mut index = SearchIndex::new();
+        let pothos = product("Golden Pothos", "A trailing vine");
+        let aroid = product("Aroid", "Related to the pothos family");
+        let quartz = product("Quartz", "A clear crystal");
+        for p in [&pothos, &aroid, &quartz] {
+            index.insert(p);
+        }
+
+        let results = index.search("Pothos");
+        assert_eq!(results.len(), 2);
+        assert!(results[&pothos.listing_id] > results[&aroid.listing_id]);
+
+        assert_eq!(index.search("golden pot").len(), 1);
+        assert!(index.search("golden crystal").is_empty());
+        assert!(index.search("  ").is_empty());
+    }
+}
@@ -0,0 +1,306 @@
+use axum_login::axum::async_trait;
+use axum_login::tower_sessions::{
+    cookie::time::OffsetDateTime,
+    session::{Id, Record},
+    session_store::{self, ExpiredDeletion},
+    SessionStore,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{any::AnyPoolOptions, AnyPool, Row};
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// Works in both Postgres and SQLite, so one store covers production and
+/// local development.
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    data TEXT NOT NULL,
+    expiry_date BIGINT NOT NULL,
+    user_id TEXT
+)";
+
+/// Where a logged in session keeps its `SessionInfo`.
+pub const SESSION_INFO_KEY: &str = "session_info";
+
+/// Where and when a login has been used, so users can see their sessions
+/// and revoke them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+#[ts(export)]
+pub struct SessionInfo {
+    /// Names the session without giving away its cookie.
+    pub key: Uuid,
+    pub user_id: Uuid,
+    pub created: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub ip: String,
+    pub user_agent: String,
+}
+
+impl SessionInfo {
+    fn of(record: &Record) -> Option<Self> {
+        let info = record.data.get(SESSION_INFO_KEY)?;
+        serde_json::from_value(info.clone()).ok()
+    }
+}
+
+/// Keeps sessions in a database so they survive restarts and can be shared
+/// between instances. Takes a `postgres://` or `sqlite://` URL.
+#[derive(Clone, Debug)]
+pub struct SqlSessionStore {
+    pool: AnyPool,
+}
+
+impl SqlSessionStore {
+    /// Connects and creates the sessions table if it is missing.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        sqlx::any::install_default_drivers();
+        let pool = AnyPoolOptions::new().connect(url).await?;
+        Self::new(pool).await
+    }
+
+    pub async fn new(pool: AnyPool) -> Result<Self, sqlx::Error> {
+        sqlx::query(CREATE_TABLE).execute(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    /// The user's unexpired sessions, most recently used first, with their
+    /// ids.
+    async fn sessions_of(
+        &self,
+        user_id: &Uuid,
+    ) -> session_store::Result<Vec<(String, SessionInfo)>> {
+        let rows =
+            sqlx::query("SELECT id, data FROM sessions WHERE user_id = $1 AND expiry_date > $2")
+                .bind(user_id.to_string())
+                .bind(OffsetDateTime::now_utc().unix_timestamp())
+                .fetch_all(&self.pool)
+                .await
+                .map_err(backend)?;
+
+        let mut sessions = Vec::new();
+        for row in rows {
+            let data: String = row.try_get("data").map_err(backend)?;
+            let info = serde_json::from_str::<Record>(&data)
+                .ok()
+                .and_then(|record| SessionInfo::of(&record));
+            if let Some(info) = info {
+                sessions.push((row.try_get("id").map_err(backend)?, info));
+            }
+        }
+        sessions.sort_by_key(|(_, info)| std::cmp::Reverse(info.last_seen));
+        Ok(sessions)
+    }
+
+    pub async fn user_sessions(&self, user_id: &Uuid) -> session_store::Result<Vec<SessionInfo>> {
+        let sessions = self.sessions_of(user_id).await?;
+        Ok(sessions.into_iter().map(|(_, info)| info).collect())
+    }
+
+    /// Ends one of the user's sessions, returning whether it was found.
+    pub async fn revoke(&self, user_id: &Uuid, key: &Uuid) -> session_store::Result<bool> {
+        let sessions = self.sessions_of(user_id).await?;
+        let Some((id, _)) = sessions.iter().find(|(_, info)| info.key == *key) else {
+            return Ok(false);
+        };
+        self.delete_by_id(id).await?;
+        Ok(true)
+    }
+
+    /// Ends every session of the user's but `keep`, returning how many.
+    pub async fn revoke_others(&self, user_id: &Uuid, keep: &Uuid) -> session_store::Result<usize> {
+        let sessions = self.sessions_of(user_id).await?;
+        let mut revoked = 0;
+        for (id, _) in sessions.iter().filter(|(_, info)| info.key != *keep) {
+            self.delete_by_id(id).await?;
+            revoked += 1;
+        }
+        Ok(revoked)
+    }
+
+    /// Ends every session of the user's, returning how many.
+    pub async fn revoke_all(&self, user_id: &Uuid) -> session_store::Result<u64> {
+        let deleted = sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?;
+        Ok(deleted.rows_affected())
+    }
+
+    async fn delete_by_id(&self, id: &str) -> session_store::Result<()> {
+        sqlx::query("DELETE FROM sessions WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?;
+        Ok(())
+    }
+}
+
+fn user_id(record: &Record) -> Option<String> {
+    SessionInfo::of(record).map(|info| info.user_id.to_string())
+}
+
+fn backend(e: sqlx::Error) -> session_store::Error {
+    session_store::Error::Backend(e.to_string())
+}
+
+fn encode(record: &Record) -> session_store::Result<String> {
+    serde_json::to_string(record).map_err(|e| session_store::Error::Encode(e.to_string()))
+}
+
+#[async_trait]
+impl SessionStore for SqlSessionStore {
+    /// Inserts the record, picking a new id if the random one is taken.
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        loop {
+            let inserted = sqlx::query(
+                "INSERT INTO sessions (id, data, expiry_date, user_id) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (id) DO NOTHING",
+            )
+            .bind(record.id.to_string())
+            .bind(encode(record)?)
+            .bind(record.expiry_date.unix_timestamp())
+            .bind(user_id(record))
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?;
+
+            if inserted.rows_affected() > 0 {
+                return Ok(());
+            }
+            record.id = Id::default();
+        }
+    }
+
+    /// Updates the record only if it is still stored. A request that was
+    /// already running when its session got revoked must not write it back.
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        sqlx::query("UPDATE sessions SET data = $1, expiry_date = $2, user_id = $3 WHERE id = $4")
+            .bind(encode(record)?)
+            .bind(record.expiry_date.unix_timestamp())
+            .bind(user_id(record))
+            .bind(record.id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?;
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let row = sqlx::query("SELECT data FROM sessions WHERE id = $1 AND expiry_date > $2")
+            .bind(session_id.to_string())
+            .bind(OffsetDateTime::now_utc().unix_timestamp())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(backend)?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let data: String = row.try_get("data").map_err(backend)?;
+        serde_json::from_str(&data)
+            .map(Some)
+            .map_err(|e| session_store::Error::Decode(e.to_string()))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        self.delete_by_id(&session_id.to_string()).await
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for SqlSessionStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        sqlx::query("DELETE FROM sessions WHERE expiry_date <= $1")
+            .bind(OffsetDateTime::now_utc().unix_timestamp())
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum_login::tower_sessions::cookie::time::Duration;
+
+    #[test]
+    fn sessions_round_trip_until_they_expire() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            sqlx::any::install_default_drivers();
+            // One connection, since each opens its own in-memory database.
+            let pool = AnyPoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .unwrap();
+            let store = SqlSessionStore::new(pool).await.unwrap();
+
+            let mut record = Record {
+                id: Id::default(),
+                data: [("cart".to_string(), serde_json::json!(["rose quartz"]))].into(),
+                expiry_date: OffsetDateTime::now_utc() + Duration::hours(1),
+            };
+            store.create(&mut record).await.unwrap();
+            let loaded = store.load(&record.id).await.unwrap().unwrap();
+            assert_eq!(loaded.data, record.data);
+
+            record.expiry_date = OffsetDateTime::now_utc() - Duration::hours(1);
+            store.save(&record).await.unwrap();
+            assert!(store.load(&record.id).await.unwrap().is_none());
+
+            store.delete_expired().await.unwrap();
+            let left: i64 = sqlx::query("SELECT COUNT(*) AS left FROM sessions")
+                .fetch_one(&store.pool)
+                .await
+                .unwrap()
+                .get("left");
+            assert_eq!(left, 0);
+
+            // Logged in sessions can be listed and revoked by their key.
+            let user_id = Uuid::new_v4();
+            let mut keys = Vec::new();
+            let mut records = Vec::new();
+            for _ in 0..3 {
+                let info = SessionInfo {
+                    key: Uuid::new_v4(),
+                    user_id,
+                    created: Utc::now(),
+                    last_seen: Utc::now(),
+                    ip: "203.0.113.7".to_string(),
+                    user_agent: "curl".to_string(),
+                };
+                keys.push(info.key);
+                let mut record = Record {
+                    id: Id::default(),
+                    data: [(SESSION_INFO_KEY.to_string(), serde_json::json!(info))].into(),
+                    expiry_date: OffsetDateTime::now_utc() + Duration::hours(1),
+                };
+                store.create(&mut record).await.unwrap();
+                records.push(record);
+            }
+            assert_eq!(store.user_sessions(&user_id).await.unwrap().len(), 3);
+            assert!(store.revoke(&user_id, &keys[0]).await.unwrap());
+            assert!(!store.revoke(&Uuid::new_v4(), &keys[1]).await.unwrap());
+            assert_eq!(store.revoke_others(&user_id, &keys[1]).await.unwrap(), 1);
+            let left = store.user_sessions(&user_id).await.unwrap();
+            assert_eq!(left.iter().map(|s| s.key).collect::<Vec<_>>(), [keys[1]]);
+
+            // Requests still running on revoked sessions can't save them back.
+            for record in &records {
+                store.save(record).await.unwrap();
+            }
+            assert!(store.load(&records[0].id).await.unwrap().is_none());
+            assert_eq!(store.user_sessions(&user_id).await.unwrap().len(), 1);
+            assert_eq!(store.revoke_all(&user_id).await.unwrap(), 1);
+            assert!(store.user_sessions(&user_id).await.unwrap().is_empty());
+        });
+    }
+}
@@ -1,4 +1,6 @@
+use axum_login::tracing::info;
 use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
 use rand::distributions::Uniform;
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -9,24 +11,293 @@ use std::{
 use ts_rs::TS;
 use uuid::Uuid;
 
+use crate::search::SearchIndex;
+
+/// Told when a product that had no free stock in any variant gets some back.
+pub trait RestockListener: Send + Sync {
+    fn restocked(&self, product: &Product);
+}
+
 #[derive(Clone)]
 pub struct InventoryBackend {
     pub inventory: Arc<Mutex<HashMap<Uuid, Inventory>>>,
     pub products: Arc<Mutex<HashMap<Uuid, Product>>>,
+    pub ledger: Arc<Mutex<Vec<StockAdjustment>>>,
+    pub search: Arc<Mutex<SearchIndex>>,
+    restock_listeners: Arc<Mutex<Vec<Arc<dyn RestockListener>>>>,
 }
+
 impl InventoryBackend {
     pub fn new() -> Self {
         Self {
             inventory: Arc::new(Mutex::new(HashMap::new())),
             products: Arc::new(Mutex::new(HashMap::new())),
+            ledger: Arc::new(Mutex::new(Vec::new())),
+            search: Arc::new(Mutex::new(SearchIndex::new())),
+            restock_listeners: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn on_restock(&self, listener: Arc<dyn RestockListener>) {
+        let mut listeners = self
+            .restock_listeners
+            .lock()
+            .expect("inventory backend threads");
+        listeners.push(listener);
+    }
+
+    /// Lists a product with `stock` for each of its variants, recording the
+    /// opening `free` stock as a restock so the ledger always sums to the
+    /// current count.
+    pub fn add_product(&self, product: Product, stock: Inventory) {
+        let stock = vec![stock; product.variants.len()];
+        self.add_product_with_stock(product, stock);
+    }
+
+    /// Like `add_product`, with stock given per variant, in order.
+    pub fn add_product_with_stock(&self, product: Product, stock: Vec<Inventory>) {
+        let mut products = self.products.lock().expect("inventory backend threads");
+        let mut inventory = self.inventory.lock().expect("inventory backend threads");
+        let mut ledger = self.ledger.lock().expect("inventory backend threads");
+        let mut search = self.search.lock().expect("inventory backend threads");
+
+        search.insert(&product);
+        for (variant, stock) in product.variants.iter().zip(stock) {
+            ledger.push(StockAdjustment::new(
+                product.listing_id,
+                variant.variant_id,
+                None,
+                stock.free as i64,
+                AdjustmentReason::Restock,
+            ));
+            inventory.insert(variant.variant_id, stock);
+        }
+        products.insert(product.listing_id, product);
+    }
+
+    pub fn product(&self, listing_id: &Uuid) -> Option<Product> {
+        let products = self.products.lock().expect("inventory backend threads");
+        products.get(listing_id).cloned()
+    }
+
+    /// The product and variant a stock-keeping id belongs to.
+    pub fn variant(&self, variant_id: &Uuid) -> Option<(Product, Variant)> {
+        let products = self.products.lock().expect("inventory backend threads");
+        products.values().find_map(|product| {
+            let variant = product.variant(variant_id)?.clone();
+            Some((product.clone(), variant))
+        })
+    }
+
+    /// Stock of a single variant. A product's first variant shares its
+    /// listing id, so this is also the stock of single-variant products.
+    pub fn stock(&self, variant_id: &Uuid) -> Option<Inventory> {
+        let inventory = self.inventory.lock().expect("inventory backend threads");
+        inventory.get(variant_id).cloned()
+    }
+
+    /// Stock of a product summed over all of its variants.
+    pub fn product_stock(&self, listing_id: &Uuid) -> Option<Inventory> {
+        let products = self.products.lock().expect("inventory backend threads");
+        let inventory = self.inventory.lock().expect("inventory backend threads");
+        Inventory::total(products.get(listing_id)?, &inventory)
+    }
+
+    pub fn adjust(
+        &self,
+        variant_id: Uuid,
+        user_id: Uuid,
+        AdjustStock { delta, reason }: AdjustStock,
+    ) -> Result<Inventory, InventoryError> {
+        if reason == AdjustmentReason::Sale {
+            return Err(InventoryError::ManualSale);
+        }
+        let (product, _) = self.variant(&variant_id).ok_or(InventoryError::NotFound)?;
+        let (stock, restocked) = {
+            let mut inventory = self.inventory.lock().expect("inventory backend threads");
+            let mut ledger = self.ledger.lock().expect("inventory backend threads");
+            let was_available = Inventory::total(&product, &inventory).is_some_and(|s| s.free > 0);
+
+            let stock = inventory
+                .get_mut(&variant_id)
+                .ok_or(InventoryError::NotFound)?;
+
+            let free = stock.free as i64 + delta;
+            if free < 0 {
+                return Err(InventoryError::InsufficientStock);
+            }
+
+            stock.free = free as usize;
+            let stock = stock.clone();
+            ledger.push(StockAdjustment::new(
+                product.listing_id,
+                variant_id,
+                Some(user_id),
+                delta,
+                reason,
+            ));
+            info!("Adjusted stock of {variant_id} by {delta} ({reason})");
+
+            (stock, !was_available && free > 0)
+        };
+
+        // Listeners may read the inventory, so they run once it is unlocked.
+        if restocked {
+            let listeners = self
+                .restock_listeners
+                .lock()
+                .expect("inventory backend threads")
+                .clone();
+            for listener in listeners {
+                listener.restocked(&product);
+            }
+        }
+
+        Ok(stock)
+    }
+
+    /// Moves `number` of each variant from free to ordered stock for a
+    /// customer's order. Either every line is taken or none are.
+    pub fn sell(&self, user_id: Uuid, lines: &[(Uuid, usize)]) -> Result<(), InventoryError> {
+        let products = self.products.lock().expect("inventory backend threads");
+        let mut inventory = self.inventory.lock().expect("inventory backend threads");
+        let mut ledger = self.ledger.lock().expect("inventory backend threads");
+
+        let mut listings = Vec::with_capacity(lines.len());
+        for (variant_id, number) in lines {
+            let listing_id = products
+                .values()
+                .find(|product| product.variant(variant_id).is_some())
+                .map(|product| product.listing_id)
+                .ok_or(InventoryError::NotFound)?;
+            let stock = inventory.get(variant_id).ok_or(InventoryError::NotFound)?;
+            if stock.free < *number {
+                return Err(InventoryError::InsufficientStock);
+            }
+            listings.push(listing_id);
+        }
+
+        for ((variant_id, number), listing_id) in lines.iter().zip(listings) {
+            let stock = inventory.get_mut(variant_id).expect("stock checked above");
+            stock.free -= number;
+            stock.ordered += number;
+            ledger.push(StockAdjustment::new(
+                listing_id,
+                *variant_id,
+                Some(user_id),
+                -(*number as i64),
+                AdjustmentReason::Sale,
+            ));
         }
+
+        Ok(())
+    }
+
+    /// Moves the stock of a shipped order from ordered to sent.
+    pub fn ship(&self, lines: &[(Uuid, usize)]) {
+        let mut inventory = self.inventory.lock().expect("inventory backend threads");
+        for (variant_id, number) in lines {
+            if let Some(stock) = inventory.get_mut(variant_id) {
+                stock.ordered = stock.ordered.saturating_sub(*number);
+                stock.sent += number;
+            }
+        }
+    }
+
+    /// Listings without a stock record are treated as unavailable.
+    pub fn stock_status(&self, listing_id: &Uuid) -> StockStatus {
+        self.product_stock(listing_id)
+            .map(|stock| stock.status())
+            .unwrap_or(StockStatus::OutOfStock)
+    }
+
+    pub fn variant_status(&self, variant_id: &Uuid) -> StockStatus {
+        self.stock(variant_id)
+            .map(|stock| stock.status())
+            .unwrap_or(StockStatus::OutOfStock)
+    }
+
+    pub fn set_low_stock_threshold(
+        &self,
+        variant_id: &Uuid,
+        LowStockThreshold { threshold }: LowStockThreshold,
+    ) -> Result<Inventory, InventoryError> {
+        let mut inventory = self.inventory.lock().expect("inventory backend threads");
+        let stock = inventory
+            .get_mut(variant_id)
+            .ok_or(InventoryError::NotFound)?;
+        stock.low_stock_threshold = threshold;
+        Ok(stock.clone())
+    }
+
+    /// Every variant with its stock, ordered by product name.
+    pub fn stock_levels(&self) -> Vec<(Product, Variant, Inventory)> {
+        let products = self.products.lock().expect("inventory backend threads");
+        let inventory = self.inventory.lock().expect("inventory backend threads");
+
+        let mut products: Vec<&Product> = products.values().collect();
+        products.sort_by(|a, b| a.name.cmp(&b.name).then(a.listing_id.cmp(&b.listing_id)));
+
+        products
+            .into_iter()
+            .flat_map(|product| {
+                product.variants.iter().filter_map(|variant| {
+                    let stock = inventory.get(&variant.variant_id)?.clone();
+                    Some((product.clone(), variant.clone(), stock))
+                })
+            })
+            .collect()
+    }
+
+    /// Variants that are low on or out of stock, emptiest first.
+    pub fn low_stock_alerts(&self) -> Vec<(Product, Variant, Inventory)> {
+        let mut alerts: Vec<_> = self
+            .stock_levels()
+            .into_iter()
+            .filter(|(_, _, stock)| stock.status() != StockStatus::InStock)
+            .collect();
+        alerts.sort_by_key(|(_, _, stock)| stock.free);
+        alerts
+    }
+
+    /// Adjustments for a variant, newest first.
+    pub fn ledger(&self, variant_id: &Uuid) -> Vec<StockAdjustment> {
+        let ledger = self.ledger.lock().expect("inventory backend threads");
+        ledger
+            .iter()
+            .rev()
+            .filter(|entry| entry.variant_id == *variant_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Rebuilds the `free` count of a variant from its ledger alone.
+    pub fn reconstruct_free(&self, variant_id: &Uuid) -> i64 {
+        let ledger = self.ledger.lock().expect("inventory backend threads");
+        ledger
+            .iter()
+            .filter(|entry| entry.variant_id == *variant_id)
+            .map(|entry| entry.delta)
+            .sum()
+    }
+}
+
+impl Default for InventoryBackend {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+/// Free stock at or below which a listing is shown as running low.
+pub const DEFAULT_LOW_STOCK_THRESHOLD: usize = 5;
+
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export)]
 pub struct Inventory {
     pub free: usize,
     pub ordered: usize,
     pub sent: usize,
+    pub low_stock_threshold: usize,
 }
 
 impl Inventory {
@@ -35,6 +306,153 @@ impl Inventory {
             free,
             ordered,
             sent,
+            low_stock_threshold: DEFAULT_LOW_STOCK_THRESHOLD,
+        }
+    }
+
+    /// Sums the stock of every variant of `product`. The threshold is the
+    /// largest of the variants', as summing them would call a product with
+    /// plenty of every variant low on stock.
+    pub fn total(product: &Product, inventory: &HashMap<Uuid, Inventory>) -> Option<Inventory> {
+        product
+            .variants
+            .iter()
+            .filter_map(|variant| inventory.get(&variant.variant_id))
+            .fold(None, |total: Option<Inventory>, stock| {
+                let mut total = total.unwrap_or(Inventory {
+                    low_stock_threshold: 0,
+                    ..Inventory::new(0, 0, 0)
+                });
+                total.free += stock.free;
+                total.ordered += stock.ordered;
+                total.sent += stock.sent;
+                total.low_stock_threshold =
+                    total.low_stock_threshold.max(stock.low_stock_threshold);
+                Some(total)
+            })
+    }
+
+    pub fn status(&self) -> StockStatus {
+        match self.free {
+            0 => StockStatus::OutOfStock,
+            n if n <= self.low_stock_threshold => StockStatus::LowStock(n),
+            _ => StockStatus::InStock,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum StockStatus {
+    InStock,
+    LowStock(usize),
+    OutOfStock,
+}
+
+impl StockStatus {
+    pub fn available(&self) -> bool {
+        *self != StockStatus::OutOfStock
+    }
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct LowStockThreshold {
+    pub threshold: usize,
+}
+
+#[derive(Debug, TS)]
+#[ts(export)]
+pub enum InventoryError {
+    NotFound,
+    InsufficientStock,
+    /// Sales are recorded by orders, never by hand.
+    ManualSale,
+}
+
+impl std::fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let error = match self {
+            InventoryError::NotFound => "not found",
+            InventoryError::InsufficientStock => "not enough stock",
+            InventoryError::ManualSale => "sales can't be recorded by hand",
+        };
+
+        write!(f, "{}", error)
+    }
+}
+
+impl std::error::Error for InventoryError {}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum AdjustmentReason {
+    Restock,
+    Damage,
+    Correction,
+    /// Stock taken by a placed order. Not offered as a manual adjustment.
+    Sale,
+}
+
+impl AdjustmentReason {
+    /// Reasons staff can pick when adjusting stock by hand.
+    pub const ALL: [AdjustmentReason; 3] = [
+        AdjustmentReason::Restock,
+        AdjustmentReason::Damage,
+        AdjustmentReason::Correction,
+    ];
+}
+
+impl std::fmt::Display for AdjustmentReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            AdjustmentReason::Restock => "Restock",
+            AdjustmentReason::Damage => "Damage",
+            AdjustmentReason::Correction => "Correction",
+            AdjustmentReason::Sale => "Sale",
+        };
+
+        write!(f, "{}", reason)
+    }
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct AdjustStock {
+    pub delta: i64,
+    pub reason: AdjustmentReason,
+}
+
+/// A single change to a listing's `free` stock. `user_id` is empty for
+/// adjustments made by the store itself, such as opening stock.
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export)]
+pub struct StockAdjustment {
+    pub id: Uuid,
+    pub listing_id: Uuid,
+    pub variant_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub delta: i64,
+    pub reason: AdjustmentReason,
+}
+
+impl StockAdjustment {
+    fn new(
+        listing_id: Uuid,
+        variant_id: Uuid,
+        user_id: Option<Uuid>,
+        delta: i64,
+        reason: AdjustmentReason,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            listing_id,
+            variant_id,
+            user_id,
+            created_at: Utc::now(),
+            delta,
+            reason,
         }
     }
 }
@@ -44,26 +462,192 @@ impl Inventory {
 pub struct Product {
     pub listing_id: Uuid,
     pub name: String,
+    /// The lowest variant price, shown as "from" on listings.
     pub price: BigDecimal,
     pub description: String,
     pub image: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub listed_at: DateTime<Utc>,
+    pub specs: ProductSpecs,
+    /// Never empty. The first variant shares the product's `listing_id`.
+    pub variants: Vec<Variant>,
+}
+
+/// A purchasable configuration of a product, such as a size and pot colour,
+/// with its own SKU, price and stock.
+#[derive(Deserialize, Serialize, Clone, Debug, TS)]
+#[ts(export)]
+pub struct Variant {
+    pub variant_id: Uuid,
+    pub sku: String,
+    pub options: Vec<VariantOption>,
+    pub price: BigDecimal,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, TS)]
+#[ts(export)]
+pub struct VariantOption {
+    pub name: String,
+    pub value: String,
+}
+
+impl Variant {
+    /// "Large / White", or "Standard" for products without options.
+    pub fn label(&self) -> String {
+        if self.options.is_empty() {
+            return "Standard".to_string();
+        }
+
+        self.options
+            .iter()
+            .map(|option| option.value.as_str())
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+/// Care and physical details shown on the product page. Plants fill in the
+/// care fields, rocks the mineral ones.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, TS)]
+#[ts(export)]
+pub struct ProductSpecs {
+    pub light: Option<LightNeeds>,
+    pub watering: Option<Watering>,
+    pub pet_safe: Option<bool>,
+    pub size: Option<String>,
+    /// Mohs scale, 1 to 10.
+    pub hardness: Option<f32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum LightNeeds {
+    Low,
+    Medium,
+    BrightIndirect,
+    Direct,
+}
+
+impl std::fmt::Display for LightNeeds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let light = match self {
+            LightNeeds::Low => "Low light",
+            LightNeeds::Medium => "Medium light",
+            LightNeeds::BrightIndirect => "Bright, indirect light",
+            LightNeeds::Direct => "Direct sun",
+        };
+
+        write!(f, "{}", light)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, TS)]
+#[ts(export)]
+pub enum Watering {
+    Weekly,
+    Fortnightly,
+    Monthly,
 }
 
+impl std::fmt::Display for Watering {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let watering = match self {
+            Watering::Weekly => "Once a week",
+            Watering::Fortnightly => "Every two weeks",
+            Watering::Monthly => "Once a month",
+        };
+
+        write!(f, "{}", watering)
+    }
+}
+
+struct Template {
+    name: &'static str,
+    category: &'static str,
+    tags: &'static [&'static str],
+    light: Option<LightNeeds>,
+    watering: Option<Watering>,
+    pet_safe: Option<bool>,
+    hardness: Option<f32>,
+}
+
+const fn plant(
+    name: &'static str,
+    tags: &'static [&'static str],
+    light: LightNeeds,
+    watering: Watering,
+    pet_safe: bool,
+) -> Template {
+    Template {
+        name,
+        category: "Plants",
+        tags,
+        light: Some(light),
+        watering: Some(watering),
+        pet_safe: Some(pet_safe),
+        hardness: None,
+    }
+}
+
+const fn rock(name: &'static str, tags: &'static [&'static str], hardness: f32) -> Template {
+    Template {
+        name,
+        category: "Rocks",
+        tags,
+        light: None,
+        watering: None,
+        pet_safe: None,
+        hardness: Some(hardness),
+    }
+}
+
+const RANDOM_PRODUCTS: [Template; 7] = [
+    rock("Amethyst", &["Crystal", "Purple"], 7.0),
+    plant(
+        "Pothos",
+        &["Low Light", "Trailing", "Beginner"],
+        LightNeeds::Low,
+        Watering::Weekly,
+        false,
+    ),
+    rock("Ruby", &["Gemstone", "Red"], 9.0),
+    plant(
+        "Aroid",
+        &["Tropical", "Rare"],
+        LightNeeds::BrightIndirect,
+        Watering::Weekly,
+        false,
+    ),
+    rock("Garnet", &["Gemstone", "Red"], 7.5),
+    rock("Diamond", &["Gemstone", "Rare"], 10.0),
+    plant(
+        "Succulent",
+        &["Pet Safe", "Beginner"],
+        LightNeeds::Direct,
+        Watering::Monthly,
+        true,
+    ),
+];
+
 impl Product {
+    pub fn default_variant(&self) -> &Variant {
+        &self.variants[0]
+    }
+
+    pub fn variant(&self, variant_id: &Uuid) -> Option<&Variant> {
+        self.variants.iter().find(|v| v.variant_id == *variant_id)
+    }
+
+    pub fn has_options(&self) -> bool {
+        self.variants.len() > 1
+    }
+
     pub fn random() -> Self {
         let mut rng = thread_rng();
-        let name = vec![
-            "Amethyst",
-            "Pothos",
-            "Ruby",
-            "Aroid",
-            "Garnet",
-            "Diamond",
-            "Succulent",
-        ]
-        .choose(&mut rng)
-        .expect("Hard coded list has items")
-        .to_string();
+        let template = RANDOM_PRODUCTS
+            .choose(&mut rng)
+            .expect("Hard coded list has items");
 
         let image = vec![
             "amethyst.jpg",
@@ -82,12 +666,142 @@ impl Product {
         let price = BigDecimal::new(rng.sample(Uniform::new(100, 3000)).into(), 2);
         let description = "The description of the item we are looking at".to_string();
 
+        let variants = random_variants(template, listing_id, &price);
+        let size = template.hardness.and_then(|_| {
+            ["Tumbled", "Palm-sized", "Display piece"]
+                .choose(&mut rng)
+                .map(|size| size.to_string())
+        });
+
         Self {
-            name,
+            name: template.name.to_string(),
             listing_id,
             price,
             image,
             description,
+            category: template.category.to_string(),
+            tags: template.tags.iter().map(|t| t.to_string()).collect(),
+            listed_at: Utc::now(),
+            specs: ProductSpecs {
+                light: template.light,
+                watering: template.watering,
+                pet_safe: template.pet_safe,
+                size,
+                hardness: template.hardness,
+            },
+            variants,
         }
     }
 }
+
+/// Plants come in two sizes and pot colours, rocks as a single piece.
+fn random_variants(template: &Template, listing_id: Uuid, price: &BigDecimal) -> Vec<Variant> {
+    let sku = |options: &[&str]| {
+        let mut parts = vec![
+            template.name.to_uppercase(),
+            listing_id.simple().to_string()[..6].to_uppercase(),
+        ];
+        parts.extend(options.iter().map(|o| o.to_uppercase()));
+        parts.join("-")
+    };
+
+    if template.category != "Plants" {
+        return vec![Variant {
+            variant_id: listing_id,
+            sku: sku(&[]),
+            options: Vec::new(),
+            price: price.clone(),
+        }];
+    }
+
+    let sizes = [
+        ("Small", BigDecimal::from(0)),
+        ("Large", BigDecimal::from(10)),
+    ];
+    let pots = ["Terracotta", "White"];
+
+    sizes
+        .iter()
+        .flat_map(|(size, upcharge)| pots.iter().map(move |pot| (*size, upcharge, *pot)))
+        .enumerate()
+        .map(|(i, (size, upcharge, pot))| Variant {
+            variant_id: if i == 0 { listing_id } else { Uuid::new_v4() },
+            sku: sku(&[size, pot]),
+            options: vec![
+                VariantOption {
+                    name: "Size".to_string(),
+                    value: size.to_string(),
+                },
+                VariantOption {
+                    name: "Pot".to_string(),
+                    value: pot.to_string(),
+                },
+            ],
+            price: price + upcharge,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ledger_reconstructs_free_stock() {
+        let backend = InventoryBackend::new();
+        let product = Product::random();
+        let listing_id = product.listing_id;
+        let admin = Uuid::new_v4();
+        backend.add_product(product, Inventory::new(12, 3, 15));
+
+        let adjust =
+            |delta, reason| backend.adjust(listing_id, admin, AdjustStock { delta, reason });
+        adjust(5, AdjustmentReason::Restock).unwrap();
+        adjust(-2, AdjustmentReason::Damage).unwrap();
+        assert!(matches!(
+            adjust(-100, AdjustmentReason::Correction),
+            Err(InventoryError::InsufficientStock)
+        ));
+        assert!(matches!(
+            adjust(-1, AdjustmentReason::Sale),
+            Err(InventoryError::ManualSale)
+        ));
+
+        assert_eq!(backend.stock(&listing_id).unwrap().free, 15);
+        assert_eq!(backend.reconstruct_free(&listing_id), 15);
+        assert_eq!(backend.ledger(&listing_id).len(), 3);
+    }
+
+    #[test]
+    fn stock_status_follows_threshold() {
+        let mut stock = Inventory::new(6, 0, 0);
+        assert_eq!(stock.status(), StockStatus::InStock);
+
+        stock.low_stock_threshold = 6;
+        assert_eq!(stock.status(), StockStatus::LowStock(6));
+
+        stock.free = 0;
+        assert_eq!(stock.status(), StockStatus::OutOfStock);
+    }
+
+    #[test]
+    fn product_threshold_is_not_summed_over_variants() {
+        let backend = InventoryBackend::new();
+        let mut product = Product::random();
+        let first = product.default_variant().clone();
+        product.variants = (0..4)
+            .map(|i| Variant {
+                variant_id: if i == 0 {
+                    first.variant_id
+                } else {
+                    Uuid::new_v4()
+                },
+                ..first.clone()
+            })
+            .collect();
+        let listing_id = product.listing_id;
+        backend.add_product(product, Inventory::new(8, 0, 0));
+
+        assert_eq!(backend.stock_status(&listing_id), StockStatus::InStock);
+    }
+}
@@ -0,0 +1,201 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, TOTP};
+use ts_rs::TS;
+
+/// Recovery codes issued at a time.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+const ISSUER: &str = "Plantomics";
+const SECRET_BYTES: usize = 20;
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+/// Steps either side of now accepted, for clocks that have drifted.
+const SKEW_STEPS: u64 = 1;
+
+/// A user's authenticator secret and recovery codes. Nothing guards the
+/// account until a first code confirms the app was set up. Recovery codes
+/// are kept hashed and each works once.
+#[derive(Clone, Debug)]
+pub struct TwoFactor {
+    secret: Vec<u8>,
+    confirmed: bool,
+    recovery_codes: Vec<String>,
+    /// The last time step a code was accepted for, so codes can't be
+    /// replayed.
+    last_step: Option<u64>,
+}
+
+/// What an authenticator app needs to be set up, as a secret to type in or
+/// an `otpauth://` URI to show as a QR code.
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export)]
+pub struct TwoFactorSetup {
+    pub secret: String,
+    pub uri: String,
+}
+
+/// A code from an authenticator app, or a recovery code.
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
+/// The password, asked for again before turning 2FA off or replacing the
+/// recovery codes.
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct ConfirmPassword {
+    pub password: String,
+}
+
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export)]
+pub struct RecoveryCodes {
+    pub codes: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct StaffTwoFactorPolicy {
+    pub required: bool,
+}
+
+impl TwoFactor {
+    pub fn new() -> Self {
+        Self {
+            secret: rand::random::<[u8; SECRET_BYTES]>().to_vec(),
+            confirmed: false,
+            recovery_codes: Vec::new(),
+            last_step: None,
+        }
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed
+    }
+
+    pub fn setup(&self, account: &str) -> TwoFactorSetup {
+        let totp = self.totp(account);
+        TwoFactorSetup {
+            secret: totp.get_secret_base32(),
+            uri: totp.get_url(),
+        }
+    }
+
+    /// Confirms setup with a first code, returning the recovery codes.
+    pub fn confirm(&mut self, code: &str, now: DateTime<Utc>) -> Option<Vec<String>> {
+        if !self.check_code(code, now) {
+            return None;
+        }
+        self.confirmed = true;
+        Some(self.issue_recovery_codes())
+    }
+
+    /// Checks a code from the app, or failing that uses up a recovery code.
+    pub fn check(&mut self, code: &str, now: DateTime<Utc>) -> bool {
+        self.check_code(code, now) || self.use_recovery_code(code)
+    }
+
+    /// Replaces the recovery codes, returning the new ones in the clear.
+    pub fn issue_recovery_codes(&mut self) -> Vec<String> {
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| {
+                let [a, b] = rand::random::<[u32; 2]>();
+                format!("{:05x}-{:05x}", a & 0xfffff, b & 0xfffff)
+            })
+            .collect();
+        self.recovery_codes = codes.iter().map(|code| hash(code)).collect();
+        codes
+    }
+
+    pub fn recovery_codes_left(&self) -> usize {
+        self.recovery_codes.len()
+    }
+
+    fn check_code(&mut self, code: &str, now: DateTime<Utc>) -> bool {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        let Ok(now) = u64::try_from(now.timestamp()) else {
+            return false;
+        };
+        let totp = self.totp("");
+        let current = now / STEP_SECONDS;
+        let step = (current.saturating_sub(SKEW_STEPS)..=current + SKEW_STEPS)
+            .filter(|step| Some(*step) > self.last_step)
+            .find(|step| totp.generate(step * STEP_SECONDS) == code);
+
+        match step {
+            Some(step) => {
+                self.last_step = Some(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn use_recovery_code(&mut self, code: &str) -> bool {
+        let hashed = hash(code);
+        let before = self.recovery_codes.len();
+        self.recovery_codes.retain(|code| *code != hashed);
+        self.recovery_codes.len() < before
+    }
+
+    fn totp(&self, account: &str) -> TOTP {
+        TOTP::new_unchecked(
+            Algorithm::SHA1,
+            DIGITS,
+            0,
+            STEP_SECONDS,
+            self.secret.clone(),
+            Some(ISSUER.to_string()),
+            account.to_string(),
+        )
+    }
+}
+
+impl Default for TwoFactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hashes a recovery code, ignoring case, spaces and dashes.
+fn hash(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code.as_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn codes_work_once_and_recovery_codes_are_single_use() {
+        let mut two_factor = TwoFactor::new();
+        let now = Utc::now();
+        let code = two_factor.totp("").generate(now.timestamp() as u64);
+
+        assert!(two_factor.confirm("000000x", now).is_none());
+        let recovery = two_factor.confirm(&code, now).unwrap();
+        assert!(two_factor.is_confirmed());
+        assert_eq!(recovery.len(), RECOVERY_CODE_COUNT);
+
+        // Replaying the code that confirmed setup fails.
+        assert!(!two_factor.check(&code, now));
+        let later = now + Duration::seconds(STEP_SECONDS as i64);
+        let next = two_factor.totp("").generate(later.timestamp() as u64);
+        assert!(two_factor.check(&next, later));
+
+        assert!(two_factor.check(&recovery[0].to_uppercase(), later));
+        assert!(!two_factor.check(&recovery[0], later));
+        assert_eq!(two_factor.recovery_codes_left(), RECOVERY_CODE_COUNT - 1);
+    }
+}
@@ -0,0 +1,140 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::account::User;
+
+/// How long a verification link stays valid.
+pub const VERIFY_TOKEN_HOURS: i64 = 24;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Issues and checks signed email verification tokens. A token names the
+/// user and the address it was sent to, so changing address invalidates
+/// links sent to the old one. Nothing is stored server side.
+#[derive(Clone)]
+pub struct EmailVerifier {
+    key: Vec<u8>,
+}
+
+/// The user and address a valid token was issued for.
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifiedEmail {
+    pub user_id: Uuid,
+    pub email: String,
+}
+
+#[derive(Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct VerifyEmail {
+    pub token: String,
+}
+
+impl EmailVerifier {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+
+    pub fn token(&self, user: &User, now: DateTime<Utc>) -> String {
+        let expires = (now + Duration::hours(VERIFY_TOKEN_HOURS)).timestamp();
+        let payload = URL_SAFE_NO_PAD.encode(format!("{}:{}:{}", user.id, expires, user.email));
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    pub fn verify(&self, token: &str, now: DateTime<Utc>) -> Result<VerifiedEmail, VerifyError> {
+        let (payload, signature) = token.trim().split_once('.').ok_or(VerifyError::Invalid)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| VerifyError::Invalid)?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| VerifyError::Invalid)?;
+
+        // Signed by us, so the payload is well formed.
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|p| String::from_utf8(p).ok())
+            .ok_or(VerifyError::Invalid)?;
+        let mut parts = payload.splitn(3, ':');
+        let (Some(user_id), Some(expires), Some(email)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(VerifyError::Invalid);
+        };
+        let user_id = user_id.parse().map_err(|_| VerifyError::Invalid)?;
+        let expires: i64 = expires.parse().map_err(|_| VerifyError::Invalid)?;
+
+        if now.timestamp() > expires {
+            return Err(VerifyError::Expired);
+        }
+
+        Ok(VerifiedEmail {
+            user_id,
+            email: email.to_string(),
+        })
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac takes any key length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    Invalid,
+    Expired,
+    /// The account was deleted or its address changed since the link was sent.
+    Stale,
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::Invalid => write!(f, "verification link is invalid"),
+            VerifyError::Expired => write!(f, "verification link has expired"),
+            VerifyError::Stale => write!(f, "verification link is for an old email address"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::account::{signup, UserStore};
+
+    #[test]
+    fn tokens_verify_until_they_expire() {
+        let mut users = UserStore::default();
+        let user = users.add(signup("fern@example.com")).unwrap();
+        assert!(!user.verified);
+
+        let verifier = EmailVerifier::new(b"secret");
+        let now = Utc::now();
+        let token = verifier.token(&user, now);
+
+        assert_eq!(
+            EmailVerifier::new(b"other secret").verify(&token, now),
+            Err(VerifyError::Invalid)
+        );
+        let tampered = token.replacen(&token[..4], "AAAA", 1);
+        assert_eq!(verifier.verify(&tampered, now), Err(VerifyError::Invalid));
+        assert_eq!(
+            verifier.verify(&token, now + Duration::hours(VERIFY_TOKEN_HOURS + 1)),
+            Err(VerifyError::Expired)
+        );
+
+        let verified = users.verify_email(&verifier, &token, now).unwrap();
+        assert!(verified.verified);
+        assert!(users.find_by_email("fern@example.com").unwrap().verified);
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use ts_rs::TS;
+use uuid::Uuid;
+
+use crate::{
+    cart::{Cart, CartBackend},
+    store::{InventoryBackend, InventoryError, Product, RestockListener},
+};
+
+#[derive(Clone)]
+pub struct WishlistBackend(Arc<Mutex<WishlistStore>>);
+
+impl WishlistBackend {
+    pub fn new() -> Self {
+        WishlistBackend(Arc::new(Mutex::new(WishlistStore {
+            wishlists: HashMap::new(),
+        })))
+    }
+
+    pub fn wishlist(&self, user_id: &Uuid) -> Wishlist {
+        let wishlist_store = self.lock().expect("wishlist backend threads");
+        wishlist_store
+            .wishlists
+            .get(user_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn contains(&self, user_id: &Uuid, listing_id: &Uuid) -> bool {
+        let wishlist_store = self.lock().expect("wishlist backend threads");
+        wishlist_store
+            .wishlists
+            .get(user_id)
+            .is_some_and(|wishlist| wishlist.contains(listing_id))
+    }
+
+    pub fn add(&self, user_id: Uuid, product: &Product) -> Wishlist {
+        let mut wishlist_store = self.lock().expect("wishlist backend threads");
+        let wishlist = wishlist_store.wishlists.entry(user_id).or_default();
+        wishlist.insert(product);
+        wishlist.clone()
+    }
+
+    pub fn remove(&self, user_id: Uuid, listing_id: &Uuid) -> Wishlist {
+        let mut wishlist_store = self.lock().expect("wishlist backend threads");
+        let wishlist = wishlist_store.wishlists.entry(user_id).or_default();
+        wishlist.remove(listing_id);
+        wishlist.clone()
+    }
+
+    /// Adds or removes a listing, returning whether it is now wishlisted.
+    pub fn toggle(&self, user_id: Uuid, product: &Product) -> bool {
+        let mut wishlist_store = self.lock().expect("wishlist backend threads");
+        let wishlist = wishlist_store.wishlists.entry(user_id).or_default();
+        if wishlist.contains(&product.listing_id) {
+            wishlist.remove(&product.listing_id);
+            false
+        } else {
+            wishlist.insert(product);
+            true
+        }
+    }
+
+    /// Puts one unit of the first variant with free stock into the cart and
+    /// takes the listing off the wishlist.
+    pub fn move_to_cart(
+        &self,
+        user_id: Uuid,
+        listing_id: &Uuid,
+        carts: &CartBackend,
+        inventory: &InventoryBackend,
+    ) -> Result<Cart, InventoryError> {
+        let product = inventory
+            .product(listing_id)
+            .ok_or(InventoryError::NotFound)?;
+        let variant = product
+            .variants
+            .iter()
+            .find(|v| inventory.variant_status(&v.variant_id).available())
+            .ok_or(InventoryError::InsufficientStock)?;
+
+        let cart = carts.add(user_id, inventory, listing_id, Some(variant.variant_id), 1)?;
+        self.remove(user_id, listing_id);
+        Ok(cart)
+    }
+
+    pub fn clear(&self, user_id: &Uuid) {
+        let mut wishlist_store = self.lock().expect("wishlist backend threads");
+        wishlist_store.wishlists.remove(user_id);
+    }
+
+    /// Clears a user's back-in-stock notices once they have been shown.
+    pub fn dismiss_notices(&self, user_id: &Uuid) {
+        let mut wishlist_store = self.lock().expect("wishlist backend threads");
+        if let Some(wishlist) = wishlist_store.wishlists.get_mut(user_id) {
+            wishlist.notices.clear();
+        }
+    }
+}
+
+impl RestockListener for WishlistBackend {
+    fn restocked(&self, product: &Product) {
+        let mut wishlist_store = self.lock().expect("wishlist backend threads");
+        for wishlist in wishlist_store.wishlists.values_mut() {
+            if wishlist.contains(&product.listing_id)
+                && !wishlist
+                    .notices
+                    .iter()
+                    .any(|n| n.listing_id == product.listing_id)
+            {
+                wishlist.notices.push(BackInStock {
+                    listing_id: product.listing_id,
+                    name: product.name.clone(),
+                    restocked_at: Utc::now(),
+                });
+            }
+        }
+    }
+}
+
+impl Default for WishlistBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct WishlistStore {
+    pub wishlists: HashMap<Uuid, Wishlist>,
+}
+
+impl std::ops::Deref for WishlistBackend {
+    type Target = Arc<Mutex<WishlistStore>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Saved listings in the order they were added, with notices for any that
+/// have come back into stock since.
+#[derive(Serialize, Clone, Default, TS)]
+#[ts(export)]
+pub struct Wishlist {
+    pub items: Vec<WishlistItem>,
+    pub notices: Vec<BackInStock>,
+}
+
+impl Wishlist {
+    pub fn contains(&self, listing_id: &Uuid) -> bool {
+        self.items.iter().any(|item| item.listing_id == *listing_id)
+    }
+
+    fn insert(&mut self, product: &Product) {
+        if !self.contains(&product.listing_id) {
+            self.items.push(WishlistItem {
+                listing_id: product.listing_id,
+                name: product.name.clone(),
+                added_at: Utc::now(),
+            });
+        }
+    }
+
+    fn remove(&mut self, listing_id: &Uuid) {
+        self.items.retain(|item| item.listing_id != *listing_id);
+        self.notices
+            .retain(|notice| notice.listing_id != *listing_id);
+    }
+}
+
+#[derive(Serialize, Clone, TS)]
+#[ts(export)]
+pub struct WishlistItem {
+    pub listing_id: Uuid,
+    pub name: String,
+    pub added_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Clone, TS)]
+#[ts(export)]
+pub struct BackInStock {
+    pub listing_id: Uuid,
+    pub name: String,
+    pub restocked_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::{AdjustStock, AdjustmentReason, Inventory};
+
+    #[test]
+    fn restocking_a_wishlisted_product_leaves_a_notice() {
+        let inventory = InventoryBackend::new();
+        let wishlists = WishlistBackend::new();
+        inventory.on_restock(Arc::new(wishlists.clone()));
+        let user = Uuid::new_v4();
+
+        let mut product = Product::random();
+        product.variants.truncate(1);
+        let listing_id = product.listing_id;
+        inventory.add_product(product.clone(), Inventory::new(0, 0, 0));
+        assert!(wishlists.toggle(user, &product));
+
+        let restock = |delta| AdjustStock {
+            delta,
+            reason: AdjustmentReason::Restock,
+        };
+        inventory.adjust(listing_id, user, restock(2)).unwrap();
+        inventory.adjust(listing_id, user, restock(2)).unwrap();
+        assert_eq!(wishlists.wishlist(&user).notices.len(), 1);
+
+        let carts = CartBackend::new();
+        let cart = wishlists
+            .move_to_cart(user, &listing_id, &carts, &inventory)
+            .unwrap();
+        assert_eq!(cart.count(), 1);
+        let wishlist = wishlists.wishlist(&user);
+        assert!(wishlist.items.is_empty() && wishlist.notices.is_empty());
+    }
+}